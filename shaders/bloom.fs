@@ -0,0 +1,46 @@
+#version 330
+
+// Input vertex attributes (from vertex shader)
+in vec2 fragTexCoord;
+in vec4 fragColor;
+
+// Input uniform values
+uniform sampler2D texture0;
+uniform vec4 colDiffuse;
+
+// Bloom controls, set from the world file and adjustable at runtime
+uniform vec2 resolution;
+uniform float threshold;
+uniform float radius;
+uniform float intensity;
+
+// Output fragment color
+out vec4 finalColor;
+
+float offset[3] = float[](0.0, 1.3846153846, 3.2307692308);
+float weight[3] = float[](0.2270270270, 0.3162162162, 0.0702702703);
+
+// Only the parts of a texel brighter than the threshold bloom
+vec3 brightPass(vec2 coord)
+{
+    vec3 color = texture(texture0, coord).rgb;
+    return max(color - vec3(threshold), vec3(0.0));
+}
+
+void main()
+{
+    vec3 baseColor = texture(texture0, fragTexCoord).rgb;
+
+    // Blur the bright parts of the image, horizontally and vertically
+    vec3 bloomColor = brightPass(fragTexCoord)*weight[0]*2.0;
+    for (int i = 1; i < 3; i++)
+    {
+        vec2 texelStep = vec2(offset[i]*radius)/resolution;
+        bloomColor += brightPass(fragTexCoord + vec2(texelStep.x, 0.0))*weight[i];
+        bloomColor += brightPass(fragTexCoord - vec2(texelStep.x, 0.0))*weight[i];
+        bloomColor += brightPass(fragTexCoord + vec2(0.0, texelStep.y))*weight[i];
+        bloomColor += brightPass(fragTexCoord - vec2(0.0, texelStep.y))*weight[i];
+    }
+
+    finalColor = vec4(baseColor + bloomColor*0.5*intensity, 1.0);
+}
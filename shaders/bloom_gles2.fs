@@ -0,0 +1,47 @@
+#version 100
+
+precision mediump float;
+
+// Input vertex attributes (from vertex shader)
+varying vec2 fragTexCoord;
+varying vec4 fragColor;
+
+// Input uniform values
+uniform sampler2D texture0;
+uniform vec4 colDiffuse;
+
+// Bloom controls, set from the world file and adjustable at runtime
+uniform vec2 resolution;
+uniform float threshold;
+uniform float radius;
+uniform float intensity;
+
+// Only the parts of a texel brighter than the threshold bloom
+vec3 brightPass(vec2 coord)
+{
+    vec3 color = texture2D(texture0, coord).rgb;
+    return max(color - vec3(threshold), vec3(0.0));
+}
+
+// Four blur taps at an offset, horizontally and vertically
+vec3 blurTaps(float offset, float weight)
+{
+    vec2 texelStep = vec2(offset*radius)/resolution;
+    vec3 color = brightPass(fragTexCoord + vec2(texelStep.x, 0.0));
+    color += brightPass(fragTexCoord - vec2(texelStep.x, 0.0));
+    color += brightPass(fragTexCoord + vec2(0.0, texelStep.y));
+    color += brightPass(fragTexCoord - vec2(0.0, texelStep.y));
+    return color*weight;
+}
+
+void main()
+{
+    vec3 baseColor = texture2D(texture0, fragTexCoord).rgb;
+
+    // GLSL ES 1.00 has no array initializers, so the kernel is unrolled
+    vec3 bloomColor = brightPass(fragTexCoord)*0.2270270270*2.0;
+    bloomColor += blurTaps(1.3846153846, 0.3162162162);
+    bloomColor += blurTaps(3.2307692308, 0.0702702703);
+
+    gl_FragColor = vec4(baseColor + bloomColor*0.5*intensity, 1.0);
+}
@@ -0,0 +1,23 @@
+#version 100
+
+precision mediump float;
+
+// Input vertex attributes (from vertex shader)
+varying vec2 fragTexCoord;
+varying vec4 fragColor;
+
+// Input uniform values
+uniform sampler2D texture0;
+uniform vec4 colDiffuse;
+
+// Rows of the matrix simulating a color vision deficiency
+uniform vec3 red;
+uniform vec3 green;
+uniform vec3 blue;
+
+void main()
+{
+    vec4 color = texture2D(texture0, fragTexCoord);
+    vec3 seen = vec3(dot(red, color.rgb), dot(green, color.rgb), dot(blue, color.rgb));
+    gl_FragColor = vec4(clamp(seen, 0.0, 1.0), color.a)*colDiffuse*fragColor;
+}
@@ -0,0 +1,26 @@
+#version 300 es
+
+precision mediump float;
+
+// Input vertex attributes (from vertex shader)
+in vec2 fragTexCoord;
+in vec4 fragColor;
+
+// Input uniform values
+uniform sampler2D texture0;
+uniform vec4 colDiffuse;
+
+// Rows of the matrix simulating a color vision deficiency
+uniform vec3 red;
+uniform vec3 green;
+uniform vec3 blue;
+
+// Output fragment color
+out vec4 finalColor;
+
+void main()
+{
+    vec4 color = texture(texture0, fragTexCoord);
+    vec3 seen = vec3(dot(red, color.rgb), dot(green, color.rgb), dot(blue, color.rgb));
+    finalColor = vec4(clamp(seen, 0.0, 1.0), color.a)*colDiffuse*fragColor;
+}
@@ -0,0 +1,129 @@
+use std::io::{self, Write};
+
+use crate::color::Color;
+use crate::math::Vector2;
+use crate::trace::RaySink;
+
+/// A high dynamic range buffer that ray samples add their energy into
+pub struct Accumulator {
+    width: usize,
+    height: usize,
+
+    /// Linear RGB energy per pixel
+    energy: Vec<[f32; 3]>,
+}
+
+impl Accumulator {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            energy: vec![[0.0; 3]; width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Linear RGB energy per pixel, in rows from the top
+    pub fn energy(&self) -> &[[f32; 3]] {
+        &self.energy
+    }
+
+    /// Add another buffer's energy into this one, scaled by a factor
+    pub fn add_scaled(&mut self, other: &Accumulator, scale: f32) {
+        for (energy, other) in self.energy.iter_mut().zip(other.energy.iter()) {
+            for channel in 0..3 {
+                energy[channel] += other[channel] * scale;
+            }
+        }
+    }
+
+    /// Write the raw energy as a color Portable Float Map, with no tone mapping
+    pub fn write_pfm(&self, writer: &mut impl Write) -> io::Result<()> {
+        // A negative scale marks the data as little endian
+        write!(writer, "PF\n{} {}\n-1.0\n", self.width, self.height)?;
+
+        // PFM stores rows from the bottom up
+        for row in self.energy.chunks(self.width).rev() {
+            for pixel in row {
+                for channel in pixel {
+                    writer.write_all(&channel.to_le_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// A black and white mask as 8-bit luma, with white wherever the brightest channel's energy
+    /// is above a threshold, and black in shadow
+    pub fn mask(&self, threshold: f32) -> Vec<u8> {
+        self.energy
+            .iter()
+            .map(|[r, g, b]| {
+                if r.max(*g).max(*b) > threshold {
+                    255
+                } else {
+                    0
+                }
+            })
+            .collect()
+    }
+
+    /// Remove all accumulated energy
+    pub fn clear(&mut self) {
+        for pixel in self.energy.iter_mut() {
+            *pixel = [0.0; 3];
+        }
+    }
+
+    /// Tone map the buffer to RGBA8 pixels.
+    ///
+    /// Energy is normalized against the average lit pixel, so exposure stays stable no matter how many rays were cast.
+    pub fn tone_map(&self) -> Vec<u8> {
+        // Find the average brightness of every pixel that received light
+        let (sum, count) = self
+            .energy
+            .iter()
+            .map(|[r, g, b]| (r + g + b) / 3.0)
+            .filter(|luminance| *luminance > 0.0)
+            .fold((0.0, 0), |(sum, count), luminance| {
+                (sum + luminance, count + 1)
+            });
+        let mean = if count > 0 { sum / count as f32 } else { 1.0 };
+
+        let map = |energy: f32| ((1.0 - (-energy / mean).exp()) * 255.0) as u8;
+        self.energy
+            .iter()
+            .flat_map(|[r, g, b]| vec![map(*r), map(*g), map(*b), 255])
+            .collect()
+    }
+}
+
+impl RaySink for Accumulator {
+    fn deposit(&mut self, pixel: Vector2, color: Color) {
+        if pixel.x < 0.0 || pixel.y < 0.0 {
+            return;
+        }
+        let (x, y) = (pixel.x as usize, pixel.y as usize);
+        if x >= self.width || y >= self.height {
+            return;
+        }
+
+        // Samples are weighted by their alpha, so multi-emitter lights share their energy
+        let weight = color.a as f32 / (255.0 * 255.0);
+        let energy = &mut self.energy[y * self.width + x];
+        energy[0] += color.r as f32 * weight;
+        energy[1] += color.g as f32 * weight;
+        energy[2] += color.b as f32 * weight;
+    }
+
+    fn ignores_black(&self) -> bool {
+        true
+    }
+}
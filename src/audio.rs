@@ -0,0 +1,85 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{SampleFormat, Stream};
+use log::error;
+
+use crate::errors::AppError;
+
+/// Tracks the loudness of the default audio input device
+pub struct AudioLevel {
+    /// RMS amplitude of the most recent buffer, stored as `f32` bits
+    level: Arc<AtomicU32>,
+
+    /// The input stream stops when dropped
+    _stream: Stream,
+}
+
+/// Store the RMS amplitude of a buffer of samples in the range -1.0 to 1.0
+fn store_rms(level: &AtomicU32, samples: impl Iterator<Item = f32>) {
+    let (sum, count) = samples.fold((0.0, 0), |(sum, count), s| (sum + s * s, count + 1));
+    if count > 0 {
+        let rms: f32 = (sum / count as f32).sqrt();
+        level.store(rms.to_bits(), Ordering::Relaxed);
+    }
+}
+
+impl AudioLevel {
+    /// Start listening to the default audio input device
+    pub fn open() -> Result<Self, AppError> {
+        let audio_error = |e: &dyn std::fmt::Display| AppError::Audio(e.to_string());
+        let device = cpal::default_host()
+            .default_input_device()
+            .ok_or_else(|| AppError::Audio("No audio input device found".to_string()))?;
+        let config = device.default_input_config().map_err(|e| audio_error(&e))?;
+
+        let level = Arc::new(AtomicU32::new(0));
+        let writer = level.clone();
+        let on_error = |e| error!("Audio input error: {}", e);
+
+        let stream = match config.sample_format() {
+            SampleFormat::F32 => device
+                .build_input_stream(
+                    &config.into(),
+                    move |data: &[f32], _: &cpal::InputCallbackInfo| {
+                        store_rms(&writer, data.iter().copied())
+                    },
+                    on_error,
+                )
+                .map_err(|e| audio_error(&e))?,
+            SampleFormat::I16 => device
+                .build_input_stream(
+                    &config.into(),
+                    move |data: &[i16], _: &cpal::InputCallbackInfo| {
+                        store_rms(&writer, data.iter().map(|s| *s as f32 / i16::MAX as f32))
+                    },
+                    on_error,
+                )
+                .map_err(|e| audio_error(&e))?,
+            SampleFormat::U16 => device
+                .build_input_stream(
+                    &config.into(),
+                    move |data: &[u16], _: &cpal::InputCallbackInfo| {
+                        store_rms(
+                            &writer,
+                            data.iter().map(|s| (*s as f32 - 32768.0) / 32768.0),
+                        )
+                    },
+                    on_error,
+                )
+                .map_err(|e| audio_error(&e))?,
+        };
+        stream.play().map_err(|e| audio_error(&e))?;
+
+        Ok(Self {
+            level,
+            _stream: stream,
+        })
+    }
+
+    /// Current input amplitude, roughly 0.0 to 1.0
+    pub fn level(&self) -> f32 {
+        f32::from_bits(self.level.load(Ordering::Relaxed))
+    }
+}
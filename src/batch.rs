@@ -0,0 +1,98 @@
+use wide::f32x8;
+
+use crate::math::Vector2;
+use crate::world::Wall;
+
+/// Number of walls tested at once
+const LANES: usize = 8;
+
+/// Walls laid out as structure-of-arrays, so a point can be tested against eight at a time.
+///
+/// Unused lanes of the last batch hold walls at infinity, which never match.
+#[derive(Debug, Clone, Default)]
+pub struct WallBatch {
+    start_x: Vec<f32x8>,
+    start_y: Vec<f32x8>,
+    delta_x: Vec<f32x8>,
+    delta_y: Vec<f32x8>,
+
+    /// One over each wall's squared length, or zero for walls with no length
+    inverse_length: Vec<f32x8>,
+
+    count: usize,
+}
+
+impl WallBatch {
+    pub fn new(walls: &[Wall]) -> Self {
+        let mut batch = Self {
+            count: walls.len(),
+            ..Self::default()
+        };
+
+        for chunk in walls.chunks(LANES) {
+            let mut start_x = [f32::INFINITY; LANES];
+            let mut start_y = [f32::INFINITY; LANES];
+            let mut delta_x = [0.0; LANES];
+            let mut delta_y = [0.0; LANES];
+            let mut inverse_length = [0.0; LANES];
+            for (lane, wall) in chunk.iter().enumerate() {
+                let delta = wall.end - wall.start;
+                let length_squared = delta.x * delta.x + delta.y * delta.y;
+                start_x[lane] = wall.start.x;
+                start_y[lane] = wall.start.y;
+                delta_x[lane] = delta.x;
+                delta_y[lane] = delta.y;
+                if length_squared > 0.0 {
+                    inverse_length[lane] = 1.0 / length_squared;
+                }
+            }
+            batch.start_x.push(f32x8::from(start_x));
+            batch.start_y.push(f32x8::from(start_y));
+            batch.delta_x.push(f32x8::from(delta_x));
+            batch.delta_y.push(f32x8::from(delta_y));
+            batch.inverse_length.push(f32x8::from(inverse_length));
+        }
+        batch
+    }
+
+    /// Number of walls in the batch
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// The index of the first wall closer to a point than a distance, and how many walls were
+    /// tested to find it, counted like a linear search
+    pub fn first_within(&self, point: Vector2, distance: f32) -> (Option<usize>, u64) {
+        let x = f32x8::splat(point.x);
+        let y = f32x8::splat(point.y);
+        let limit = f32x8::splat(distance * distance);
+        let zero = f32x8::splat(0.0);
+        let one = f32x8::splat(1.0);
+
+        for batch in 0..self.start_x.len() {
+            // Project onto each wall, clamped to its ends
+            let offset_x = x - self.start_x[batch];
+            let offset_y = y - self.start_y[batch];
+            let t = ((offset_x * self.delta_x[batch] + offset_y * self.delta_y[batch])
+                * self.inverse_length[batch])
+                .max(zero)
+                .min(one);
+            let away_x = offset_x - self.delta_x[batch] * t;
+            let away_y = offset_y - self.delta_y[batch] * t;
+
+            let hits = (away_x * away_x + away_y * away_y)
+                .cmp_lt(limit)
+                .move_mask();
+            if hits != 0 {
+                let index = batch * LANES + hits.trailing_zeros() as usize;
+                return (Some(index), index as u64 + 1);
+            }
+        }
+
+        (None, self.count as u64)
+    }
+}
@@ -0,0 +1,251 @@
+use std::fmt;
+
+use serde::de::{self, Deserializer, Visitor};
+use serde::ser::{SerializeTuple, Serializer};
+use serde::{Deserialize, Serialize};
+
+/// An 8-bit RGBA color
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::new(0, 0, 0, 255);
+    pub const WHITE: Color = Color::new(255, 255, 255, 255);
+    pub const TRANSPARENT: Color = Color::new(0, 0, 0, 0);
+
+    pub const fn new(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self { r, g, b, a }
+    }
+}
+
+/// CSS-style color names accepted in world files
+const NAMED_COLORS: &[(&str, (u8, u8, u8))] = &[
+    ("black", (0, 0, 0)),
+    ("white", (255, 255, 255)),
+    ("red", (255, 0, 0)),
+    ("lime", (0, 255, 0)),
+    ("green", (0, 128, 0)),
+    ("blue", (0, 0, 255)),
+    ("yellow", (255, 255, 0)),
+    ("cyan", (0, 255, 255)),
+    ("aqua", (0, 255, 255)),
+    ("magenta", (255, 0, 255)),
+    ("fuchsia", (255, 0, 255)),
+    ("silver", (192, 192, 192)),
+    ("gray", (128, 128, 128)),
+    ("grey", (128, 128, 128)),
+    ("maroon", (128, 0, 0)),
+    ("olive", (128, 128, 0)),
+    ("purple", (128, 0, 128)),
+    ("teal", (0, 128, 128)),
+    ("navy", (0, 0, 128)),
+    ("orange", (255, 165, 0)),
+    ("gold", (255, 215, 0)),
+    ("pink", (255, 192, 203)),
+    ("brown", (165, 42, 42)),
+    ("violet", (238, 130, 238)),
+    ("indigo", (75, 0, 130)),
+    ("coral", (255, 127, 80)),
+    ("salmon", (250, 128, 114)),
+    ("crimson", (220, 20, 60)),
+    ("turquoise", (64, 224, 208)),
+    ("skyblue", (135, 206, 235)),
+    ("khaki", (240, 230, 140)),
+    ("lavender", (230, 230, 250)),
+    ("beige", (245, 245, 220)),
+    ("ivory", (255, 255, 240)),
+    ("tomato", (255, 99, 71)),
+    ("orchid", (218, 112, 214)),
+    ("chartreuse", (127, 255, 0)),
+];
+
+/// A color as written in a world file.
+///
+/// Accepts an `[r, g, b, a]` tuple, a `"#rrggbb"` / `"#rrggbbaa"` hex string, or a CSS color name.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorSpec(pub u8, pub u8, pub u8, pub u8);
+
+impl Default for ColorSpec {
+    fn default() -> Self {
+        Self(0, 0, 0, 0)
+    }
+}
+
+impl ColorSpec {
+    /// Parse a hex string or color name
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+
+        // Hex colors
+        if let Some(hex) = text.strip_prefix('#') {
+            // Slicing by byte is only safe on ASCII, and other characters are never hex anyway.
+            // The digits are checked first, as `from_str_radix` also takes a leading sign
+            let channel = |i: usize| {
+                hex.get(i..i + 2)
+                    .filter(|digits| digits.bytes().all(|b| b.is_ascii_hexdigit()))
+                    .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                    .ok_or_else(|| format!("Invalid hex color: {}", text))
+            };
+            return match hex.len() {
+                6 => Ok(Self(channel(0)?, channel(2)?, channel(4)?, 255)),
+                8 => Ok(Self(channel(0)?, channel(2)?, channel(4)?, channel(6)?)),
+                _ => Err(format!("Invalid hex color: {}", text)),
+            };
+        }
+
+        // Named colors
+        let name = text.to_lowercase();
+        NAMED_COLORS
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, (r, g, b))| Self(*r, *g, *b, 255))
+            .ok_or_else(|| format!("Unknown color name: {}", text))
+    }
+}
+
+impl From<ColorSpec> for Color {
+    fn from(spec: ColorSpec) -> Self {
+        Color::new(spec.0, spec.1, spec.2, spec.3)
+    }
+}
+
+impl From<Color> for ColorSpec {
+    fn from(color: Color) -> Self {
+        Self(color.r, color.g, color.b, color.a)
+    }
+}
+
+impl Serialize for ColorSpec {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut tuple = serializer.serialize_tuple(4)?;
+        tuple.serialize_element(&self.0)?;
+        tuple.serialize_element(&self.1)?;
+        tuple.serialize_element(&self.2)?;
+        tuple.serialize_element(&self.3)?;
+        tuple.end()
+    }
+}
+
+struct ColorSpecVisitor;
+
+impl<'de> Visitor<'de> for ColorSpecVisitor {
+    type Value = ColorSpec;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an [r, g, b, a] tuple, a hex color string, or a color name")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<ColorSpec, E> {
+        ColorSpec::parse(value).map_err(E::custom)
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<ColorSpec, A::Error> {
+        let mut channel = |i: usize| -> Result<u8, A::Error> {
+            seq.next_element()?
+                .ok_or_else(|| de::Error::invalid_length(i, &self))
+        };
+        Ok(ColorSpec(
+            channel(0)?,
+            channel(1)?,
+            channel(2)?,
+            channel(3)?,
+        ))
+    }
+}
+
+impl<'de> Deserialize<'de> for ColorSpec {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ColorSpecVisitor)
+    }
+}
+
+/// Convert a color temperature (in Kelvin) to an RGB color
+pub fn kelvin_to_color(kelvin: f32) -> Color {
+    // Approximation by Tanner Helland, valid for 1000K - 40000K
+    let temp = kelvin.clamp(1000.0, 40000.0) / 100.0;
+
+    let r = if temp <= 66.0 {
+        255.0
+    } else {
+        329.698727446 * (temp - 60.0).powf(-0.1332047592)
+    };
+    let g = if temp <= 66.0 {
+        99.4708025861 * temp.ln() - 161.1195681661
+    } else {
+        288.1221695283 * (temp - 60.0).powf(-0.0755148492)
+    };
+    let b = if temp >= 66.0 {
+        255.0
+    } else if temp <= 19.0 {
+        0.0
+    } else {
+        138.5177312231 * (temp - 10.0).ln() - 305.0447927307
+    };
+
+    Color {
+        r: r.clamp(u8::MIN as f32, u8::MAX as f32) as u8,
+        g: g.clamp(u8::MIN as f32, u8::MAX as f32) as u8,
+        b: b.clamp(u8::MIN as f32, u8::MAX as f32) as u8,
+        a: 255,
+    }
+}
+
+/// Rotate the hue of a color by some number of degrees
+pub fn rotate_hue(color: Color, degrees: f32) -> Color {
+    let r = color.r as f32 / 255.0;
+    let g = color.g as f32 / 255.0;
+    let b = color.b as f32 / 255.0;
+
+    // Convert to HSV
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+    let hue = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * (((g - b) / delta) % 6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    let saturation = if max == 0.0 { 0.0 } else { delta / max };
+    let value = max;
+
+    // Rotate and convert back to RGB
+    let hue = (hue + degrees).rem_euclid(360.0);
+    let c = value * saturation;
+    let x = c * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+    let m = value - c;
+    let (r, g, b) = match (hue / 60.0) as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+
+    Color {
+        r: ((r + m) * 255.0).round() as u8,
+        g: ((g + m) * 255.0).round() as u8,
+        b: ((b + m) * 255.0).round() as u8,
+        a: color.a,
+    }
+}
+
+/// Linearly interpolate between two colors
+pub fn lerp_color(a: Color, b: Color, t: f32) -> Color {
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+    Color {
+        r: mix(a.r, b.r),
+        g: mix(a.g, b.g),
+        b: mix(a.b, b.b),
+        a: mix(a.a, b.a),
+    }
+}
@@ -0,0 +1,542 @@
+use std::collections::BTreeMap;
+use std::fs::{create_dir_all, read_to_string, write, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, sleep};
+use std::time::Duration;
+
+use clap::ArgMatches;
+use glasscast::diff::{comparison_image, WorldDiff};
+use glasscast::headless::{measure_sensors, render_energy, render_image, render_mask};
+use glasscast::lightmap::LightmapInfo;
+use glasscast::query::Selector;
+use glasscast::sensor::{Reading, CSV_HEADER};
+use glasscast::verify::Baseline;
+use glasscast::world::{save_path, Chunk, Wall};
+use glasscast::{Vector2, World};
+use log::{error, info};
+use serde_json::{Map, Value};
+
+use crate::errors::AppError;
+use crate::watch::WatchedFile;
+use crate::{open_csv, parse_resolution, parse_vector};
+
+/// Starter worlds for `glasscast new`, by name
+pub const TEMPLATES: &[(&str, &str)] = &[
+    ("empty-room", include_str!("../templates/empty-room.json5")),
+    ("prism", include_str!("../templates/prism.json5")),
+    (
+        "mirror-maze",
+        include_str!("../templates/mirror-maze.json5"),
+    ),
+];
+
+/// `glasscast new`: write a starter world from a template
+pub fn new(matches: &ArgMatches) -> Result<(), AppError> {
+    let path = matches.value_of("path").unwrap();
+    let name = matches.value_of("template").unwrap();
+
+    let template = match TEMPLATES.iter().find(|(n, _)| *n == name) {
+        Some((_, template)) => template,
+        None => return Err(AppError::Usage(format!("Unknown template: {}", name))),
+    };
+    if Path::new(path).exists() && !matches.is_present("force") {
+        return Err(AppError::Usage(format!(
+            "{} already exists (use --force to overwrite it)",
+            path
+        )));
+    }
+
+    write(path, template)?;
+    println!("Wrote the {} template to {}", name, path);
+    Ok(())
+}
+
+/// How often watched worlds are checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Settings shared by every world in a `glasscast render`
+#[derive(Debug, Clone, Copy)]
+struct RenderSettings {
+    width: u32,
+    height: u32,
+    accumulate: bool,
+    sensors: bool,
+
+    /// Hour of the day to place the sun at, for worlds with a day cycle
+    hour: Option<f32>,
+
+    /// Energy above which pixels are lit, when rendering a black and white mask
+    mask: Option<f32>,
+}
+
+/// Render a world to an image, returning its sensor readings if they were requested
+fn render_world(
+    world_path: &Path,
+    output: &Path,
+    settings: RenderSettings,
+) -> Result<Vec<Reading>, AppError> {
+    let mut world = World::from_file(world_path)?;
+    world.load_all_chunks()?;
+    if let Some(hour) = settings.hour {
+        world.set_hour(hour);
+    }
+
+    // Float maps hold the raw light energy, rather than a tone mapped image
+    let float_map = output
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map_or(false, |extension| extension.eq_ignore_ascii_case("pfm"));
+    if let Some(threshold) = settings.mask {
+        let mask = render_mask(&world, settings.width, settings.height, threshold);
+        let lit = mask.pixels().filter(|pixel| pixel[0] > 0).count();
+        info!(
+            "{:.1}% of {} is lit",
+            lit as f64 * 100.0 / (settings.width as f64 * settings.height as f64),
+            world_path.display()
+        );
+        mask.save(output)?;
+    } else if float_map {
+        let energy = render_energy(&world, settings.width, settings.height);
+        let mut file = BufWriter::new(File::create(output)?);
+        energy.write_pfm(&mut file)?;
+        file.flush()?;
+    } else {
+        let image = render_image(&world, settings.width, settings.height, settings.accumulate);
+        image.save(output)?;
+    }
+    info!("Rendered {} to {}", world_path.display(), output.display());
+
+    Ok(if settings.sensors {
+        measure_sensors(&world, settings.width, settings.height)
+    } else {
+        Vec::new()
+    })
+}
+
+/// Read the world paths listed in a manifest, one per line, relative to the manifest.
+///
+/// Blank lines and lines starting with `#` are ignored.
+fn read_manifest(path: &Path) -> Result<Vec<PathBuf>, AppError> {
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    Ok(read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| directory.join(line))
+        .collect())
+}
+
+/// `glasscast render`: render one or more worlds to images without a window
+pub fn render(matches: &ArgMatches) -> Result<(), AppError> {
+    let (width, height) = parse_resolution(matches.value_of("resolution").unwrap()).unwrap();
+    let settings = RenderSettings {
+        width: width as u32,
+        height: height as u32,
+        accumulate: matches.is_present("accumulate"),
+        sensors: matches.is_present("sensors"),
+        hour: matches.value_of("hour").map(|hour| hour.parse().unwrap()),
+        mask: if matches.is_present("mask") {
+            Some(matches.value_of("mask-threshold").unwrap().parse().unwrap())
+        } else {
+            None
+        },
+    };
+
+    // Collect the worlds from the command line and any manifest
+    let mut worlds: Vec<PathBuf> = matches
+        .values_of("world")
+        .map(|values| values.map(PathBuf::from).collect())
+        .unwrap_or_default();
+    if let Some(manifest) = matches.value_of("manifest") {
+        worlds.extend(read_manifest(Path::new(manifest))?);
+    }
+
+    // A single world renders to the output file, while batches render into a directory
+    let jobs: Vec<(PathBuf, PathBuf)> = match matches.value_of("output-dir") {
+        Some(directory) => {
+            create_dir_all(directory)?;
+            let format = matches.value_of("format").unwrap();
+            worlds
+                .into_iter()
+                .map(|world| {
+                    let name = world.file_stem().unwrap_or_default().to_owned();
+                    let output = Path::new(directory).join(name).with_extension(format);
+                    (world, output)
+                })
+                .collect()
+        }
+        None if worlds.len() == 1 => {
+            let output = PathBuf::from(matches.value_of("output").unwrap());
+            vec![(worlds.remove(0), output)]
+        }
+        None => {
+            return Err(AppError::Usage(
+                "Rendering several worlds needs an --output-dir".to_string(),
+            ))
+        }
+    };
+
+    // Share the jobs out between worker threads
+    let threads: usize = matches.value_of("jobs").unwrap().parse().unwrap();
+    let watched = jobs.clone();
+    let queue = Arc::new(Mutex::new(jobs.into_iter().enumerate()));
+    let workers: Vec<_> = (0..threads)
+        .map(|_| {
+            let queue = Arc::clone(&queue);
+            thread::spawn(move || {
+                let mut results = Vec::new();
+                loop {
+                    let job = queue.lock().unwrap().next();
+                    let (index, (world, output)) = match job {
+                        Some(job) => job,
+                        None => return results,
+                    };
+                    let readings = render_world(&world, &output, settings);
+                    if let Err(error) = &readings {
+                        error!("Failed to render {}: {}", world.display(), error);
+                    }
+                    results.push((index, world, readings));
+                }
+            })
+        })
+        .collect();
+    let mut results: Vec<_> = workers
+        .into_iter()
+        .flat_map(|worker| worker.join().expect("Render thread panicked"))
+        .collect();
+    results.sort_by_key(|(index, ..)| *index);
+
+    // Write every world's sensor readings, labelled by world when there are several
+    let batch = results.len() > 1;
+    if let Some(path) = matches.value_of("sensors") {
+        let header = if batch {
+            format!("world,{}", CSV_HEADER)
+        } else {
+            CSV_HEADER.to_string()
+        };
+        let mut csv = open_csv(path, &header)?;
+        for (_, world, readings) in results.iter() {
+            for reading in readings.iter().flatten() {
+                if batch {
+                    writeln!(csv, "{},{}", world.display(), reading.to_csv())?;
+                } else {
+                    writeln!(csv, "{}", reading.to_csv())?;
+                }
+            }
+        }
+    }
+
+    // Report the first failure, once every other world has had its chance, and before watching
+    // as the watch never returns
+    if let Some(error) = results
+        .into_iter()
+        .find_map(|(_, _, readings)| readings.err())
+    {
+        return Err(error);
+    }
+
+    if matches.is_present("watch") {
+        watch(watched, settings);
+    }
+    Ok(())
+}
+
+/// Re-render each world whenever its file changes, until the process is interrupted.
+///
+/// Failed renders are reported and skipped, so a half-saved world never ends the watch.
+fn watch(jobs: Vec<(PathBuf, PathBuf)>, settings: RenderSettings) {
+    let mut files: Vec<_> = jobs
+        .into_iter()
+        .map(|(world, output)| (WatchedFile::new(&world), world, output))
+        .collect();
+    println!(
+        "Watching {} worlds for changes (Ctrl+C to stop)",
+        files.len()
+    );
+
+    loop {
+        sleep(WATCH_INTERVAL);
+        for (file, world, output) in files.iter_mut() {
+            if !file.changed() {
+                continue;
+            }
+            match render_world(world, output, settings) {
+                Ok(_) => println!("Rendered {} to {}", world.display(), output.display()),
+                Err(error) => error!("Failed to render {}: {}", world.display(), error),
+            }
+        }
+    }
+}
+
+/// `glasscast convert`: rewrite a world in JSON or the binary format
+pub fn convert(matches: &ArgMatches) -> Result<(), AppError> {
+    let input = matches.value_of("input").unwrap();
+    let output = matches.value_of("output").unwrap();
+    let world = World::from_file(input)?;
+    world.save(output)?;
+    println!("Converted {} to {}", input, output);
+    Ok(())
+}
+
+/// `glasscast chunk`: split a world's walls into files by area, which the window loads only
+/// while they are near the view
+pub fn chunk(matches: &ArgMatches) -> Result<(), AppError> {
+    let input = Path::new(matches.value_of("world").unwrap());
+    let output = matches
+        .value_of("output")
+        .map_or_else(|| save_path(input), PathBuf::from);
+    let output = output.as_path();
+    let size: f32 = matches.value_of("size").unwrap().parse().unwrap();
+    let mut world = World::from_file(input)?;
+    world.load_all_chunks()?;
+
+    // Group walls by the cell their middle lies in. Included walls stay in their includes
+    let mut cells: BTreeMap<(i32, i32), Vec<Wall>> = BTreeMap::new();
+    for wall in world.walls.drain(..).filter(|wall| !wall.included) {
+        let middle = (wall.start + wall.end) / 2.0;
+        let cell = (
+            (middle.x / size).floor() as i32,
+            (middle.y / size).floor() as i32,
+        );
+        cells.entry(cell).or_default().push(wall);
+    }
+
+    // Write each cell's walls to its own file, next to the world
+    let directory = output.parent().unwrap_or_else(|| Path::new(""));
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    world.chunks.clear();
+    for ((x, y), walls) in cells {
+        let path = format!("{}.chunk_{}_{}.json", stem, x, y);
+        let points = walls.iter().flat_map(|wall| vec![wall.start, wall.end]);
+        let (min, max) = points.fold(
+            (
+                Vector2::new(f32::MAX, f32::MAX),
+                Vector2::new(f32::MIN, f32::MIN),
+            ),
+            |(min, max), point| {
+                (
+                    Vector2::new(min.x.min(point.x), min.y.min(point.y)),
+                    Vector2::new(max.x.max(point.x), max.y.max(point.y)),
+                )
+            },
+        );
+        let fragment = serde_json::json!({
+            "version": glasscast::migrate::CURRENT_VERSION,
+            "walls": walls,
+        });
+        write(
+            directory.join(&path),
+            serde_json::to_string_pretty(&fragment).unwrap(),
+        )?;
+        world.chunks.push(Chunk {
+            path,
+            position: min,
+            size: max - min,
+            directory: directory.to_path_buf(),
+            loaded: false,
+        });
+    }
+
+    world.save(output)?;
+    println!(
+        "Split {} into {} chunks in {}",
+        input.display(),
+        world.chunks.len(),
+        output.display()
+    );
+    Ok(())
+}
+
+/// `glasscast bake`: render a world into a lightmap texture, with metadata placing it in a game
+/// engine's world
+pub fn bake(matches: &ArgMatches) -> Result<(), AppError> {
+    let mut world = World::from_file(matches.value_of("world").unwrap())?;
+    world.load_all_chunks()?;
+    let output = Path::new(matches.value_of("output").unwrap());
+    let (width, height) = parse_resolution(matches.value_of("resolution").unwrap()).unwrap();
+    let (width, height) = (width as u32, height as u32);
+
+    let image = render_image(&world, width, height, matches.is_present("accumulate"));
+    image.save(output)?;
+
+    let texture = output
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let info = LightmapInfo::new(
+        &world,
+        texture,
+        width,
+        height,
+        matches
+            .value_of("pixels-per-unit")
+            .unwrap()
+            .parse()
+            .unwrap(),
+        parse_vector(matches.value_of("origin").unwrap()).unwrap(),
+    );
+    let metadata = output.with_extension("json");
+    write(&metadata, serde_json::to_string_pretty(&info).unwrap())?;
+
+    println!(
+        "Baked {} lights into {} and {}",
+        info.lights.len(),
+        output.display(),
+        metadata.display()
+    );
+    Ok(())
+}
+
+/// `glasscast verify`: compare renders of a world against a stored baseline
+pub fn verify(matches: &ArgMatches) -> Result<(), AppError> {
+    let world_path = Path::new(matches.value_of("world").unwrap());
+    let mut world = World::from_file(world_path)?;
+    world.load_all_chunks()?;
+    let baseline_path = matches
+        .value_of("baseline")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| world_path.with_extension("baseline.json"));
+
+    // Write a fresh baseline, or one for a world that doesn't have one yet
+    if matches.is_present("update") || !baseline_path.exists() {
+        let (width, height) = parse_resolution(matches.value_of("resolution").unwrap()).unwrap();
+        let baseline = Baseline::render(&world, width as u32, height as u32);
+        write(
+            &baseline_path,
+            serde_json::to_string_pretty(&baseline).unwrap(),
+        )?;
+        println!(
+            "Wrote a baseline of {} frames to {}",
+            baseline.frames.len(),
+            baseline_path.display()
+        );
+        return Ok(());
+    }
+
+    // Renders are compared at the baseline's resolution, whatever was asked for
+    let baseline: Baseline = serde_json::from_str(&read_to_string(&baseline_path)?)
+        .map_err(|e| AppError::Usage(format!("{}: {}", baseline_path.display(), e)))?;
+    let current = Baseline::render(&world, baseline.width, baseline.height);
+    let mismatches = current.mismatches(&baseline);
+    if mismatches.is_empty() {
+        println!(
+            "All {} frames match {}",
+            current.frames.len(),
+            baseline_path.display()
+        );
+        Ok(())
+    } else {
+        for position in mismatches.iter() {
+            println!(
+                "Frame differs with the light at {}, {}",
+                position.x, position.y
+            );
+        }
+        Err(AppError::Verify(format!(
+            "{} of {} frames differ from {}",
+            mismatches.len(),
+            current.frames.len().max(baseline.frames.len()),
+            baseline_path.display()
+        )))
+    }
+}
+
+/// Report how two worlds differ, and optionally render them for comparison
+pub fn diff(matches: &ArgMatches) -> Result<(), AppError> {
+    let load = |path: &str| -> Result<World, AppError> {
+        let mut world = World::from_file(path)?;
+        world.load_all_chunks()?;
+        Ok(world)
+    };
+    let before = load(matches.value_of("before").unwrap())?;
+    let after = load(matches.value_of("after").unwrap())?;
+
+    print!("{}", WorldDiff::new(&before, &after));
+
+    if let Some(path) = matches.value_of("image") {
+        let (width, height) = parse_resolution(matches.value_of("resolution").unwrap()).unwrap();
+        let image = comparison_image(
+            &before,
+            &after,
+            width as u32,
+            height as u32,
+            matches.is_present("overlay"),
+        );
+        image.save(path)?;
+        info!("Wrote the comparison to {}", path);
+    }
+    Ok(())
+}
+
+/// Parse a `FIELD=VALUE` assignment. Values are JSON5, or plain text when they don't parse
+fn parse_assignment(text: &str) -> Result<(String, Value), AppError> {
+    let (field, value) = text.split_once('=').ok_or_else(|| {
+        AppError::Usage(format!(
+            "Invalid assignment {} (expected FIELD=VALUE)",
+            text
+        ))
+    })?;
+    let value = json5::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()));
+    Ok((field.trim().to_string(), value))
+}
+
+/// `glasscast query`: list the walls or lights a selector picks, or change them all at once
+pub fn query(matches: &ArgMatches) -> Result<(), AppError> {
+    let path = matches.value_of("world").unwrap();
+    let selector: Selector = matches
+        .value_of("selector")
+        .unwrap()
+        .parse()
+        .map_err(AppError::Usage)?;
+    let lights = matches.is_present("lights");
+    let kind = if lights { "light" } else { "wall" };
+    let mut world = World::from_file(path)?;
+
+    let found = if lights {
+        world.find_lights(&selector)
+    } else {
+        world.find_walls(&selector)
+    };
+
+    let mut fields = Map::new();
+    for assignment in matches.values_of("set").into_iter().flatten() {
+        let (field, value) = parse_assignment(assignment)?;
+        fields.insert(field, value);
+    }
+
+    if fields.is_empty() {
+        for &index in found.iter() {
+            let object = if lights {
+                serde_json::to_value(&world.lights[index])
+            } else {
+                serde_json::to_value(&world.walls[index])
+            };
+            // Numbered from zero, like the indices the stdio and HTTP APIs take
+            println!("{} {}: {}", kind, index, object.unwrap());
+        }
+        info!("{} {}s match {}", found.len(), kind, selector);
+        return Ok(());
+    }
+
+    let updated = if lights {
+        world.update_lights(&found, &fields)
+    } else {
+        world.update_walls(&found, &fields)
+    };
+    updated.map_err(AppError::Usage)?;
+
+    let output = matches
+        .value_of("output")
+        .map_or_else(|| save_path(Path::new(path)), PathBuf::from);
+    world.save(&output)?;
+    println!(
+        "Changed {} {}s matching {} in {}",
+        found.len(),
+        kind,
+        selector,
+        output.display()
+    );
+    Ok(())
+}
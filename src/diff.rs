@@ -0,0 +1,244 @@
+//! Comparing two versions of a world.
+
+use std::fmt;
+
+use image::{Rgba, RgbaImage};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::headless::render_image;
+use crate::world::{Light, Wall, World};
+
+/// A field that differs, with its old and new values as JSON
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: String,
+    pub after: String,
+}
+
+/// How a wall or light differs between two versions of a world
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// Only in the newer world, described
+    Added(String),
+
+    /// Only in the older world, described
+    Removed(String),
+
+    /// In both worlds, described, with the fields that differ
+    Changed(String, Vec<FieldChange>),
+}
+
+/// Everything that differs between two versions of a world.
+///
+/// Walls are matched by their ends, so a wall that moved shows up as removed and added again.
+/// Lights are matched by their index, as that is how they are numbered everywhere else.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WorldDiff {
+    pub walls: Vec<Change>,
+    pub lights: Vec<Change>,
+
+    /// Other world settings that differ, such as bloom and fog
+    pub settings: Vec<FieldChange>,
+}
+
+/// World fields compared separately, or that only describe how the world is stored
+const SKIPPED_SETTINGS: &[&str] = &["version", "include", "chunks", "walls", "lights"];
+
+impl WorldDiff {
+    pub fn new(before: &World, after: &World) -> Self {
+        Self {
+            walls: diff_walls(&before.walls, &after.walls),
+            lights: diff_lights(before, after),
+            settings: field_changes(&to_value(before), &to_value(after))
+                .into_iter()
+                .filter(|change| !SKIPPED_SETTINGS.contains(&change.field.as_str()))
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.walls.is_empty() && self.lights.is_empty() && self.settings.is_empty()
+    }
+}
+
+fn to_value(value: &impl Serialize) -> Value {
+    serde_json::to_value(value).expect("Worlds always serialize")
+}
+
+/// The fields of two JSON objects that differ, in order of name
+fn field_changes(before: &Value, after: &Value) -> Vec<FieldChange> {
+    let empty = serde_json::Map::new();
+    let before = before.as_object().unwrap_or(&empty);
+    let after = after.as_object().unwrap_or(&empty);
+
+    let mut fields: Vec<&String> = before.keys().chain(after.keys()).collect();
+    fields.sort();
+    fields.dedup();
+
+    let describe = |value: Option<&Value>| value.map_or("none".to_string(), Value::to_string);
+    fields
+        .into_iter()
+        .filter(|field| before.get(*field) != after.get(*field))
+        .map(|field| FieldChange {
+            field: field.clone(),
+            before: describe(before.get(field)),
+            after: describe(after.get(field)),
+        })
+        .collect()
+}
+
+fn describe_wall(wall: &Wall) -> String {
+    format!(
+        "wall ({}, {}) to ({}, {})",
+        wall.start.x, wall.start.y, wall.end.x, wall.end.y
+    )
+}
+
+fn diff_walls(before: &[Wall], after: &[Wall]) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let mut matched = vec![false; after.len()];
+    for wall in before.iter() {
+        let twin = (0..after.len()).find(|&index| {
+            !matched[index] && after[index].start == wall.start && after[index].end == wall.end
+        });
+        match twin {
+            Some(index) => {
+                matched[index] = true;
+                let fields = field_changes(&to_value(wall), &to_value(&after[index]));
+                if !fields.is_empty() {
+                    changes.push(Change::Changed(describe_wall(wall), fields));
+                }
+            }
+            None => changes.push(Change::Removed(describe_wall(wall))),
+        }
+    }
+
+    changes.extend(
+        after
+            .iter()
+            .zip(matched.iter())
+            .filter(|(_, matched)| !**matched)
+            .map(|(wall, _)| Change::Added(describe_wall(wall))),
+    );
+    changes
+}
+
+fn diff_lights(before: &World, after: &World) -> Vec<Change> {
+    let count = before.lights.len().max(after.lights.len());
+    (0..count)
+        .filter_map(|index| {
+            let name = |light: &Light| {
+                format!(
+                    "light {} at ({:.2}, {:.2})",
+                    index + 1,
+                    light.position.x,
+                    light.position.y
+                )
+            };
+            match (before.lights.get(index), after.lights.get(index)) {
+                (Some(a), Some(b)) => {
+                    let fields = field_changes(&to_value(a), &to_value(b));
+                    if fields.is_empty() {
+                        None
+                    } else {
+                        Some(Change::Changed(format!("light {}", index + 1), fields))
+                    }
+                }
+                (Some(a), None) => Some(Change::Removed(name(a))),
+                (None, Some(b)) => Some(Change::Added(name(b))),
+                (None, None) => None,
+            }
+        })
+        .collect()
+}
+
+/// Write one section of the report, with a summary line and a line per change
+fn write_changes(f: &mut fmt::Formatter, name: &str, changes: &[Change]) -> fmt::Result {
+    if changes.is_empty() {
+        return Ok(());
+    }
+
+    let count = |pick: fn(&Change) -> bool| changes.iter().filter(|change| pick(change)).count();
+    writeln!(
+        f,
+        "{}: {} added, {} removed, {} changed",
+        name,
+        count(|change| matches!(change, Change::Added(_))),
+        count(|change| matches!(change, Change::Removed(_))),
+        count(|change| matches!(change, Change::Changed(..))),
+    )?;
+    for change in changes {
+        match change {
+            Change::Added(description) => writeln!(f, "  + {}", description)?,
+            Change::Removed(description) => writeln!(f, "  - {}", description)?,
+            Change::Changed(description, fields) => {
+                writeln!(f, "  ~ {}", description)?;
+                for field in fields {
+                    writeln!(
+                        f,
+                        "      {}: {} -> {}",
+                        field.field, field.before, field.after
+                    )?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+impl fmt::Display for WorldDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No differences");
+        }
+
+        write_changes(f, "walls", &self.walls)?;
+        write_changes(f, "lights", &self.lights)?;
+        if !self.settings.is_empty() {
+            writeln!(f, "settings:")?;
+            for field in self.settings.iter() {
+                writeln!(
+                    f,
+                    "  ~ {}: {} -> {}",
+                    field.field, field.before, field.after
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Render two versions of a world for comparison.
+///
+/// Side by side, the older world is on the left. As an overlay, light only in the older world
+/// is magenta, light only in the newer world is green, and light in both is white.
+pub fn comparison_image(
+    before: &World,
+    after: &World,
+    width: u32,
+    height: u32,
+    overlay: bool,
+) -> RgbaImage {
+    let before = render_image(before, width, height, true);
+    let after = render_image(after, width, height, true);
+
+    if overlay {
+        let luma = |pixel: &Rgba<u8>| {
+            (0.2126 * pixel[0] as f32 + 0.7152 * pixel[1] as f32 + 0.0722 * pixel[2] as f32) as u8
+        };
+        RgbaImage::from_fn(width, height, |x, y| {
+            let (a, b) = (luma(before.get_pixel(x, y)), luma(after.get_pixel(x, y)));
+            Rgba([a, b, a, 255])
+        })
+    } else {
+        RgbaImage::from_fn(width * 2, height, |x, y| {
+            if x < width {
+                *before.get_pixel(x, y)
+            } else {
+                *after.get_pixel(x - width, y)
+            }
+        })
+    }
+}
@@ -0,0 +1,394 @@
+use glasscast::world::Wall;
+use glasscast::{ColorSpec, Vector2, World};
+use log::debug;
+use raylib::prelude::*;
+
+/// Colors new walls can be drawn with, cycled with C
+const PALETTE: &[(&str, ColorSpec)] = &[
+    ("white", ColorSpec(255, 255, 255, 255)),
+    ("red", ColorSpec(255, 0, 0, 255)),
+    ("green", ColorSpec(0, 255, 0, 255)),
+    ("blue", ColorSpec(0, 0, 255, 255)),
+    ("cyan glass", ColorSpec(0, 255, 255, 96)),
+    ("magenta glass", ColorSpec(255, 0, 255, 96)),
+    ("yellow glass", ColorSpec(255, 255, 0, 96)),
+];
+
+/// Drags shorter than this, in pixels, are treated as clicks rather than walls
+const MIN_WALL_LENGTH: f32 = 4.0;
+
+/// How close the cursor must be to a wall, in pixels, to pick it
+const PICK_DISTANCE: f32 = 8.0;
+
+/// How far Ctrl+D moves duplicates, in pixels, until a paste sets the spacing
+const DUPLICATE_OFFSET: Vector2 = Vector2::new(20.0, 20.0);
+
+/// Convert a world color for drawing with raylib
+pub fn draw_color(color: glasscast::Color) -> Color {
+    Color::new(color.r, color.g, color.b, color.a)
+}
+
+/// Convert a world position for drawing with raylib
+pub fn draw_vector(vector: Vector2) -> raylib::math::Vector2 {
+    raylib::math::Vector2::new(vector.x, vector.y)
+}
+
+/// What the left mouse button does in the editor, cycled with T
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tool {
+    /// Drag to draw a wall
+    Draw,
+
+    /// Click to remove the wall under the cursor
+    Erase,
+
+    /// Click two points to measure the distance and angle between them
+    Measure,
+
+    /// Click walls to select them for copying and duplicating
+    Select,
+}
+
+impl Default for Tool {
+    fn default() -> Self {
+        Tool::Draw
+    }
+}
+
+/// A copy of a wall with both ends moved, that is saved with the world wherever it came from
+fn transformed(wall: &Wall, transform: impl Fn(Vector2) -> Vector2) -> Wall {
+    let mut wall = wall.clone();
+    wall.start = transform(wall.start);
+    wall.end = transform(wall.end);
+    wall.included = false;
+    wall.chunk = None;
+    wall.load_line();
+    wall
+}
+
+/// Interactive scene editing, toggled with E.
+///
+/// While editing, the light stops following the mouse, and the mouse uses the current tool.
+/// The wall under the cursor can be removed with Delete in any tool, or the selected walls if
+/// there are any. Walls loaded from chunks can't be picked.
+///
+/// Selected walls are copied with Ctrl+C, and pasted centered on the cursor with Ctrl+V. M
+/// mirrors the copied walls (Shift+M vertically) and O rotates them a quarter turn before
+/// pasting. Ctrl+D duplicates the selection, spaced like the last paste was from its original.
+#[derive(Default)]
+pub struct Editor {
+    pub active: bool,
+    pub tool: Tool,
+
+    /// The wall under the cursor
+    hovered: Option<usize>,
+
+    /// Where the current drag started, in pixels
+    drag_start: Option<Vector2>,
+
+    /// Index into the palette of the color for new walls
+    color: usize,
+
+    /// The first point of a measurement, and the second once it is placed
+    measurement: Option<(Vector2, Option<Vector2>)>,
+
+    /// Indices of the selected walls
+    selection: Vec<usize>,
+
+    /// Copied walls, centered on the origin
+    clipboard: Vec<Wall>,
+
+    /// Where the copied or last pasted walls were centered
+    clipboard_origin: Option<Vector2>,
+
+    /// How far Ctrl+D moves duplicates, if a paste has set it
+    spacing: Option<Vector2>,
+}
+
+impl Editor {
+    /// Handle editor input. Returns true if the world's walls changed
+    pub fn update(&mut self, d: &RaylibDrawHandle, world: &mut World) -> bool {
+        if d.is_key_pressed(KeyboardKey::KEY_E) {
+            self.active = !self.active;
+            self.drag_start = None;
+            debug!("Editor: {}", self.active);
+        }
+        if !self.active {
+            return false;
+        }
+
+        let control = d.is_key_down(KeyboardKey::KEY_LEFT_CONTROL)
+            || d.is_key_down(KeyboardKey::KEY_RIGHT_CONTROL);
+        let shift = d.is_key_down(KeyboardKey::KEY_LEFT_SHIFT)
+            || d.is_key_down(KeyboardKey::KEY_RIGHT_SHIFT);
+        if d.is_key_pressed(KeyboardKey::KEY_C) && !control {
+            self.color = (self.color + 1) % PALETTE.len();
+        }
+        if d.is_key_pressed(KeyboardKey::KEY_T) {
+            self.tool = match self.tool {
+                Tool::Draw => Tool::Erase,
+                Tool::Erase => Tool::Measure,
+                Tool::Measure => Tool::Select,
+                Tool::Select => Tool::Draw,
+            };
+            self.drag_start = None;
+            self.measurement = None;
+            debug!("Editor tool: {:?}", self.tool);
+        }
+
+        let mouse = d.get_mouse_position();
+        let mouse = Vector2::new(mouse.x, mouse.y);
+        // Chunks' walls are only loaded while in view and are saved in their own files, so they
+        // can't be picked. Loading chunks never moves the other walls, so the selection stays put
+        self.hovered = world
+            .nearest_wall(mouse, PICK_DISTANCE)
+            .filter(|&index| world.walls[index].chunk.is_none());
+
+        // Remove the selected walls, or else the hovered one
+        if d.is_key_pressed(KeyboardKey::KEY_DELETE) && !self.selection.is_empty() {
+            let mut selection = std::mem::take(&mut self.selection);
+            selection.sort_unstable();
+            for index in selection.into_iter().rev() {
+                world.walls.remove(index);
+            }
+            self.hovered = None;
+            return true;
+        }
+        let erase = d.is_key_pressed(KeyboardKey::KEY_DELETE)
+            || (self.tool == Tool::Erase
+                && d.is_mouse_button_pressed(MouseButton::MOUSE_LEFT_BUTTON));
+        if let (true, Some(index)) = (erase, self.hovered) {
+            world.walls.remove(index);
+            self.hovered = None;
+            self.selection.clear();
+            return true;
+        }
+
+        // Copy the selection, and flip or turn the copy
+        if control && d.is_key_pressed(KeyboardKey::KEY_C) && !self.selection.is_empty() {
+            let walls: Vec<&Wall> = self
+                .selection
+                .iter()
+                .map(|&index| &world.walls[index])
+                .collect();
+            let center = walls.iter().fold(Vector2::new(0.0, 0.0), |total, wall| {
+                total + wall.start + wall.end
+            }) / (walls.len() * 2) as f32;
+            self.clipboard = walls
+                .into_iter()
+                .map(|wall| transformed(wall, |point| point - center))
+                .collect();
+            self.clipboard_origin = Some(center);
+            debug!("Copied {} walls", self.clipboard.len());
+        }
+        if d.is_key_pressed(KeyboardKey::KEY_M) {
+            let mirror = |point: Vector2| {
+                if shift {
+                    Vector2::new(point.x, -point.y)
+                } else {
+                    Vector2::new(-point.x, point.y)
+                }
+            };
+            self.clipboard = self
+                .clipboard
+                .iter()
+                .map(|wall| transformed(wall, mirror))
+                .collect();
+        }
+        if d.is_key_pressed(KeyboardKey::KEY_O) {
+            let rotate = |point: Vector2| Vector2::new(-point.y, point.x);
+            self.clipboard = self
+                .clipboard
+                .iter()
+                .map(|wall| transformed(wall, rotate))
+                .collect();
+        }
+
+        // Paste the copied walls around the cursor, remembering how far they moved for Ctrl+D
+        if control && d.is_key_pressed(KeyboardKey::KEY_V) && !self.clipboard.is_empty() {
+            if let Some(origin) = self.clipboard_origin {
+                self.spacing = Some(mouse - origin);
+            }
+            self.clipboard_origin = Some(mouse);
+            let pasted: Vec<Wall> = self
+                .clipboard
+                .iter()
+                .map(|wall| transformed(wall, |point| point + mouse))
+                .collect();
+            self.select_new(world, pasted);
+            return true;
+        }
+
+        // Duplicate the selection beside itself
+        if control && d.is_key_pressed(KeyboardKey::KEY_D) && !self.selection.is_empty() {
+            let offset = self.spacing.unwrap_or(DUPLICATE_OFFSET);
+            let duplicates: Vec<Wall> = self
+                .selection
+                .iter()
+                .map(|&index| transformed(&world.walls[index], |point| point + offset))
+                .collect();
+            self.select_new(world, duplicates);
+            return true;
+        }
+
+        match self.tool {
+            Tool::Draw => {
+                if d.is_mouse_button_pressed(MouseButton::MOUSE_LEFT_BUTTON) {
+                    self.drag_start = Some(mouse);
+                }
+                if d.is_mouse_button_released(MouseButton::MOUSE_LEFT_BUTTON) {
+                    if let Some(start) = self.drag_start.take() {
+                        if (mouse - start).length() >= MIN_WALL_LENGTH {
+                            world.add_walls(Some(Wall::new(start, mouse, PALETTE[self.color].1)));
+                            return true;
+                        }
+                    }
+                }
+            }
+            Tool::Measure => {
+                if d.is_mouse_button_pressed(MouseButton::MOUSE_LEFT_BUTTON) {
+                    self.measurement = match self.measurement {
+                        Some((start, None)) => Some((start, Some(mouse))),
+                        _ => Some((mouse, None)),
+                    };
+                }
+            }
+            Tool::Select => {
+                if d.is_mouse_button_pressed(MouseButton::MOUSE_LEFT_BUTTON) {
+                    match self.hovered {
+                        Some(index) => match self.selection.iter().position(|&i| i == index) {
+                            Some(position) => {
+                                self.selection.remove(position);
+                            }
+                            None => self.selection.push(index),
+                        },
+                        None => self.selection.clear(),
+                    }
+                }
+            }
+            Tool::Erase => {}
+        }
+
+        false
+    }
+
+    /// Add walls to the world and select them in place of the current selection
+    fn select_new(&mut self, world: &mut World, walls: Vec<Wall>) {
+        self.selection = world.add_walls(walls).collect();
+        debug!("Added {} walls", self.selection.len());
+    }
+
+    /// Draw wall outlines and the wall being dragged out, in world coordinates
+    pub fn draw(&self, d: &mut RaylibDrawHandle, world: &World) {
+        if !self.active {
+            return;
+        }
+
+        for (index, wall) in world.walls.iter().enumerate() {
+            if self.selection.contains(&index) {
+                d.draw_line_ex(
+                    draw_vector(wall.start),
+                    draw_vector(wall.end),
+                    3.0,
+                    Color::YELLOW,
+                );
+            } else if self.hovered == Some(index) {
+                d.draw_line_ex(
+                    draw_vector(wall.start),
+                    draw_vector(wall.end),
+                    3.0,
+                    Color::RED,
+                );
+            } else {
+                d.draw_line_v(
+                    draw_vector(wall.start),
+                    draw_vector(wall.end),
+                    Color::new(255, 255, 255, 96),
+                );
+            }
+        }
+
+        let outlines = world
+            .mirrors
+            .iter()
+            .map(|mirror| mirror.outline(32))
+            .chain(world.lenses.iter().map(|lens| lens.outline(32)));
+        for outline in outlines {
+            for pair in outline.windows(2) {
+                d.draw_line_v(
+                    draw_vector(pair[0]),
+                    draw_vector(pair[1]),
+                    Color::new(128, 192, 255, 160),
+                );
+            }
+        }
+
+        // Where the copied walls would be pasted
+        if self.tool == Tool::Select {
+            let mouse = d.get_mouse_position();
+            let mouse = Vector2::new(mouse.x, mouse.y);
+            for wall in self.clipboard.iter() {
+                d.draw_line_v(
+                    draw_vector(wall.start + mouse),
+                    draw_vector(wall.end + mouse),
+                    Color::new(255, 255, 0, 96),
+                );
+            }
+        }
+
+        let color = draw_color(PALETTE[self.color].1.into());
+        if let Some(start) = self.drag_start {
+            d.draw_line_ex(draw_vector(start), d.get_mouse_position(), 2.0, color);
+        }
+
+        // The measurement, following the cursor until its second point is placed
+        if let Some((start, end)) = self.measurement {
+            let mouse = d.get_mouse_position();
+            let end = end.unwrap_or_else(|| Vector2::new(mouse.x, mouse.y));
+            let delta = end - start;
+
+            // Angles are counter-clockwise from the +x axis, as on paper, even though y points down
+            let angle = (-delta.y).atan2(delta.x).to_degrees();
+            d.draw_line_ex(draw_vector(start), draw_vector(end), 2.0, Color::YELLOW);
+            d.draw_circle_v(draw_vector(start), 3.0, Color::YELLOW);
+            d.draw_circle_v(draw_vector(end), 3.0, Color::YELLOW);
+            let middle = (start + end) / 2.0;
+            d.draw_text(
+                &format!("{:.1} px, {:.1} deg", delta.length(), angle),
+                middle.x as i32 + 8,
+                middle.y as i32 + 8,
+                20,
+                Color::YELLOW,
+            );
+        }
+    }
+
+    /// Draw the editor's status, with a swatch of the current color
+    pub fn draw_status(&self, d: &mut RaylibDrawHandle) {
+        if !self.active {
+            return;
+        }
+
+        let (name, color) = PALETTE[self.color];
+        let color = draw_color(color.into());
+        let y = d.get_screen_height() - 50;
+        d.draw_rectangle(5, y, 20, 20, color);
+        d.draw_rectangle_lines(5, y, 20, 20, Color::WHITE);
+        let status = match self.tool {
+            Tool::Draw => format!(
+                "EDITING: drag to draw a {} wall (C: color, T: tool, E: exit)",
+                name
+            ),
+            Tool::Erase => "ERASING: click a wall to remove it (T: tool, E: exit)".to_string(),
+            Tool::Measure => {
+                "MEASURING: click two points to measure between them (T: tool, E: exit)".to_string()
+            }
+            Tool::Select => format!(
+                "SELECTING: {} selected, {} copied (Ctrl+C/V/D: copy/paste/duplicate, M/O: mirror/rotate, T: tool, E: exit)",
+                self.selection.len(),
+                self.clipboard.len()
+            ),
+        };
+        d.draw_text(&status, 30, y, 20, Color::WHITE);
+    }
+}
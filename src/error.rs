@@ -0,0 +1,33 @@
+use std::io;
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+/// Everything that can go wrong while loading a world
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{}: {}", .path.display(), .source)]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// The file is not valid JSON5, or does not describe a world
+    #[error("{}: invalid world: {}", .path.display(), .message)]
+    Parse { path: PathBuf, message: String },
+
+    /// The file's format version can't be migrated
+    #[error("{}: {}", .path.display(), .message)]
+    Version { path: PathBuf, message: String },
+
+    #[error("{}: includes are nested too deeply (is there a cycle?)", .path.display())]
+    IncludeDepth { path: PathBuf },
+
+    #[error("{}: failed to load image: {}", .path.display(), .source)]
+    Image {
+        path: PathBuf,
+        #[source]
+        source: image::ImageError,
+    },
+}
@@ -0,0 +1,98 @@
+use std::io;
+
+use raylib::prelude::*;
+use thiserror::Error;
+
+/// Everything that can stop the command line tool
+#[derive(Debug, Error)]
+pub enum AppError {
+    #[error(transparent)]
+    World(#[from] glasscast::Error),
+
+    /// The window, or a GPU resource, could not be created
+    #[error("graphics error: {0}")]
+    Graphics(String),
+
+    #[error("failed to write output: {0}")]
+    Output(#[from] io::Error),
+
+    #[error("failed to save image: {0}")]
+    Image(#[from] image::ImageError),
+
+    #[error("export failed: {0}")]
+    Export(String),
+
+    #[error("audio input error: {0}")]
+    Audio(String),
+
+    #[error("terminal error: {0}")]
+    Terminal(String),
+
+    /// The HTTP API could not start listening
+    #[error("server error: {0}")]
+    Server(String),
+
+    /// The MQTT broker could not be subscribed to
+    #[error("MQTT error: {0}")]
+    Mqtt(String),
+
+    /// Renders no longer match a verification baseline
+    #[error("verification failed: {0}")]
+    Verify(String),
+
+    /// The command line asked for something that can't be done
+    #[error("{0}")]
+    Usage(String),
+}
+
+impl AppError {
+    /// The process exit code for this kind of failure
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            AppError::Usage(_) => 2,
+            AppError::World(_) => 3,
+            AppError::Graphics(_) => 4,
+            AppError::Output(_) | AppError::Image(_) | AppError::Export(_) => 5,
+            AppError::Audio(_) => 6,
+            AppError::Terminal(_) => 7,
+            AppError::Verify(_) => 8,
+            AppError::Server(_) => 9,
+            AppError::Mqtt(_) => 10,
+        }
+    }
+}
+
+/// An on-screen panel for failures the program recovers from, like a shader that fails to reload
+#[derive(Default)]
+pub struct ErrorPanel {
+    message: Option<String>,
+}
+
+impl ErrorPanel {
+    pub fn show(&mut self, message: String) {
+        log::error!("{}", message);
+        self.message = Some(message);
+    }
+
+    pub fn clear(&mut self) {
+        self.message = None;
+    }
+
+    pub fn draw(&self, d: &mut RaylibDrawHandle) {
+        let message = match &self.message {
+            Some(message) => message,
+            None => return,
+        };
+
+        let lines: Vec<&str> = message.lines().collect();
+        let height = 35 + 20 * lines.len() as i32;
+        let y = d.get_screen_height() - height - 60;
+        let width = d.get_screen_width() - 10;
+        d.draw_rectangle(5, y, width, height, Color::new(80, 0, 0, 220));
+        d.draw_rectangle_lines(5, y, width, height, Color::RED);
+        d.draw_text("Error", 15, y + 8, 20, Color::WHITE);
+        for (index, line) in lines.iter().enumerate() {
+            d.draw_text(line, 15, y + 30 + 20 * index as i32, 16, Color::WHITE);
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use std::fs::create_dir_all;
+use std::io::{stdout, Write};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+
+use glasscast::World;
+use raylib::prelude::*;
+
+use crate::errors::AppError;
+use crate::pass::RenderPass;
+use crate::render::LightRenderer;
+use crate::shaders::PostProcess;
+
+/// Where exported frames are sent
+pub enum FrameTarget {
+    /// Write `frame_NNNN.png` files into a directory
+    Directory(PathBuf),
+
+    /// Write raw RGBA frames to stdout
+    Stdout,
+
+    /// Stream raw RGBA frames into an ffmpeg process encoding the given output file
+    Ffmpeg(String),
+}
+
+/// Settings for exporting a light animation as an image sequence
+pub struct ExportSettings {
+    pub target: FrameTarget,
+
+    /// Frames per second of animation time
+    pub fps: f32,
+
+    /// Length of the animation in seconds
+    pub duration: f32,
+}
+
+/// Spawn an ffmpeg process that reads raw RGBA frames from its stdin
+fn spawn_ffmpeg(output: &str, width: i32, height: i32, fps: f32) -> Result<Child, AppError> {
+    Command::new("ffmpeg")
+        .args(&["-y", "-f", "rawvideo", "-pix_fmt", "rgba", "-s"])
+        .arg(format!("{}x{}", width, height))
+        .arg("-r")
+        .arg(fps.to_string())
+        .args(&["-i", "-", "-pix_fmt", "yuv420p"])
+        .arg(output)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| AppError::Export(format!("failed to start ffmpeg: {}", e)))
+}
+
+/// Flatten an image into raw RGBA bytes
+pub fn raw_rgba(image: &Image) -> Vec<u8> {
+    image
+        .get_image_data()
+        .iter()
+        .flat_map(|c| vec![c.r, c.g, c.b, c.a])
+        .collect()
+}
+
+/// Read a render texture back as an image, the right way up
+pub fn texture_image(texture: &RenderTexture2D) -> Result<image::RgbaImage, String> {
+    let mut screenshot = texture.texture().get_texture_data()?;
+    screenshot.flip_vertical();
+    let (width, height) = (screenshot.width() as u32, screenshot.height() as u32);
+    image::RgbaImage::from_raw(width, height, raw_rgba(&screenshot))
+        .ok_or_else(|| "frame has the wrong size".to_string())
+}
+
+/// Render the world's light animation to a sequence of frames.
+///
+/// Frame timing is derived from the frame rate, not from wall-clock time.
+pub fn export_frames(
+    rl: &mut RaylibHandle,
+    thread: &RaylibThread,
+    world: &mut World,
+    post: &mut PostProcess,
+    renderer: &mut LightRenderer,
+    settings: &ExportSettings,
+) -> Result<(), AppError> {
+    let mut ffmpeg = match &settings.target {
+        FrameTarget::Directory(directory) => {
+            create_dir_all(directory)?;
+            None
+        }
+        FrameTarget::Stdout => None,
+        FrameTarget::Ffmpeg(output) => Some(spawn_ffmpeg(
+            output,
+            renderer.surface.width(),
+            renderer.surface.height(),
+            settings.fps,
+        )?),
+    };
+
+    // The composited frame is rendered here before being read back
+    let (width, height) = (renderer.surface.width(), renderer.surface.height());
+    let output = rl
+        .load_render_texture(thread, width as u32, height as u32)
+        .map_err(AppError::Graphics)?;
+    let size = glasscast::Vector2::new(width as f32, height as f32);
+
+    let frame_count = ((settings.duration * settings.fps).ceil() as usize).max(1);
+    for frame in 0..frame_count {
+        // Move the lights to where they are at this frame
+        let time = frame as f32 / settings.fps;
+        world.animate(time);
+
+        {
+            let mut d = rl.begin_drawing(thread);
+            renderer.render_now(world, &size, &mut d);
+            post.prepare(&mut d, renderer, time);
+
+            // Composite the frame offscreen
+            {
+                let mut target = RenderPass::begin(&mut d, &output, "export");
+                target.clear_background(Color::BLACK);
+                post.draw(&mut target, renderer, time);
+            }
+
+            // Show progress in the window
+            d.clear_background(Color::BLACK);
+            d.draw_text(
+                &format!("Exporting frame {} of {}", frame + 1, frame_count),
+                5,
+                5,
+                20,
+                Color::WHITE,
+            );
+        }
+
+        // Read the frame back and save it
+        let mut image = output
+            .texture()
+            .get_texture_data()
+            .map_err(AppError::Graphics)?;
+        image.flip_vertical();
+        match &settings.target {
+            FrameTarget::Directory(directory) => {
+                let path = directory.join(format!("frame_{:04}.png", frame + 1));
+                image.export_image(&path.to_string_lossy());
+            }
+            FrameTarget::Stdout => stdout().write_all(&raw_rgba(&image))?,
+            FrameTarget::Ffmpeg(_) => {
+                let stdin = ffmpeg.as_mut().and_then(|child| child.stdin.as_mut());
+                if let Some(stdin) = stdin {
+                    stdin.write_all(&raw_rgba(&image))?;
+                }
+            }
+        }
+    }
+
+    // Close ffmpeg's input and wait for it to finish encoding
+    if let Some(mut child) = ffmpeg {
+        drop(child.stdin.take());
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(AppError::Export(format!("ffmpeg exited with {}", status)));
+        }
+    }
+    stdout().flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,80 @@
+use crate::color::Color;
+use crate::math::Vector2;
+use crate::trace::RaySink;
+
+/// An in-memory RGBA image that ray samples are drawn into
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Color::BLACK; width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Fill every pixel with a color
+    pub fn clear(&mut self, color: Color) {
+        for pixel in self.pixels.iter_mut() {
+            *pixel = color;
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    /// Flatten the buffer into raw RGBA bytes
+    pub fn to_rgba(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|c| vec![c.r, c.g, c.b, c.a])
+            .collect()
+    }
+}
+
+impl RaySink for Framebuffer {
+    fn deposit(&mut self, pixel: Vector2, color: Color) {
+        if pixel.x < 0.0 || pixel.y < 0.0 {
+            return;
+        }
+        let (x, y) = (pixel.x as usize, pixel.y as usize);
+        if x >= self.width || y >= self.height {
+            return;
+        }
+
+        // Blend over the existing pixel, like drawing with alpha. Transparent pixels are ones
+        // no ray has reached yet
+        let destination = &mut self.pixels[y * self.width + x];
+        let alpha = color.a as f32 / 255.0;
+        let below = destination.a as f32 / 255.0 * (1.0 - alpha);
+        let coverage = alpha + below;
+        if coverage <= 0.0 {
+            return;
+        }
+        let blend = |src: u8, dst: u8| ((src as f32 * alpha + dst as f32 * below) / coverage) as u8;
+        *destination = Color {
+            r: blend(color.r, destination.r),
+            g: blend(color.g, destination.g),
+            b: blend(color.b, destination.b),
+            a: (coverage * 255.0) as u8,
+        };
+    }
+}
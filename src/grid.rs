@@ -0,0 +1,117 @@
+use std::ops::RangeInclusive;
+
+use log::debug;
+use raylib::prelude::*;
+
+use crate::view::View;
+
+/// Pixels between minor grid lines
+const MINOR_SPACING: i32 = 20;
+
+/// Minor lines per major, labelled line
+const MAJOR_EVERY: i32 = 5;
+
+/// Width of the rulers along the top and left edges
+const RULER_SIZE: i32 = 18;
+
+/// A world-space grid with rulers and the cursor's coordinates, toggled with G
+#[derive(Default)]
+pub struct Grid {
+    pub visible: bool,
+}
+
+impl Grid {
+    pub fn update(&mut self, d: &RaylibDrawHandle) {
+        if d.is_key_pressed(KeyboardKey::KEY_G) {
+            self.visible = !self.visible;
+            debug!("Grid: {}", self.visible);
+        }
+    }
+
+    /// Draw the grid lines over the part of the world in view, in world coordinates
+    pub fn draw(&self, d: &mut RaylibDrawHandle, view: &View) {
+        if !self.visible {
+            return;
+        }
+        let (columns, rows) = lines_in_view(view);
+        let (top, bottom) = (rows.start() * MINOR_SPACING, rows.end() * MINOR_SPACING);
+        let (left, right) = (
+            columns.start() * MINOR_SPACING,
+            columns.end() * MINOR_SPACING,
+        );
+
+        // Grid lines, with every few lines drawn brighter
+        let line_color = |index: i32| {
+            if index.rem_euclid(MAJOR_EVERY) == 0 {
+                Color::new(255, 255, 255, 64)
+            } else {
+                Color::new(255, 255, 255, 24)
+            }
+        };
+        for index in columns {
+            let x = index * MINOR_SPACING;
+            d.draw_line(x, top, x, bottom, line_color(index));
+        }
+        for index in rows {
+            let y = index * MINOR_SPACING;
+            d.draw_line(left, y, right, y, line_color(index));
+        }
+    }
+
+    /// Draw the rulers and the cursor's coordinates, labelled for the part of the world in view
+    pub fn draw_rulers(&self, d: &mut RaylibDrawHandle, view: &View) {
+        if !self.visible {
+            return;
+        }
+        let (width, height) = (d.get_screen_width(), d.get_screen_height());
+        let (columns, rows) = lines_in_view(view);
+        let major = |index: &i32| index.rem_euclid(MAJOR_EVERY) == 0;
+        let scene = view.scene_size();
+
+        // Rulers, labelled at every major line
+        let ruler_color = Color::new(0, 0, 0, 160);
+        d.draw_rectangle(0, 0, width, RULER_SIZE, ruler_color);
+        d.draw_rectangle(0, RULER_SIZE, RULER_SIZE, height - RULER_SIZE, ruler_color);
+        for index in columns.filter(major) {
+            let x = index * MINOR_SPACING;
+            let screen_x = view.to_screen(glasscast::Vector2::new(x as f32, 0.0)).x as i32;
+            if screen_x < RULER_SIZE || screen_x > width {
+                continue;
+            }
+            d.draw_line(screen_x, 0, screen_x, RULER_SIZE, Color::WHITE);
+            d.draw_text(&x.to_string(), screen_x + 2, 4, 10, Color::WHITE);
+        }
+        for index in rows.filter(major) {
+            let y = index * MINOR_SPACING;
+            let screen_y = view.to_screen(glasscast::Vector2::new(0.0, y as f32)).y as i32;
+            if screen_y < RULER_SIZE || screen_y > height {
+                continue;
+            }
+            d.draw_line(0, screen_y, RULER_SIZE, screen_y, Color::WHITE);
+            d.draw_text(&y.to_string(), 2, screen_y + 2, 10, Color::WHITE);
+        }
+
+        // Cursor coordinates in pixels (as walls use), and normalized (as lights use)
+        let mouse = d.get_mouse_position();
+        let text = format!(
+            "{:.0}, {:.0} ({:.3}, {:.3})",
+            mouse.x,
+            mouse.y,
+            mouse.x / scene.x,
+            mouse.y / scene.y
+        );
+        let text_width = measure_text(&text, 20);
+        d.draw_text(&text, width - text_width - 5, height - 25, 20, Color::WHITE);
+    }
+}
+
+/// Indices of the grid lines across and down the part of the world in view
+fn lines_in_view(view: &View) -> (RangeInclusive<i32>, RangeInclusive<i32>) {
+    let (position, size) = view.visible();
+    let spacing = MINOR_SPACING as f32;
+    let end = position + size;
+    (
+        (position.x / spacing).floor() as i32..=(end.x / spacing).ceil() as i32,
+        (position.y / spacing).floor() as i32..=(end.y / spacing).ceil() as i32,
+    )
+}
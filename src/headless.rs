@@ -0,0 +1,107 @@
+use image::{GrayImage, RgbaImage};
+
+use crate::accumulate::Accumulator;
+use crate::color::Color;
+use crate::framebuffer::Framebuffer;
+use crate::layer::{blend_layer, fill_background};
+use crate::math::Vector2;
+use crate::sensor::{Discard, Reading, SensorProbe};
+use crate::trace::trace_light;
+use crate::world::{Light, World};
+
+/// A copy of a world with its walls rasterized for tracing into a window
+fn rasterized(world: &World, window_vec: Vector2) -> World {
+    let mut world = world.clone();
+    world.rasterize_walls(window_vec);
+    world
+}
+
+/// Render a world entirely on the CPU, with no window or GPU required.
+///
+/// Each enabled light is traced into its own layer, and the layers are composited in order.
+/// When `additive` is set, rays accumulate their energy like the renderer's accumulate mode.
+pub fn render_image(world: &World, width: u32, height: u32, additive: bool) -> RgbaImage {
+    let world = &rasterized(world, Vector2::new(width as f32, height as f32));
+    let mut pixels = vec![0; width as usize * height as usize * 4];
+    for light in world.lights.iter().filter(|light| light.enabled) {
+        let layer = render_layer(world, light, width, height, additive);
+        blend_layer(&mut pixels, &layer, &light.layer);
+    }
+
+    // Direct layers only cover the pixels their rays reached, over a shared white background
+    if !additive {
+        fill_background(&mut pixels, Color::WHITE);
+    }
+
+    RgbaImage::from_raw(width, height, pixels).expect("Render buffer size mismatch")
+}
+
+/// Render the total light energy reaching every pixel, without tone mapping.
+///
+/// Each enabled light's energy is scaled by its layer's opacity. Blend modes only apply to
+/// displayed pixels, so every light's energy is added.
+pub fn render_energy(world: &World, width: u32, height: u32) -> Accumulator {
+    let window_vec = Vector2::new(width as f32, height as f32);
+    let world = &rasterized(world, window_vec);
+
+    let mut total = Accumulator::new(width as usize, height as usize);
+    let mut buffer = Accumulator::new(width as usize, height as usize);
+    for light in world.lights.iter().filter(|light| light.enabled) {
+        buffer.clear();
+        trace_light(world, light, &window_vec, &mut buffer);
+        total.add_scaled(&buffer, light.layer.opacity);
+    }
+    total
+}
+
+/// Render which pixels any enabled light reaches, as a black and white mask.
+///
+/// Pixels are lit when their energy is above the threshold, where a single full-strength ray
+/// sample is 1.0.
+pub fn render_mask(world: &World, width: u32, height: u32, threshold: f32) -> GrayImage {
+    let energy = render_energy(world, width, height);
+    GrayImage::from_raw(width, height, energy.mask(threshold)).expect("Render buffer size mismatch")
+}
+
+/// Render a single light's layer as RGBA8 pixels. Without `additive`, pixels no ray reached are
+/// transparent
+pub fn render_layer(
+    world: &World,
+    light: &Light,
+    width: u32,
+    height: u32,
+    additive: bool,
+) -> Vec<u8> {
+    let window_vec = Vector2::new(width as f32, height as f32);
+    let prepared;
+    let world = if world.occupancy.is_some() {
+        world
+    } else {
+        prepared = rasterized(world, window_vec);
+        &prepared
+    };
+
+    if additive {
+        let mut buffer = Accumulator::new(width as usize, height as usize);
+        trace_light(world, light, &window_vec, &mut buffer);
+        buffer.tone_map()
+    } else {
+        let mut buffer = Framebuffer::new(width as usize, height as usize);
+        buffer.clear(Color::TRANSPARENT);
+        trace_light(world, light, &window_vec, &mut buffer);
+        buffer.to_rgba()
+    }
+}
+
+/// Measure the light that every enabled light casts onto the world's sensors
+pub fn measure_sensors(world: &World, width: u32, height: u32) -> Vec<Reading> {
+    let window_vec = Vector2::new(width as f32, height as f32);
+    let world = &rasterized(world, window_vec);
+
+    let mut discard = Discard;
+    let mut probe = SensorProbe::new(&mut discard, &world.sensors);
+    for light in world.lights.iter().filter(|light| light.enabled) {
+        trace_light(world, light, &window_vec, &mut probe);
+    }
+    probe.into_readings()
+}
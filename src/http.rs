@@ -0,0 +1,196 @@
+use std::io::Read;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+
+use glasscast::query::Selector;
+use glasscast::world::{ColorLoad, Wall};
+use glasscast::{ColorSpec, Vector2, World};
+use log::{debug, info, warn};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::errors::AppError;
+
+/// Changes to a light made with `PUT /light`. Anything left out stays as it is
+#[derive(Debug, Deserialize)]
+struct LightUpdate {
+    /// Which light to change, in the order of the world file
+    #[serde(default)]
+    index: usize,
+
+    /// Which light to change by its name. Takes priority over `index`
+    name: Option<String>,
+
+    /// Normalized position, like the world file's
+    position: Option<Vector2>,
+    color: Option<ColorSpec>,
+    intensity: Option<f32>,
+    enabled: Option<bool>,
+}
+
+/// Walls added with `POST /walls`, either one or a list
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum NewWalls {
+    One(Wall),
+    Many(Vec<Wall>),
+}
+
+/// Changes to every wall a selector picks, made with `PATCH /walls`
+#[derive(Debug, Deserialize)]
+struct WallsUpdate {
+    /// `*`, `name:NAME`, or `tag:TAG`
+    select: String,
+
+    /// Fields to replace, as they would be written in the world file
+    set: Map<String, Value>,
+}
+
+/// A plain HTTP interface for querying and driving the simulation from other programs.
+///
+/// Requests are accepted on a background thread, then answered on the main thread between
+/// frames, so they see and change the same world as the window:
+///
+/// - `GET /world` returns the world as JSON
+/// - `PUT /light` changes a light's position, color, intensity, or visibility
+/// - `POST /walls` adds walls
+/// - `PATCH /walls` changes every wall picked by name or tag
+/// - `GET /frame.png` returns the current traced light as an image
+pub struct ApiServer {
+    requests: Receiver<Request>,
+}
+
+impl ApiServer {
+    /// Start listening for requests on an address like `127.0.0.1:8080`
+    pub fn start(address: &str) -> Result<Self, AppError> {
+        let server = Server::http(address).map_err(|e| AppError::Server(e.to_string()))?;
+        info!("Serving the HTTP API on http://{}", address);
+
+        let (sender, requests) = channel();
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                if sender.send(request).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Self { requests })
+    }
+
+    /// Answer every request that arrived since the last frame. `frame` encodes the current
+    /// frame as a PNG, and is only called when one is asked for.
+    ///
+    /// Returns true if the walls changed, so every light needs re-tracing.
+    pub fn handle(&self, world: &mut World, frame: impl Fn() -> Result<Vec<u8>, String>) -> bool {
+        let mut walls_changed = false;
+        for mut request in self.requests.try_iter() {
+            debug!("HTTP {} {}", request.method(), request.url());
+            let mut body = String::new();
+            if let Err(error) = request.as_reader().read_to_string(&mut body) {
+                warn!("Failed to read HTTP request body: {}", error);
+                continue;
+            }
+
+            let response = match (request.method(), request.url()) {
+                (&Method::Get, "/world") => match serde_json::to_string(&*world) {
+                    Ok(json) => json_response(200, json),
+                    Err(error) => error_response(500, &error.to_string()),
+                },
+                (&Method::Put, "/light") => match serde_json::from_str(&body) {
+                    Ok(update) => update_light(world, update),
+                    Err(error) => error_response(400, &error.to_string()),
+                },
+                (&Method::Post, "/walls") => match serde_json::from_str(&body) {
+                    Ok(walls) => {
+                        let mut walls = match walls {
+                            NewWalls::One(wall) => vec![wall],
+                            NewWalls::Many(walls) => walls,
+                        };
+                        for wall in walls.iter_mut() {
+                            wall.load_colors();
+                            wall.load_line();
+                        }
+                        let count = world.add_walls(walls).len();
+                        walls_changed = true;
+                        json_response(200, format!("{{\"added\":{}}}", count))
+                    }
+                    Err(error) => error_response(400, &error.to_string()),
+                },
+                (&Method::Patch, "/walls") => match serde_json::from_str(&body) {
+                    Ok(update) => match update_walls(world, update) {
+                        Ok(count) => {
+                            walls_changed |= count > 0;
+                            json_response(200, format!("{{\"changed\":{}}}", count))
+                        }
+                        Err(error) => error_response(400, &error),
+                    },
+                    Err(error) => error_response(400, &error.to_string()),
+                },
+                (&Method::Get, "/frame.png") => match frame() {
+                    Ok(png) => {
+                        Response::from_data(png).with_header(header("Content-Type", "image/png"))
+                    }
+                    Err(error) => error_response(500, &error),
+                },
+                _ => error_response(404, "no such endpoint"),
+            };
+            if let Err(error) = request.respond(response) {
+                warn!("Failed to send HTTP response: {}", error);
+            }
+        }
+        walls_changed
+    }
+}
+
+fn update_light(world: &mut World, update: LightUpdate) -> Response<std::io::Cursor<Vec<u8>>> {
+    let index = match &update.name {
+        Some(name) => match world.find_lights(&Selector::Name(name.clone())).first() {
+            Some(&index) => index,
+            None => return error_response(404, &format!("there is no light named {}", name)),
+        },
+        None => update.index,
+    };
+    let light = match world.lights.get_mut(index) {
+        Some(light) => light,
+        None => return error_response(404, &format!("there is no light {}", index)),
+    };
+    if let Some(position) = update.position {
+        light.position = position;
+    }
+    if let Some(color) = update.color {
+        light.set_color(color);
+    }
+    if let Some(intensity) = update.intensity {
+        light.intensity = intensity.max(0.0);
+    }
+    if let Some(enabled) = update.enabled {
+        light.enabled = enabled;
+    }
+    match serde_json::to_string(&*light) {
+        Ok(json) => json_response(200, json),
+        Err(error) => error_response(500, &error.to_string()),
+    }
+}
+
+/// Apply a `PATCH /walls` request, returning how many walls changed
+fn update_walls(world: &mut World, update: WallsUpdate) -> Result<usize, String> {
+    let selector: Selector = update.select.parse()?;
+    let found = world.find_walls(&selector);
+    world.update_walls(&found, &update.set)?;
+    Ok(found.len())
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).unwrap()
+}
+
+fn json_response(status: u16, json: String) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_string(json)
+        .with_status_code(status)
+        .with_header(header("Content-Type", "application/json"))
+}
+
+fn error_response(status: u16, message: &str) -> Response<std::io::Cursor<Vec<u8>>> {
+    json_response(status, serde_json::json!({ "error": message }).to_string())
+}
@@ -0,0 +1,39 @@
+use crate::color::Color;
+use crate::world::{BlendMode, LayerSettings};
+
+/// Composite one RGBA8 layer onto another in place.
+///
+/// The layer's alpha is how much of each pixel its rays covered, so pixels no ray reached leave
+/// the destination unchanged. The destination's alpha collects the coverage of every layer.
+pub fn blend_layer(destination: &mut [u8], layer: &[u8], settings: &LayerSettings) {
+    let opacity = settings.opacity.max(0.0).min(1.0);
+
+    for (dst, src) in destination.chunks_mut(4).zip(layer.chunks(4)) {
+        let coverage = src[3] as f32 / 255.0;
+        let weight = opacity * coverage;
+        for channel in 0..3 {
+            let (d, s) = (dst[channel] as f32, src[channel] as f32);
+            let blended = match settings.blend {
+                BlendMode::Normal => s,
+                BlendMode::Additive => d + s,
+                BlendMode::Multiply => d * s / 255.0,
+                BlendMode::Subtract => d - s,
+            };
+            dst[channel] = (d + (blended - d) * weight).max(0.0).min(255.0) as u8;
+        }
+        dst[3] = (src[3] as f32 + dst[3] as f32 * (1.0 - coverage)) as u8;
+    }
+}
+
+/// Fill the parts of a composite that no layer covered with a background color, once every
+/// layer has been blended
+pub fn fill_background(pixels: &mut [u8], color: Color) {
+    for pixel in pixels.chunks_mut(4) {
+        let uncovered = 1.0 - pixel[3] as f32 / 255.0;
+        for (channel, background) in [color.r, color.g, color.b].iter().enumerate() {
+            let filled = pixel[channel] as f32 + *background as f32 * uncovered;
+            pixel[channel] = filled.min(255.0) as u8;
+        }
+        pixel[3] = 255;
+    }
+}
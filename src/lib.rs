@@ -0,0 +1,33 @@
+//! The GlassCast light simulation.
+//!
+//! Worlds are loaded with [`World::from_file`], and traced into any [`RaySink`] with the functions in [`trace`].
+//! Nothing here depends on a window or GPU.
+
+pub mod accumulate;
+pub mod batch;
+pub mod color;
+pub mod diff;
+pub mod error;
+pub mod framebuffer;
+pub mod headless;
+pub mod layer;
+pub mod lightmap;
+pub mod math;
+pub mod migrate;
+pub mod occupancy;
+pub mod optics;
+pub mod plugin;
+pub mod query;
+pub mod raypath;
+pub mod recording;
+pub mod sensor;
+pub mod shading;
+pub mod trace;
+pub mod verify;
+pub mod world;
+
+pub use color::{Color, ColorSpec};
+pub use error::Error;
+pub use math::Vector2;
+pub use trace::RaySink;
+pub use world::World;
@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+
+use crate::math::Vector2;
+use crate::world::World;
+
+/// The area of a game engine's world that a lightmap covers, in engine units
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Bounds {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// A light that a lightmap was baked from, for placing matching lights in the engine
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BakedLight {
+    /// Position of the light in the world file
+    pub index: usize,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+
+    /// Position in engine units
+    pub position: Vector2,
+
+    pub color: [u8; 3],
+    pub intensity: f32,
+
+    /// Radius of the emitter in engine units
+    pub radius: f32,
+}
+
+/// Metadata written next to a baked lightmap texture, describing where the texture sits in a
+/// game engine's world.
+///
+/// Positions use y pointing down, as Godot's 2D space and the texture itself do. Engines with
+/// y pointing up, like Unity, need to flip them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LightmapInfo {
+    /// Path of the texture, relative to the metadata file
+    pub texture: String,
+
+    /// Size of the texture in pixels
+    pub width: u32,
+    pub height: u32,
+
+    /// Texture pixels per engine unit
+    pub pixels_per_unit: f32,
+
+    pub bounds: Bounds,
+    pub lights: Vec<BakedLight>,
+}
+
+impl LightmapInfo {
+    /// Describe a lightmap of a world, with the texture's top left corner placed at `origin` in
+    /// engine units
+    pub fn new(
+        world: &World,
+        texture: impl Into<String>,
+        width: u32,
+        height: u32,
+        pixels_per_unit: f32,
+        origin: Vector2,
+    ) -> Self {
+        let window_vec = Vector2::new(width as f32, height as f32);
+        let lights = world
+            .lights
+            .iter()
+            .enumerate()
+            .filter(|(_, light)| light.enabled)
+            .map(|(index, light)| BakedLight {
+                index,
+                group: light.group.clone(),
+                position: origin + light.position * window_vec / pixels_per_unit,
+                color: [light.color.r, light.color.g, light.color.b],
+                intensity: light.intensity,
+                radius: light.radius / pixels_per_unit,
+            })
+            .collect();
+
+        Self {
+            texture: texture.into(),
+            width,
+            height,
+            pixels_per_unit,
+            bounds: Bounds {
+                x: origin.x,
+                y: origin.y,
+                width: width as f32 / pixels_per_unit,
+                height: height as f32 / pixels_per_unit,
+            },
+            lights,
+        }
+    }
+}
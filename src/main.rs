@@ -1,278 +1,1526 @@
+use std::collections::HashMap;
 use std::fs::File;
+use std::io::{stdout, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use clap::{App, Arg};
-use failure::Error;
-use geo::algorithm::euclidean_distance::EuclideanDistance;
-use geo::{Line, Point};
+use clap::{App, AppSettings, Arg, SubCommand};
+use glasscast::occupancy::Occupancy;
+use glasscast::recording::Recording;
+use glasscast::world::{save_path, TraceKey};
+use glasscast::{ColorSpec, Vector2, World};
+use log::{debug, info, LevelFilter};
+
+use crate::errors::{AppError, ErrorPanel};
 use raylib::prelude::*;
-use raylib::{color::Color, math::Vector2};
-use serde::{Deserialize, Serialize};
 
-trait ColorLoad {
-    fn load_colors(&mut self);
-}
+#[cfg(feature = "audio")]
+mod audio;
+mod commands;
+mod editor;
+mod errors;
+mod export;
+mod grid;
+#[cfg(feature = "http")]
+mod http;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+mod pass;
+mod raydebug;
+mod render;
+mod shaders;
+mod stdio;
+mod timeline;
+mod tui;
+mod view;
+mod watch;
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Wall {
-    #[serde(rename = "color")]
-    raw_color: (u8, u8, u8, u8),
+/// Keys that toggle the first twelve lights
+const FUNCTION_KEYS: [KeyboardKey; 12] = [
+    KeyboardKey::KEY_F1,
+    KeyboardKey::KEY_F2,
+    KeyboardKey::KEY_F3,
+    KeyboardKey::KEY_F4,
+    KeyboardKey::KEY_F5,
+    KeyboardKey::KEY_F6,
+    KeyboardKey::KEY_F7,
+    KeyboardKey::KEY_F8,
+    KeyboardKey::KEY_F9,
+    KeyboardKey::KEY_F10,
+    KeyboardKey::KEY_F11,
+    KeyboardKey::KEY_F12,
+];
 
-    #[serde(skip)]
-    pub color: Color,
+/// Keys that toggle the first nine light groups, with Shift held
+const GROUP_KEYS: [KeyboardKey; 9] = [
+    KeyboardKey::KEY_ONE,
+    KeyboardKey::KEY_TWO,
+    KeyboardKey::KEY_THREE,
+    KeyboardKey::KEY_FOUR,
+    KeyboardKey::KEY_FIVE,
+    KeyboardKey::KEY_SIX,
+    KeyboardKey::KEY_SEVEN,
+    KeyboardKey::KEY_EIGHT,
+    KeyboardKey::KEY_NINE,
+];
 
-    start: Vector2,
-    end: Vector2,
+/// Pixels a fixed light moves per arrow key press, and with Shift held for fine placement
+const NUDGE_STEP: f32 = 10.0;
+const FINE_NUDGE_STEP: f32 = 1.0;
 
-    #[serde(skip)]
-    pub line: Option<Line<f32>>,
-}
+/// How much each notch of the mouse wheel scales the active light's intensity, with Ctrl held
+const INTENSITY_STEP: f32 = 1.1;
 
-impl Wall {
-    fn load_line(&mut self) {
-        self.line = Some(Line::new(
-            Point::new(self.start.x, self.start.y),
-            Point::new(self.end.x, self.end.y),
-        ));
+/// Pixels each notch of the mouse wheel grows the active light's emitter, with Shift held
+const RADIUS_STEP: f32 = 1.0;
+
+/// Parse a `WIDTHxHEIGHT` resolution from the command line
+pub fn parse_resolution(text: &str) -> Result<(i32, i32), String> {
+    let mut parts = text.split('x').map(|part| part.trim().parse::<i32>());
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(width)), Some(Ok(height)), None) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(format!(
+            "Expected a resolution in the form WIDTHxHEIGHT but got: {}",
+            text
+        )),
     }
 }
 
-impl ColorLoad for Wall {
-    fn load_colors(&mut self) {
-        self.color = self.raw_color.into();
+/// Parse an `x,y` pair from the command line
+pub fn parse_vector(text: &str) -> Result<Vector2, String> {
+    let mut parts = text.split(',').map(|part| part.trim().parse::<f32>());
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(x)), Some(Ok(y)), None) => Ok(Vector2 { x, y }),
+        _ => Err(format!(
+            "Expected a position in the form x,y but got: {}",
+            text
+        )),
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Light {
-    pub fixed: bool,
-
-    #[serde(rename = "color")]
-    raw_color: (u8, u8, u8, u8),
-
-    #[serde(skip)]
-    pub color: Color,
+/// Open a CSV file for writing, or stdout for `-`, and write its header
+pub fn open_csv(path: &str, header: &str) -> Result<Box<dyn Write>, AppError> {
+    let mut output: Box<dyn Write> = if path == "-" {
+        Box::new(stdout())
+    } else {
+        Box::new(BufWriter::new(File::create(path)?))
+    };
+    writeln!(output, "{}", header)?;
+    Ok(output)
+}
 
-    pub position: Vector2,
+/// A path next to a world file that doesn't exist yet, for saving a copy of the world
+fn copy_path(path: &str) -> PathBuf {
+    let path = Path::new(path);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    (1..)
+        .map(|n| path.with_file_name(format!("{}-{}.json", stem, n)))
+        .find(|copy| !copy.exists())
+        .unwrap()
 }
 
-impl ColorLoad for Light {
-    fn load_colors(&mut self) {
-        self.color = self.raw_color.into();
+fn main() {
+    if let Err(error) = run() {
+        eprintln!("Error: {}", error);
+        std::process::exit(error.exit_code());
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct World {
-    pub walls: Vec<Wall>,
-    pub light: Light,
-}
+fn run() -> Result<(), AppError> {
+    let app = App::new("glasscast")
+        .author("Evan Pratten <ewpratten@gmail.com>")
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .multiple(true)
+                .global(true)
+                .help("Log more detail (-v for progress, -vv for debugging, -vvv for everything)"),
+        )
+        .subcommand(
+            SubCommand::with_name("render")
+                .about("Render worlds to images on the CPU, without opening a window")
+                .arg(
+                    Arg::with_name("world")
+                        .takes_value(true)
+                        .multiple(true)
+                        .required_unless("manifest")
+                        .help("Paths to the world JSON (or JSON5) files"),
+                )
+                .arg(
+                    Arg::with_name("manifest")
+                        .long("manifest")
+                        .takes_value(true)
+                        .value_name("PATH")
+                        .help("Also render the worlds listed in a file, one path per line"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .takes_value(true)
+                        .default_value("render.png")
+                        .help("Path to write the rendered image to (a .pfm path writes the raw light energy as floats)"),
+                )
+                .arg(
+                    Arg::with_name("output-dir")
+                        .long("output-dir")
+                        .takes_value(true)
+                        .value_name("DIRECTORY")
+                        .help("Render each world into this directory, named after the world file, instead of to --output"),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .default_value("png")
+                        .possible_values(&["png", "pfm"])
+                        .help("Image format used with --output-dir"),
+                )
+                .arg(
+                    Arg::with_name("jobs")
+                        .short("j")
+                        .long("jobs")
+                        .takes_value(true)
+                        .default_value("1")
+                        .validator(|v| match v.parse::<usize>() {
+                            Ok(jobs) if jobs > 0 => Ok(()),
+                            _ => Err(format!("Invalid job count: {}", v)),
+                        })
+                        .help("Number of worlds to render in parallel"),
+                )
+                .arg(
+                    Arg::with_name("watch")
+                        .long("watch")
+                        .help("Keep running, and re-render each world whenever its file changes"),
+                )
+                .arg(
+                    Arg::with_name("resolution")
+                        .long("resolution")
+                        .takes_value(true)
+                        .value_name("WIDTHxHEIGHT")
+                        .default_value("800x600")
+                        .validator(|v| parse_resolution(&v).map(|_| ()))
+                        .help("Render resolution"),
+                )
+                .arg(
+                    Arg::with_name("accumulate")
+                        .long("accumulate")
+                        .help("Accumulate ray energy additively"),
+                )
+                .arg(
+                    Arg::with_name("sensors")
+                        .long("sensors")
+                        .takes_value(true)
+                        .value_name("PATH")
+                        .help("Write the light reaching the world's sensors as CSV (use - for stdout)"),
+                )
+                .arg(
+                    Arg::with_name("hour")
+                        .long("hour")
+                        .takes_value(true)
+                        .value_name("HOUR")
+                        .validator(|v| match v.parse::<f32>() {
+                            Ok(hour) if (0.0..24.0).contains(&hour) => Ok(()),
+                            _ => Err(format!("Invalid hour: {}", v)),
+                        })
+                        .help("Place the sun at this hour (0 to 24) in worlds with a day cycle"),
+                )
+                .arg(
+                    Arg::with_name("mask")
+                        .long("mask")
+                        .help("Write a black and white mask of the lit and shadowed pixels instead of the colored render"),
+                )
+                .arg(
+                    Arg::with_name("mask-threshold")
+                        .long("mask-threshold")
+                        .takes_value(true)
+                        .value_name("ENERGY")
+                        .default_value("0")
+                        .validator(|v| match v.parse::<f32>() {
+                            Ok(threshold) if threshold >= 0.0 => Ok(()),
+                            _ => Err(format!("Invalid threshold: {}", v)),
+                        })
+                        .help("Light energy a pixel must exceed to count as lit in the mask, where one full-strength ray is 1"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("bake")
+                .about("Bake a world's light into a texture and metadata for a game engine's 2D lightmap")
+                .arg(
+                    Arg::with_name("world")
+                        .takes_value(true)
+                        .help("Path to the world JSON (or JSON5) file")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .takes_value(true)
+                        .default_value("lightmap.png")
+                        .help("Path to write the texture to. The metadata is written next to it as JSON"),
+                )
+                .arg(
+                    Arg::with_name("resolution")
+                        .long("resolution")
+                        .takes_value(true)
+                        .value_name("WIDTHxHEIGHT")
+                        .default_value("800x600")
+                        .validator(|v| parse_resolution(&v).map(|_| ()))
+                        .help("Texture resolution"),
+                )
+                .arg(
+                    Arg::with_name("pixels-per-unit")
+                        .long("pixels-per-unit")
+                        .takes_value(true)
+                        .default_value("1")
+                        .validator(|v| match v.parse::<f32>() {
+                            Ok(scale) if scale > 0.0 => Ok(()),
+                            _ => Err(format!("Invalid scale: {}", v)),
+                        })
+                        .help("Texture pixels per engine unit"),
+                )
+                .arg(
+                    Arg::with_name("origin")
+                        .long("origin")
+                        .takes_value(true)
+                        .value_name("X,Y")
+                        .default_value("0,0")
+                        .validator(|v| parse_vector(&v).map(|_| ()))
+                        .help("Engine position of the texture's top left corner, in engine units"),
+                )
+                .arg(
+                    Arg::with_name("accumulate")
+                        .long("accumulate")
+                        .help("Accumulate ray energy additively"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("chunk")
+                .about("Split a very large world's walls into files by area, loaded only while they are near the view")
+                .arg(
+                    Arg::with_name("world")
+                        .takes_value(true)
+                        .help("Path to the world to split")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .takes_value(true)
+                        .help("Path to write the split world to, with its chunks beside it (defaults to replacing the world)"),
+                )
+                .arg(
+                    Arg::with_name("size")
+                        .long("size")
+                        .takes_value(true)
+                        .default_value("256")
+                        .validator(|v| match v.parse::<f32>() {
+                            Ok(size) if size > 0.0 => Ok(()),
+                            _ => Err(format!("Invalid chunk size: {}", v)),
+                        })
+                        .help("Width and height of each chunk's area, in pixels"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("convert")
+                .about("Convert a world between JSON and the compact binary format, chosen by extension")
+                .arg(
+                    Arg::with_name("input")
+                        .takes_value(true)
+                        .help("Path to the world to convert")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .takes_value(true)
+                        .help("Path to write the converted world to. A .bin extension writes the binary format, anything else JSON")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("new")
+                .about("Write a starter world from a built-in template")
+                .arg(
+                    Arg::with_name("path")
+                        .takes_value(true)
+                        .help("Path to write the world file to")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("template")
+                        .short("t")
+                        .long("template")
+                        .takes_value(true)
+                        .default_value("empty-room")
+                        .possible_values(&["empty-room", "prism", "mirror-maze"])
+                        .help("Template to start from"),
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .long("force")
+                        .help("Overwrite the file if it already exists"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("verify")
+                .about("Render a world at fixed light positions and compare the frames against a baseline")
+                .arg(
+                    Arg::with_name("world")
+                        .takes_value(true)
+                        .help("Path to the world JSON (or JSON5) file")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("baseline")
+                        .short("b")
+                        .long("baseline")
+                        .takes_value(true)
+                        .value_name("PATH")
+                        .help("Baseline file to compare against (defaults to the world path with a .baseline.json extension)"),
+                )
+                .arg(
+                    Arg::with_name("resolution")
+                        .long("resolution")
+                        .takes_value(true)
+                        .value_name("WIDTHxHEIGHT")
+                        .default_value("320x240")
+                        .validator(|v| parse_resolution(&v).map(|_| ()))
+                        .help("Render resolution, used when writing a new baseline"),
+                )
+                .arg(
+                    Arg::with_name("update")
+                        .long("update")
+                        .help("Write the current renders as the new baseline instead of comparing"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("diff")
+                .about("Report the walls, lights, and settings that differ between two worlds")
+                .arg(
+                    Arg::with_name("before")
+                        .takes_value(true)
+                        .help("Path to the older world file")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("after")
+                        .takes_value(true)
+                        .help("Path to the newer world file")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("image")
+                        .long("image")
+                        .takes_value(true)
+                        .value_name("PATH")
+                        .help("Also render both worlds side by side into an image"),
+                )
+                .arg(
+                    Arg::with_name("overlay")
+                        .long("overlay")
+                        .requires("image")
+                        .help("Overlay the renders instead, with light only in the older world in magenta and only in the newer world in green"),
+                )
+                .arg(
+                    Arg::with_name("resolution")
+                        .long("resolution")
+                        .takes_value(true)
+                        .value_name("WIDTHxHEIGHT")
+                        .default_value("800x600")
+                        .validator(|v| parse_resolution(&v).map(|_| ()))
+                        .help("Resolution of each render in the image"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("query")
+                .about("List the walls or lights picked by name or tag, or change them all at once")
+                .arg(
+                    Arg::with_name("world")
+                        .takes_value(true)
+                        .help("Path to the world file")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("selector")
+                        .takes_value(true)
+                        .help("Which objects to pick: *, name:NAME, or tag:TAG")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("lights")
+                        .long("lights")
+                        .help("Pick lights instead of walls"),
+                )
+                .arg(
+                    Arg::with_name("set")
+                        .long("set")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .value_name("FIELD=VALUE")
+                        .help("Change a field on every picked object and save the world. Values are JSON5, or text"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .takes_value(true)
+                        .value_name("PATH")
+                        .requires("set")
+                        .help("Save the changed world here instead of over the original"),
+                ),
+        )
+        .arg(
+            Arg::with_name("world")
+                .takes_value(true)
+                .multiple(true)
+                .help("Paths to the world JSON (or JSON5) files. Page Up and Page Down switch between them")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("light-pos")
+                .long("light-pos")
+                .takes_value(true)
+                .value_name("X,Y")
+                .validator(|v| parse_vector(&v).map(|_| ()))
+                .help("Override the light position (normalized to the window size)"),
+        )
+        .arg(
+            Arg::with_name("light-color")
+                .long("light-color")
+                .takes_value(true)
+                .value_name("COLOR")
+                .validator(|v| ColorSpec::parse(&v).map(|_| ()))
+                .help("Override the light color (hex string or color name)"),
+        )
+        .arg(
+            Arg::with_name("fixed")
+                .long("fixed")
+                .conflicts_with("follow-mouse")
+                .help("Pin the light in place"),
+        )
+        .arg(
+            Arg::with_name("follow-mouse")
+                .long("follow-mouse")
+                .help("Make the light follow the mouse"),
+        )
+        .arg(
+            Arg::with_name("resolution")
+                .long("resolution")
+                .takes_value(true)
+                .value_name("WIDTHxHEIGHT")
+                .default_value("800x600")
+                .validator(|v| parse_resolution(&v).map(|_| ()))
+                .help("Window and render resolution"),
+        )
+        .arg(
+            Arg::with_name("accumulate")
+                .long("accumulate")
+                .help("Accumulate ray energy additively, so focused light renders as bright caustics"),
+        )
+        .arg(
+            Arg::with_name("progressive")
+                .long("progressive")
+                .conflicts_with("accumulate")
+                .help("Trace random rays every frame, converging on a noise-free image while the light is still"),
+        )
+        .arg(
+            Arg::with_name("jitter")
+                .long("jitter")
+                .takes_value(true)
+                .value_name("PASSES")
+                .conflicts_with_all(&["accumulate", "progressive"])
+                .validator(|v| match v.parse::<usize>() {
+                    Ok(passes) if passes > 0 => Ok(()),
+                    _ => Err(format!("Invalid pass count: {}", v)),
+                })
+                .help("Jitter ray angles and average PASSES traces over as many frames to remove banding"),
+        )
+        .arg(
+            Arg::with_name("shader")
+                .long("shader")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("Bloom fragment shader to use instead of the built-in one (reloaded when it changes)"),
+        )
+        .arg(
+            Arg::with_name("glsl")
+                .long("glsl")
+                .takes_value(true)
+                .value_name("VERSION")
+                .possible_values(&["330", "300es", "100"])
+                .help("GLSL version of the built-in shaders (detected by default)"),
+        )
+        .arg(
+            Arg::with_name("sensors")
+                .long("sensors")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("Log the light reaching the world's sensors after every trace as CSV (use - for stdout)"),
+        )
+        .arg(
+            Arg::with_name("stdio")
+                .long("stdio")
+                .conflicts_with_all(&["tui", "export-frames", "pipe-ffmpeg"])
+                .help("Read newline-delimited JSON commands from stdin, and write their replies to stdout"),
+        )
+        .arg(
+            Arg::with_name("record")
+                .long("record")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("Record the lights' movement, and save it to PATH on exit"),
+        )
+        .arg(
+            Arg::with_name("replay")
+                .long("replay")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("Play back lights' movement from a recording, instead of following the mouse"),
+        )
+        .arg(
+            Arg::with_name("borderless")
+                .long("borderless")
+                .help("Cover the whole monitor with a window without borders, for ambient displays"),
+        )
+        .arg(
+            Arg::with_name("monitor")
+                .long("monitor")
+                .takes_value(true)
+                .value_name("N")
+                .validator(|v| v.parse::<i32>().map(|_| ()).map_err(|e| e.to_string()))
+                .help("Open the window on a monitor other than the primary one, counting from 0"),
+        )
+        .arg(
+            Arg::with_name("tui")
+                .long("tui")
+                .help("Render to the terminal with colored block characters instead of a window"),
+        )
+        .arg(
+            Arg::with_name("export-frames")
+                .long("export-frames")
+                .takes_value(true)
+                .value_name("DIRECTORY")
+                .help("Render the light animation to numbered PNG frames and exit (use - for raw RGBA on stdout)"),
+        )
+        .arg(
+            Arg::with_name("pipe-ffmpeg")
+                .long("pipe-ffmpeg")
+                .takes_value(true)
+                .value_name("OUTPUT")
+                .conflicts_with("export-frames")
+                .help("Render the light animation straight into ffmpeg, encoding to OUTPUT, and exit"),
+        )
+        .arg(
+            Arg::with_name("fps")
+                .long("fps")
+                .takes_value(true)
+                .default_value("30")
+                .validator(|v| match v.parse::<f32>() {
+                    Ok(fps) if fps > 0.0 => Ok(()),
+                    _ => Err(format!("Invalid frame rate: {}", v)),
+                })
+                .help("Frame rate used when exporting frames"),
+        )
+        .arg(
+            Arg::with_name("duration")
+                .long("duration")
+                .takes_value(true)
+                .validator(|v| v.parse::<f32>().map(|_| ()).map_err(|e| e.to_string()))
+                .help("Length of the exported animation in seconds (defaults to the longest light animation)"),
+        );
+    #[cfg(feature = "audio")]
+    let app = app
+        .arg(
+            Arg::with_name("audio")
+                .long("audio")
+                .help("Modulate the light's brightness with the default audio input"),
+        )
+        .arg(
+            Arg::with_name("audio-gain")
+                .long("audio-gain")
+                .takes_value(true)
+                .default_value("4.0")
+                .validator(|v| v.parse::<f32>().map(|_| ()).map_err(|e| e.to_string()))
+                .help("How strongly audio amplitude affects the light's brightness"),
+        );
+    #[cfg(feature = "http")]
+    let app = app.arg(
+        Arg::with_name("http")
+            .long("http")
+            .takes_value(true)
+            .value_name("ADDRESS")
+            .help("Serve an HTTP API for querying and driving the simulation, on an address like 127.0.0.1:8080"),
+    );
+    #[cfg(feature = "mqtt")]
+    let app = app
+        .arg(
+            Arg::with_name("mqtt")
+                .long("mqtt")
+                .takes_value(true)
+                .value_name("HOST[:PORT]")
+                .help("Drive the lights from an MQTT broker's light topics"),
+        )
+        .arg(
+            Arg::with_name("mqtt-prefix")
+                .long("mqtt-prefix")
+                .takes_value(true)
+                .value_name("PREFIX")
+                .default_value("glasscast")
+                .help("Topic prefix for MQTT light and sensor topics"),
+        )
+        .arg(
+            Arg::with_name("mqtt-sensors")
+                .long("mqtt-sensors")
+                .requires("mqtt")
+                .help("Publish sensor readings to the MQTT broker"),
+        );
+    let matches = app.get_matches();
 
-impl ColorLoad for World {
-    fn load_colors(&mut self) {
-        for wall in self.walls.iter_mut() {
-            wall.load_colors();
-            wall.load_line();
-        }
-        self.light.load_colors();
-    }
-}
+    // Set up logging. RUST_LOG can still narrow it down to specific modules
+    let level = match matches.occurrences_of("verbose") {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    env_logger::Builder::new()
+        .filter_level(level)
+        .parse_env("RUST_LOG")
+        .init();
 
-impl World {
-    pub fn from_file(path: &str) -> Result<Self, Error> {
-        let mut world: World = serde_json::from_reader(File::open(path)?)?;
-        world.load_colors();
-        Ok(world)
+    // Handle subcommands
+    if let Some(matches) = matches.subcommand_matches("render") {
+        return commands::render(matches);
+    }
+    if let Some(matches) = matches.subcommand_matches("bake") {
+        return commands::bake(matches);
+    }
+    if let Some(matches) = matches.subcommand_matches("chunk") {
+        return commands::chunk(matches);
+    }
+    if let Some(matches) = matches.subcommand_matches("convert") {
+        return commands::convert(matches);
+    }
+    if let Some(matches) = matches.subcommand_matches("new") {
+        return commands::new(matches);
+    }
+    if let Some(matches) = matches.subcommand_matches("verify") {
+        return commands::verify(matches);
+    }
+    if let Some(matches) = matches.subcommand_matches("diff") {
+        return commands::diff(matches);
+    }
+    if let Some(matches) = matches.subcommand_matches("query") {
+        return commands::query(matches);
     }
-}
 
-fn find_intersect(wall: &Wall, point: Vector2) -> bool {
-    // Define the line
-    return wall
-        .line
-        .unwrap()
-        .euclidean_distance(&Point::new(point.x, point.y))
-        < 1.0;
-}
+    // Parse every world up front, so a broken one is reported before the window opens
+    let paths: Vec<&str> = matches.values_of("world").unwrap().collect();
+    let mut worlds = paths
+        .iter()
+        .map(World::from_file)
+        .collect::<Result<Vec<_>, _>>()?;
 
-fn get_color_modifier_of_pixel(pixel: Vector2, world: &World) -> Color {
-    // Search all walls
-    for wall in world.walls.iter() {
-        // Check for collision
-        if find_intersect(&wall, pixel) {
-            return wall.color;
+    // Apply any light overrides from the command line to the first light of each world
+    for light in worlds
+        .iter_mut()
+        .filter_map(|world| world.lights.first_mut())
+    {
+        if let Some(position) = matches.value_of("light-pos") {
+            light.position = parse_vector(position).unwrap();
+        }
+        if let Some(color) = matches.value_of("light-color") {
+            light.set_color(ColorSpec::parse(color).unwrap());
+        }
+        if matches.is_present("fixed") {
+            light.fixed = true;
+        } else if matches.is_present("follow-mouse") {
+            light.fixed = false;
         }
     }
 
-    // No modifier
-    return Color::BLACK;
-}
+    // Replay a recorded session through the lights' animations
+    if let Some(path) = matches.value_of("replay") {
+        let recording = Recording::from_file(path)?;
+        for world in worlds.iter_mut() {
+            recording.apply(world);
+        }
+    }
+
+    // The world being shown. The others keep any changes made to them while they are hidden
+    let mut current_world = 0;
+    let mut world = worlds[0].clone();
 
-fn plot(
-    position: &Vector2,
-    normal: Vector2,
-    magnitude: f32,
-    window_vec: &Vector2,
-    ray_color: &Color,
-    world: &World,
-    d: &mut RaylibDrawHandle,
-) -> Option<Color> {
-    // Calculate the current pixel coord
-    let pixel = (normal * magnitude) + (*position * *window_vec);
-
-    // We cannot plot outside the window
-    if (pixel.x < 0.0 || pixel.x > window_vec.x) || (pixel.y < 0.0 || pixel.y > window_vec.y) {
-        return None;
+    // The terminal renderer does not need a window
+    let (width, height) = parse_resolution(matches.value_of("resolution").unwrap()).unwrap();
+    if matches.is_present("tui") {
+        world.load_all_chunks()?;
+        return tui::run(&mut world, width as usize, height as usize)
+            .map_err(|e| AppError::Terminal(e.to_string()));
     }
 
-    // Modify the light ray color
-    let modifier = get_color_modifier_of_pixel(pixel, world);
-    let ray_color = Color {
-        r: (ray_color.r as f32 - modifier.r as f32).clamp(u8::MIN as f32, u8::MAX as f32) as u8,
-        g: (ray_color.g as f32 - modifier.g as f32).clamp(u8::MIN as f32, u8::MAX as f32) as u8,
-        b: (ray_color.b as f32 - modifier.b as f32).clamp(u8::MIN as f32, u8::MAX as f32) as u8,
-        a: 255,
+    // Handle frame exporting
+    let target = match (
+        matches.value_of("export-frames"),
+        matches.value_of("pipe-ffmpeg"),
+    ) {
+        (Some("-"), _) => Some(export::FrameTarget::Stdout),
+        (Some(directory), _) => Some(export::FrameTarget::Directory(directory.into())),
+        (None, Some(output)) => Some(export::FrameTarget::Ffmpeg(output.to_string())),
+        (None, None) => None,
     };
 
-    // Plot the ray
-    d.draw_pixel_v(
-        Vector2 {
-            x: pixel.x,
-            y: pixel.y,
-        },
-        ray_color,
+    // Configure a window, using every pixel of HiDPI displays
+    unsafe {
+        raylib::ffi::SetConfigFlags(raylib::consts::ConfigFlags::FLAG_WINDOW_HIGHDPI as u32);
+    }
+    let mut builder = raylib::init();
+    builder.size(width, height).title("GlassCast").resizable();
+    // builder.msaa_4x();
+    builder.vsync();
+    if matches.is_present("borderless") {
+        builder.undecorated();
+    }
+    let (mut rl, thread) = builder.build();
+
+    // Move to the requested monitor, covering it when borderless. The scene is letterboxed to fit
+    if let Some(monitor) = matches.value_of("monitor") {
+        let monitor: i32 = monitor.parse().unwrap();
+        let count = unsafe { raylib::ffi::GetMonitorCount() };
+        if monitor < 0 || monitor >= count {
+            return Err(AppError::Usage(format!(
+                "there is no monitor {}, only {} connected",
+                monitor, count
+            )));
+        }
+        let position = unsafe { raylib::ffi::GetMonitorPosition(monitor) };
+        rl.set_window_position(position.x as i32, position.y as i32);
+    }
+    if matches.is_present("borderless") {
+        let monitor = matches
+            .value_of("monitor")
+            .map_or(0, |monitor| monitor.parse().unwrap());
+        let (monitor_width, monitor_height) = unsafe {
+            (
+                raylib::ffi::GetMonitorWidth(monitor),
+                raylib::ffi::GetMonitorHeight(monitor),
+            )
+        };
+        rl.set_window_size(monitor_width, monitor_height);
+    }
+
+    // Render at the framebuffer's resolution so HiDPI displays stay sharp. Exported frames keep
+    // the requested size whatever display they were exported on
+    let resolution = match target {
+        Some(_) => 1.0,
+        None => unsafe { raylib::ffi::GetWindowScaleDPI() }.x.max(1.0),
+    };
+    let (render_width, render_height) = (
+        (width as f32 * resolution) as i32,
+        (height as f32 * resolution) as i32,
     );
+    if resolution > 1.0 {
+        info!(
+            "Display scale is {}, rendering at {}x{}",
+            resolution, render_width, render_height
+        );
+    }
 
-    // Iterate a step down the ray
-    return Some(ray_color);
-}
+    // Load the post-processing shaders
+    let mut post = shaders::PostProcess::load(
+        &mut rl,
+        &thread,
+        matches.value_of("shader"),
+        matches
+            .value_of("glsl")
+            .map(|v| shaders::GlslVersion::parse(v).unwrap()),
+        &world,
+        render_width,
+        render_height,
+    )
+    .map_err(AppError::Graphics)?;
+    let mode = if let Some(passes) = matches.value_of("jitter") {
+        render::RenderMode::Jittered {
+            passes: passes.parse().unwrap(),
+        }
+    } else if matches.is_present("progressive") {
+        render::RenderMode::Progressive
+    } else if matches.is_present("accumulate") {
+        render::RenderMode::Accumulate
+    } else {
+        render::RenderMode::Direct
+    };
+    let mut renderer =
+        render::LightRenderer::new(&mut rl, &thread, render_width, render_height, mode)
+            .map_err(AppError::Graphics)?;
 
-fn trace_and_plot(
-    position: &Vector2,
-    normal: Vector2,
-    window_vec: &Vector2,
-    ray_color: Color,
-    world: &World,
-    d: &mut RaylibDrawHandle,
-) {
-    let mut magnitude = 0.0;
-    let mut color = ray_color;
-    loop {
-        let new_color = plot(position, normal, magnitude, window_vec, &color, world, d);
-        magnitude += 2.0;
-
-        // Handle edge of the screen
-        if new_color.is_none() {
-            return;
-        }
-
-        color = new_color.unwrap();
+    if let Some(target) = target {
+        // Exported frames show the whole world, not just the chunks near a view
+        world.load_all_chunks()?;
+        let settings = export::ExportSettings {
+            target,
+            fps: matches.value_of("fps").unwrap().parse().unwrap(),
+            duration: matches
+                .value_of("duration")
+                .map(|d| d.parse().unwrap())
+                .unwrap_or_else(|| world.animation_length()),
+        };
+        return export::export_frames(
+            &mut rl,
+            &thread,
+            &mut world,
+            &mut post,
+            &mut renderer,
+            &settings,
+        );
     }
-}
 
-fn main() {
-    let matches = App::new("glasscast")
-        .author("Evan Pratten <ewpratten@gmail.com>")
-        .arg(
-            Arg::with_name("world")
-                .takes_value(true)
-                .help("Path to the world JSON file")
-                .required(true),
-        )
-        .get_matches();
+    // Sensor readings are logged after every trace
+    let mut sensor_log = match matches.value_of("sensors") {
+        Some(path) => Some(open_csv(
+            path,
+            &format!("time,light,{}", glasscast::sensor::CSV_HEADER),
+        )?),
+        None => None,
+    };
+
+    // The trace inputs of each light, so any change to them triggers a re-trace of that light
+    let mut last_lights: Vec<TraceKey> = Vec::new();
+
+    // The light that follows the mouse and receives layer controls
+    let mut active_light = 0;
+
+    // Time left to show the bloom settings after they were adjusted
+    let mut bloom_overlay = 0.0;
+
+    // Time left to show the active light's intensity and radius after they were adjusted
+    let mut light_overlay = 0.0;
+
+    // Recoverable errors, like a shader that fails to compile, shown until they are resolved
+    let mut error_panel = ErrorPanel::default();
+
+    // The session being recorded, if requested. Switching worlds starts a new recording
+    let mut recording = matches.value_of("record").map(|_| Recording::default());
+
+    // A message about the last save, and the time left to show it
+    let mut save_notice = (String::new(), 0.0);
+
+    // Whether walls are drawn lit by the light falling on them
+    let mut show_walls = false;
+
+    // Whether the render statistics overlay is shown
+    let mut show_stats = false;
+
+    // Grid and rulers for placing things precisely
+    let mut grid = grid::Grid::default();
+
+    // Interactive scene editing
+    let mut editor = editor::Editor::default();
+
+    // Casting single rays to inspect their paths
+    let mut ray_debugger = raydebug::RayDebugger::default();
+
+    // Zooming and panning around the scene
+    let mut view = view::View::new(width, height, resolution);
+    view.set_world(&world);
+
+    // When paused, the light ignores the mouse until a single step is requested
+    let mut paused = false;
 
-    // Get data
-    let world = matches.value_of("world").unwrap();
+    // Time into the light's animation
+    let mut animation_time = 0.0;
 
-    // Parse the world
-    let mut world = World::from_file(world).expect("Failed to read JSON file");
+    // Playing, pausing, and scrubbing through animations
+    let mut timeline = timeline::Timeline::default();
 
-    // Configure a window
-    let (mut rl, thread) = raylib::init()
-        .size(800, 600)
-        .title("GlassCast")
-        // .msaa_4x()
-        .vsync()
-        .build();
+    // Start listening to audio input if requested
+    #[cfg(feature = "audio")]
+    let audio = if matches.is_present("audio") {
+        Some(audio::AudioLevel::open()?)
+    } else {
+        None
+    };
+    #[cfg(feature = "audio")]
+    let audio_gain: f32 = matches.value_of("audio-gain").unwrap().parse().unwrap();
 
-    // Load bloom shader
-    let bloom_shader = rl.load_shader(&thread, None, Some("./bloom.fs")).unwrap();
-    let bloom_surface = rl.load_render_texture(&thread, 800, 600).unwrap();
+    // Connect to the MQTT broker if requested
+    #[cfg(feature = "mqtt")]
+    let mut mqtt = match matches.value_of("mqtt") {
+        Some(broker) => Some(mqtt::MqttBridge::connect(
+            broker,
+            matches.value_of("mqtt-prefix").unwrap(),
+        )?),
+        None => None,
+    };
+    #[cfg(feature = "mqtt")]
+    let mqtt_sensors = matches.is_present("mqtt-sensors");
 
-    // Last light position
-    let mut last_light_position = Vector2::new(-1.0, -1.0);
+    // Start serving the HTTP API if requested
+    #[cfg(feature = "http")]
+    let api = match matches.value_of("http") {
+        Some(address) => Some(http::ApiServer::start(address)?),
+        None => None,
+    };
+
+    // Read commands from stdin if requested
+    let stdio = if matches.is_present("stdio") {
+        // Replies own stdout, so nothing else may write to it
+        if matches.value_of("sensors") == Some("-") {
+            return Err(AppError::Usage(
+                "--stdio and --sensors - both write to stdout".to_string(),
+            ));
+        }
+        Some(stdio::StdioServer::start())
+    } else {
+        None
+    };
+
+    // The walls rasterized at the render resolution, shared by every trace until they change.
+    // Each loaded chunk's walls are rasterized on their own, so loading one leaves the rest
+    let mut occupancy: Option<Arc<Occupancy>> = None;
+    let mut chunk_occupancy: HashMap<usize, Arc<Occupancy>> = HashMap::new();
+    let mut traced_frame = view.frame();
 
     while !rl.window_should_close() {
-        let mut d = rl.begin_drawing(&thread);
+        // Set by anything that adds, removes, or moves walls
+        let mut walls_changed = false;
 
-        // Get the window size as a vector
-        let window_vec = Vector2 {
-            x: d.get_screen_width() as f32,
-            y: d.get_screen_height() as f32,
+        // Switch worlds with Page Up and Page Down
+        let next_world = if rl.is_key_pressed(KeyboardKey::KEY_PAGE_DOWN) {
+            Some((current_world + 1) % worlds.len())
+        } else if rl.is_key_pressed(KeyboardKey::KEY_PAGE_UP) {
+            Some((current_world + worlds.len() - 1) % worlds.len())
+        } else {
+            None
         };
+        if let Some(next_world) = next_world.filter(|next| *next != current_world) {
+            info!("Switching to {}", paths[next_world]);
+            worlds[current_world] = world;
+            world = worlds[next_world].clone();
+            current_world = next_world;
+            rl.set_window_title(&thread, &format!("GlassCast - {}", paths[current_world]));
 
-        // Handle light controls
-        if !world.light.fixed {
-            // Get the mouse vector
-            let mouse_pos = d.get_mouse_position();
+            if let Err(error) = post.load_world(&mut rl, &thread, &world) {
+                error_panel.show(error);
+            }
+            active_light = 0;
+            animation_time = 0.0;
+            view.set_world(&world);
+            chunk_occupancy.clear();
+            walls_changed = true;
+            if let Some(recording) = &mut recording {
+                recording.clear();
+            }
+        }
+
+        // Pick up edits to the shaders
+        match post.reload_if_changed(&mut rl, &thread) {
+            Some(Ok(())) => {
+                post.bloom
+                    .apply(&world.bloom, render_width as f32, render_height as f32);
+                error_panel.clear();
+            }
+            Some(Err(error)) => error_panel.show(error),
+            None => {}
+        }
+
+        // Scrub through the animation, before the mouse is mapped into the world
+        if timeline.update(&mut rl, &world, &mut animation_time) {
+            paused = !paused;
+            debug!("Paused: {}", paused);
+        }
 
-            // Normalize and set
-            world.light.position = mouse_pos / window_vec;
+        // Zoom and pan, mapping the mouse into the world for everything after this
+        view.update(&mut rl);
+        if view.frame() != traced_frame {
+            traced_frame = view.frame();
+            chunk_occupancy.clear();
+            walls_changed = true;
         }
 
-        // Open a shader context
-        // Skip rendering if the light didn't move
-        if world.light.position != last_light_position {
-            unsafe {
-                raylib::ffi::BeginTextureMode(*bloom_surface);
+        // Load the chunks of very large worlds around the view, and let go of the rest. Chunks'
+        // walls come after the others, so only the chunks' own maps change
+        let (position, size) = view.visible();
+        match world.load_chunks_near(position - size, size * 3.0) {
+            Ok(true) => {
+                debug!("Chunks changed, {} walls", world.walls.len());
+                chunk_occupancy.retain(|&index, _| world.chunks[index].loaded);
+                last_lights.clear();
             }
-            d.clear_background(Color::WHITE);
+            Ok(false) => {}
+            Err(error) => error_panel.show(error.to_string()),
+        }
 
-            // Render every ray extending from the light
-            for angle in 0..360 {
-                let angle = angle as f32;
+        let mut d = rl.begin_drawing(&thread);
 
-                // Calculate the ray normal
-                let normal = Vector2 {
-                    x: angle.to_radians().cos(),
-                    y: angle.to_radians().sin(),
-                };
+        // Get the scene size as a vector. It stays the same however the window is resized
+        let window_vec = view.scene_size();
 
-                // Recursive render
-                trace_and_plot(
-                    &world.light.position,
-                    normal,
-                    &window_vec,
-                    world.light.color,
-                    &world,
-                    &mut d,
+        // Handle pause (P) and single-step (N) controls
+        if d.is_key_pressed(KeyboardKey::KEY_P) {
+            paused = !paused;
+            debug!("Paused: {}", paused);
+        }
+        let step = paused && d.is_key_pressed(KeyboardKey::KEY_N);
+
+        // Handle layer controls: Tab selects a light, F1-F12 toggle lights, Shift and 1-9 toggle
+        // light groups, [ and ] fade the selected light
+        if d.is_key_pressed(KeyboardKey::KEY_TAB) && !world.lights.is_empty() {
+            active_light = (active_light + 1) % world.lights.len();
+            debug!("Selected light {}", active_light + 1);
+        }
+        for (index, key) in FUNCTION_KEYS.iter().enumerate() {
+            if d.is_key_pressed(*key) {
+                if let Some(light) = world.lights.get_mut(index) {
+                    light.enabled = !light.enabled;
+                    debug!("Light {} enabled: {}", index + 1, light.enabled);
+                }
+            }
+        }
+        if d.is_key_down(KeyboardKey::KEY_LEFT_SHIFT) || d.is_key_down(KeyboardKey::KEY_RIGHT_SHIFT)
+        {
+            let groups: Vec<String> = world.groups().into_iter().map(String::from).collect();
+            for (group, key) in groups.iter().zip(GROUP_KEYS.iter()) {
+                if d.is_key_pressed(*key) {
+                    let enabled = world.toggle_group(group);
+                    debug!("Light group {} enabled: {}", group, enabled);
+                }
+            }
+        }
+        if let Some(light) = world.lights.get_mut(active_light) {
+            if d.is_key_pressed(KeyboardKey::KEY_LEFT_BRACKET) {
+                light.layer.opacity = (light.layer.opacity - 0.1).max(0.0);
+            }
+            if d.is_key_pressed(KeyboardKey::KEY_RIGHT_BRACKET) {
+                light.layer.opacity = (light.layer.opacity + 0.1).min(1.0);
+            }
+            if d.is_key_pressed(KeyboardKey::KEY_LEFT_BRACKET)
+                || d.is_key_pressed(KeyboardKey::KEY_RIGHT_BRACKET)
+            {
+                debug!(
+                    "Light {} opacity: {:.1}",
+                    active_light + 1,
+                    light.layer.opacity
                 );
             }
+        }
+
+        // Handle live bloom adjustments
+        if shaders::handle_bloom_keys(&d, &mut world.bloom) {
+            post.bloom
+                .apply(&world.bloom, render_width as f32, render_height as f32);
+            bloom_overlay = 2.0;
+            debug!("Bloom: {:?}", world.bloom);
+        }
+
+        // Adjust the active light with the mouse wheel: Ctrl scales its intensity, and Shift
+        // grows its emitter. The wheel alone zooms the view
+        let wheel = d.get_mouse_wheel_move();
+        if let (Some(light), true) = (world.lights.get_mut(active_light), wheel != 0.0) {
+            if d.is_key_down(KeyboardKey::KEY_LEFT_CONTROL)
+                || d.is_key_down(KeyboardKey::KEY_RIGHT_CONTROL)
+            {
+                light.intensity = (light.intensity * INTENSITY_STEP.powf(wheel)).max(0.01);
+                light_overlay = 2.0;
+                debug!(
+                    "Light {} intensity: {:.2}",
+                    active_light + 1,
+                    light.intensity
+                );
+            } else if d.is_key_down(KeyboardKey::KEY_LEFT_SHIFT)
+                || d.is_key_down(KeyboardKey::KEY_RIGHT_SHIFT)
+            {
+                light.radius = (light.radius + RADIUS_STEP * wheel).max(0.0);
+                light_overlay = 2.0;
+                debug!("Light {} radius: {:.0}", active_light + 1, light.radius);
+            }
+        }
+
+        // Save the world with Ctrl+S, or a copy of it with Ctrl+Shift+S
+        let control = d.is_key_down(KeyboardKey::KEY_LEFT_CONTROL)
+            || d.is_key_down(KeyboardKey::KEY_RIGHT_CONTROL);
+        if control && d.is_key_pressed(KeyboardKey::KEY_S) {
+            let shift = d.is_key_down(KeyboardKey::KEY_LEFT_SHIFT)
+                || d.is_key_down(KeyboardKey::KEY_RIGHT_SHIFT);
+            let path = if shift {
+                copy_path(paths[current_world])
+            } else {
+                save_path(Path::new(paths[current_world]))
+            };
+            match world.save(&path) {
+                Ok(()) => save_notice = (format!("Saved to {}", path.display()), 2.0),
+                Err(error) => error_panel.show(error.to_string()),
+            }
+        }
+
+        grid.update(&d);
+        if d.is_key_pressed(KeyboardKey::KEY_L) {
+            show_walls = !show_walls;
+            debug!("Wall shading: {}", show_walls);
+        }
+        if d.is_key_pressed(KeyboardKey::KEY_I) {
+            show_stats = !show_stats;
+            debug!("Statistics overlay: {}", show_stats);
+        }
+
+        // Cycle through the simulated color vision deficiencies. Ctrl+V pastes in the editor
+        if !control && d.is_key_pressed(KeyboardKey::KEY_V) {
+            post.set_vision(post.vision().next());
+            debug!("Color vision: {:?}", post.vision());
+        }
+
+        // Handle ray debugging, and scene editing when not debugging. Clicks on the minimap
+        // belong to the view, and clicks on the timeline to it. Every light is re-traced when
+        // the walls change
+        let over_overlay = view.over_minimap || timeline.over;
+        if !editor.active && !over_overlay {
+            ray_debugger.update(&d, &world, active_light, &window_vec);
+        }
+        if !ray_debugger.active && !over_overlay && editor.update(&d, &mut world) {
+            debug!("Walls changed, {} walls", world.walls.len());
+            walls_changed = true;
+        }
+
+        // Advance the lights' animations
+        if !paused || step {
+            animation_time += d.get_frame_time();
+        }
+        world.animate(animation_time);
+
+        // Move the active light with the mouse, unless it is animated
+        if let Some(light) = world.lights.get_mut(active_light) {
+            if light.keyframes.is_empty()
+                && !light.fixed
+                && !editor.active
+                && !ray_debugger.active
+                && !timeline.dragging()
+                && (!paused || step)
+            {
+                // Get the mouse vector
+                let mouse_pos = d.get_mouse_position();
 
-            unsafe {
-                raylib::ffi::EndTextureMode();
+                // Normalize and set
+                light.position = Vector2::new(mouse_pos.x, mouse_pos.y) / window_vec;
             }
         }
-        last_light_position = world.light.position;
 
-        // Render via the shader
+        // F pins the active light in place or lets it follow the mouse again, and the arrow keys
+        // nudge a pinned light. The ray debugger has the arrow keys while it is open
+        if let Some(light) = world.lights.get_mut(active_light) {
+            if d.is_key_pressed(KeyboardKey::KEY_F) {
+                light.fixed = !light.fixed;
+                debug!("Light {} fixed: {}", active_light + 1, light.fixed);
+            }
+            if light.fixed && light.keyframes.is_empty() && !ray_debugger.active {
+                let fine = d.is_key_down(KeyboardKey::KEY_LEFT_SHIFT)
+                    || d.is_key_down(KeyboardKey::KEY_RIGHT_SHIFT);
+                let distance = if fine { FINE_NUDGE_STEP } else { NUDGE_STEP };
+                let nudge = [
+                    (KeyboardKey::KEY_LEFT, Vector2::new(-distance, 0.0)),
+                    (KeyboardKey::KEY_RIGHT, Vector2::new(distance, 0.0)),
+                    (KeyboardKey::KEY_UP, Vector2::new(0.0, -distance)),
+                    (KeyboardKey::KEY_DOWN, Vector2::new(0.0, distance)),
+                ]
+                .iter()
+                .filter(|(key, _)| d.is_key_pressed(*key))
+                .fold(Vector2::zero(), |total, (_, offset)| total + *offset);
+                if nudge != Vector2::zero() {
+                    light.position = light.position + nudge / window_vec;
+                    debug!(
+                        "Light {} nudged to {:.0}, {:.0}",
+                        active_light + 1,
+                        light.position.x * window_vec.x,
+                        light.position.y * window_vec.y
+                    );
+                }
+            }
+        }
+
+        // Record where the lights are
+        if let (Some(recording), true) = (&mut recording, !paused || step) {
+            recording.capture(animation_time, &world);
+        }
+
+        // Modulate the lights with audio input
+        #[cfg(feature = "audio")]
         {
-            let mut shader_context = d.begin_shader_mode(&bloom_shader);
-
-            // Blit the texture
-            shader_context.draw_texture_rec(
-                &bloom_surface,
-                Rectangle {
-                    x: 0.0,
-                    y: 0.0,
-                    width: bloom_surface.width() as f32,
-                    height: (bloom_surface.height() as f32) * -1.0,
-                },
-                Vector2::zero(),
-                Color::WHITE,
+            if let Some(audio) = &audio {
+                if !paused || step {
+                    let modulation = (audio.level() * audio_gain).min(1.0);
+                    for light in world.lights.iter_mut() {
+                        light.modulation = modulation;
+                    }
+                }
+            }
+        }
+
+        // Answer HTTP API requests, which may move lights or add walls
+        #[cfg(feature = "http")]
+        {
+            if let Some(api) = &api {
+                let frame = || {
+                    let pixels = export::texture_image(&renderer.surface)?;
+                    let mut png = Vec::new();
+                    image::DynamicImage::ImageRgba8(pixels)
+                        .write_to(&mut png, image::ImageOutputFormat::Png)
+                        .map_err(|e| e.to_string())?;
+                    Ok(png)
+                };
+                if api.handle(&mut world, frame) {
+                    debug!("Walls changed, {} walls", world.walls.len());
+                    walls_changed = true;
+                }
+            }
+        }
+
+        // Run commands from stdin, which may move lights or add walls
+        if let Some(stdio) = &stdio {
+            let effects = stdio.handle(&mut world, || export::texture_image(&renderer.surface));
+            if effects.walls_changed {
+                debug!("Walls changed, {} walls", world.walls.len());
+                walls_changed = true;
+            }
+            if effects.quit {
+                break;
+            }
+        }
+
+        // Mirror light state from the MQTT broker
+        #[cfg(feature = "mqtt")]
+        {
+            if let Some(mqtt) = &mqtt {
+                mqtt.update(&mut world);
+            }
+        }
+
+        // Every light is re-traced against freshly rasterized walls when the walls change
+        if walls_changed {
+            last_lights.clear();
+            occupancy = None;
+        }
+
+        // Only re-trace lights whose trace inputs changed, such as by moving or flickering,
+        // unless a step was requested
+        let lights: Vec<_> = world.lights.iter().map(|light| light.trace_key()).collect();
+        let dirty: Vec<usize> = (0..lights.len())
+            .filter(|&index| last_lights.get(index) != Some(&lights[index]) || step)
+            .collect();
+        if !dirty.is_empty() {
+            // Traced at the render resolution, which is finer than the world's pixels on HiDPI
+            // displays. Every light traced this frame shares one copy
+            let render_size = window_vec * resolution;
+            let mut render_world = world.framed(traced_frame, window_vec, resolution);
+            let unchunked = render_world.unchunked_walls();
+            let walls = occupancy.get_or_insert_with(|| {
+                Arc::new(Occupancy::new(
+                    &render_world.walls[..unchunked],
+                    render_size,
+                ))
+            });
+            render_world.occupancy = Some(walls.clone());
+            for (index, _) in world
+                .chunks
+                .iter()
+                .enumerate()
+                .filter(|(_, chunk)| chunk.loaded)
+            {
+                let chunk_walls = render_world.chunk_walls(index);
+                let walls = chunk_occupancy.entry(index).or_insert_with(|| {
+                    Arc::new(Occupancy::around(
+                        &render_world.walls[chunk_walls.clone()],
+                        render_size,
+                    ))
+                });
+                render_world
+                    .chunk_occupancy
+                    .push((chunk_walls.start, walls.clone()));
+            }
+            render_world
+                .chunk_occupancy
+                .sort_by_key(|(first, _)| *first);
+            let render_world = Arc::new(render_world);
+            for index in dirty {
+                renderer.request(index, &render_world, &render_size);
+            }
+        }
+        renderer.set_layers(&world);
+        renderer.update(&mut d, !paused);
+        last_lights = lights;
+
+        // Log the sensor readings of finished traces, and publish them over MQTT if requested
+        for (light, readings) in renderer.readings.drain(..) {
+            #[cfg(feature = "mqtt")]
+            {
+                if let (Some(mqtt), true) = (&mut mqtt, mqtt_sensors) {
+                    mqtt.publish(light, &readings);
+                }
+            }
+            if let Some(log) = &mut sensor_log {
+                for reading in readings {
+                    writeln!(log, "{},{},{}", animation_time, light, reading.to_csv())?;
+                }
+            }
+        }
+
+        // Render via the shaders
+        let time = d.get_time() as f32;
+        post.prepare(&mut d, &renderer, time);
+        d.clear_background(Color::BLACK);
+        view.begin_render();
+        post.draw(&mut d, &renderer, time);
+        view.end();
+        view.begin();
+
+        // Render the walls lit by the light
+        if show_walls {
+            renderer.draw_walls(&mut d, &world);
+        }
+
+        // Render the grid and editor over the scene
+        grid.draw(&mut d, &view);
+        editor.draw(&mut d, &world);
+        ray_debugger.draw(&mut d);
+        view.end();
+
+        // Render the overlays that stay put while zoomed
+        grid.draw_rulers(&mut d, &view);
+        editor.draw_status(&mut d);
+        ray_debugger.draw_status(&mut d);
+        view.draw_minimap(&mut d, &renderer, &world);
+        timeline.draw(&mut d, animation_time, paused);
+
+        // Render statistics
+        if show_stats {
+            let stats = renderer.stats;
+            let lines = [
+                format!("Rays traced: {}", stats.trace.rays),
+                format!("March steps: {}", stats.trace.steps),
+                format!("Intersection tests: {}", stats.trace.intersection_tests),
+                format!(
+                    "Trace time: {:.2} ms",
+                    stats.trace_time.as_secs_f64() * 1000.0
+                ),
+                format!(
+                    "Composite time: {:.2} ms",
+                    stats.composite_time.as_secs_f64() * 1000.0
+                ),
+            ];
+            let x = d.get_screen_width() - 300;
+            d.draw_rectangle(
+                x - 5,
+                0,
+                305,
+                5 + 20 * lines.len() as i32,
+                Color::new(0, 0, 0, 160),
             );
+            for (index, line) in lines.iter().enumerate() {
+                d.draw_text(line, x, 5 + 20 * index as i32, 16, Color::WHITE);
+            }
         }
 
         // Render FPS counter
         d.draw_fps(5, 5);
+
+        // Render pause indicator
+        if paused {
+            d.draw_text("PAUSED (N to step)", 5, 25, 20, Color::RED);
+        }
+
+        // Render the bloom settings for a moment after they change
+        if bloom_overlay > 0.0 {
+            bloom_overlay -= d.get_frame_time();
+            d.draw_text(
+                &format!(
+                    "Bloom threshold {:.2}, radius {:.1}, intensity {:.1}",
+                    world.bloom.threshold, world.bloom.radius, world.bloom.intensity
+                ),
+                5,
+                65,
+                20,
+                Color::WHITE,
+            );
+        }
+
+        // Render the save message for a moment after saving
+        if save_notice.1 > 0.0 {
+            save_notice.1 -= d.get_frame_time();
+            d.draw_text(&save_notice.0, 5, 85, 20, Color::GREEN);
+        }
+
+        // Render the active light's intensity and radius for a moment after they change
+        if let (Some(light), true) = (world.lights.get(active_light), light_overlay > 0.0) {
+            light_overlay -= d.get_frame_time();
+            d.draw_text(
+                &format!(
+                    "Light {} intensity {:.2}, radius {:.0} px",
+                    active_light + 1,
+                    light.intensity,
+                    light.radius
+                ),
+                5,
+                105,
+                20,
+                Color::WHITE,
+            );
+        }
+
+        // Render the time of day when the sun is simulated
+        if let Some(day) = world.day {
+            let hour = day.hour_at(animation_time);
+            d.draw_text(
+                &format!("{:02}:{:02}", hour as u32, (hour.fract() * 60.0) as u32),
+                5,
+                125,
+                20,
+                Color::WHITE,
+            );
+        }
+
+        // Render the simulated color vision deficiency, so it isn't mistaken for the real colors
+        if post.vision() != shaders::Vision::Normal {
+            d.draw_text(
+                &format!(
+                    "Simulating {} (V to cycle)",
+                    format!("{:?}", post.vision()).to_lowercase()
+                ),
+                5,
+                145,
+                20,
+                Color::YELLOW,
+            );
+        }
+
+        // Render recoverable errors
+        error_panel.draw(&mut d);
+
+        // Render the selected light's layer, when there is a choice
+        if world.lights.len() > 1 {
+            let light = &world.lights[active_light];
+            d.draw_text(
+                &format!(
+                    "Light {} of {} ({:?}, {:.0}%{})",
+                    active_light + 1,
+                    world.lights.len(),
+                    light.layer.blend,
+                    light.layer.opacity * 100.0,
+                    if light.enabled { "" } else { ", hidden" }
+                ),
+                5,
+                45,
+                20,
+                Color::WHITE,
+            );
+        }
+    }
+
+    if let (Some(recording), Some(path)) = (recording, matches.value_of("record")) {
+        recording.save(path)?;
+        info!("Saved recording to {}", path);
     }
+
+    Ok(())
 }
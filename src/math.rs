@@ -0,0 +1,114 @@
+use std::ops::{Add, Div, Mul, Sub};
+
+use serde::{Deserialize, Serialize};
+
+/// A 2D vector, used for both world positions and directions
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Vector2 {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Vector2 {
+    pub const fn new(x: f32, y: f32) -> Self {
+        Self { x, y }
+    }
+
+    pub const fn zero() -> Self {
+        Self { x: 0.0, y: 0.0 }
+    }
+
+    pub fn length(&self) -> f32 {
+        (self.x * self.x + self.y * self.y).sqrt()
+    }
+
+    pub fn dot(&self, other: Vector2) -> f32 {
+        self.x * other.x + self.y * other.y
+    }
+
+    /// The vector scaled to a length of one, or zero if it has no length
+    pub fn normalized(&self) -> Self {
+        let length = self.length();
+        if length > 0.0 {
+            *self / length
+        } else {
+            *self
+        }
+    }
+
+    /// Reflect the vector off a surface with the given unit normal
+    pub fn reflect(&self, normal: Vector2) -> Self {
+        *self - normal * (2.0 * self.dot(normal))
+    }
+}
+
+impl Add for Vector2 {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl Sub for Vector2 {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl Mul for Vector2 {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Self::new(self.x * other.x, self.y * other.y)
+    }
+}
+
+impl Mul<f32> for Vector2 {
+    type Output = Self;
+
+    fn mul(self, scale: f32) -> Self {
+        Self::new(self.x * scale, self.y * scale)
+    }
+}
+
+impl Div for Vector2 {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self {
+        Self::new(self.x / other.x, self.y / other.y)
+    }
+}
+
+impl Div<f32> for Vector2 {
+    type Output = Self;
+
+    fn div(self, scale: f32) -> Self {
+        Self::new(self.x / scale, self.y / scale)
+    }
+}
+
+/// A pseudo-random gradient between -1 and 1 for an integer lattice point
+fn lattice_gradient(point: i32) -> f32 {
+    let mut hash = (point as u32).wrapping_mul(0x27d4_eb2d);
+    hash ^= hash >> 15;
+    hash = hash.wrapping_mul(0x85eb_ca6b);
+    hash ^= hash >> 13;
+    hash as f32 / u32::MAX as f32 * 2.0 - 1.0
+}
+
+/// One-dimensional Perlin noise, varying smoothly between about -1 and 1, and 0 at whole numbers
+pub fn perlin(x: f32) -> f32 {
+    let cell = x.floor();
+    let t = x - cell;
+    let a = lattice_gradient(cell as i32) * t;
+    let b = lattice_gradient(cell as i32 + 1) * (t - 1.0);
+
+    // Blend with the quintic fade curve, so the noise is smooth across lattice points
+    let fade = t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+
+    // 1D gradient noise only reaches half of the gradients' range
+    (a + (b - a) * fade) * 2.0
+}
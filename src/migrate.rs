@@ -0,0 +1,75 @@
+use std::path::PathBuf;
+
+use log::warn;
+use serde_json::Value;
+
+use crate::error::Error;
+
+/// The world format version written by this build
+pub const CURRENT_VERSION: u64 = 3;
+
+/// A single step that upgrades a world from version `n` to `n + 1`
+type Migration = fn(Value) -> Result<Value, Error>;
+
+/// Migration steps, indexed by the version they upgrade from (starting at 1)
+const MIGRATIONS: &[Migration] = &[v1_to_v2, v2_to_v3];
+
+/// Version 1 worlds predate the `version` field, but are otherwise identical
+fn v1_to_v2(value: Value) -> Result<Value, Error> {
+    Ok(value)
+}
+
+/// Version 3 replaced the single `light` with a list of `lights`
+fn v2_to_v3(mut value: Value) -> Result<Value, Error> {
+    if let Some(object) = value.as_object_mut() {
+        if let Some(light) = object.remove("light") {
+            object.insert("lights".to_string(), Value::Array(vec![light]));
+        }
+    }
+    Ok(value)
+}
+
+/// Upgrade a raw world document to the current format version
+pub fn migrate(mut value: Value, path: &str) -> Result<Value, Error> {
+    // Worlds without a version field are version 1
+    let version = match value.get("version") {
+        Some(version) => match version.as_u64() {
+            Some(version) if version >= 1 => version,
+            _ => {
+                return Err(Error::Version {
+                    path: PathBuf::from(path),
+                    message: format!("invalid world version: {}", version),
+                })
+            }
+        },
+        None => 1,
+    };
+
+    if version > CURRENT_VERSION {
+        return Err(Error::Version {
+            path: PathBuf::from(path),
+            message: format!(
+                "world version {} is newer than the supported version {}",
+                version, CURRENT_VERSION
+            ),
+        });
+    }
+
+    if version < CURRENT_VERSION {
+        warn!(
+            "{} uses world version {}, migrating to version {}",
+            path, version, CURRENT_VERSION
+        );
+
+        for migration in &MIGRATIONS[(version - 1) as usize..] {
+            value = migration(value)?;
+        }
+    }
+
+    // Stamp the new version
+    if let Some(object) = value.as_object_mut() {
+        object.insert("version".to_string(), Value::from(CURRENT_VERSION));
+    }
+
+    Ok(value)
+}
@@ -0,0 +1,153 @@
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use glasscast::sensor::Reading;
+use glasscast::{ColorSpec, Vector2, World};
+use log::{debug, info, warn};
+use rumqttc::{Client, Event, MqttOptions, Packet, QoS};
+
+use crate::errors::AppError;
+
+/// How long to wait before reconnecting after the broker drops the connection
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// A change to a light received from the broker
+#[derive(Debug)]
+enum LightMessage {
+    Color(ColorSpec),
+    Intensity(f32),
+    Position(Vector2),
+    Enabled(bool),
+}
+
+/// Mirrors light state from an MQTT broker, so real smart-home lights can be shown in a
+/// virtual floor plan.
+///
+/// Lights are driven by `<prefix>/light/<index>/<property>` topics, where the property is
+/// `color` (any color the world file accepts), `intensity`, `position` (normalized, as JSON),
+/// or `enabled`. Sensor readings can be published to `<prefix>/sensor/<name>` as each light is
+/// traced.
+pub struct MqttBridge {
+    client: Client,
+    prefix: String,
+    messages: Receiver<(usize, LightMessage)>,
+}
+
+impl MqttBridge {
+    /// Connect to a broker at `host[:port]` and subscribe to the light topics under `prefix`
+    pub fn connect(broker: &str, prefix: &str) -> Result<Self, AppError> {
+        let (host, port) = match broker.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse()
+                    .map_err(|_| AppError::Mqtt(format!("invalid port in {}", broker)))?,
+            ),
+            None => (broker, 1883),
+        };
+        let options = MqttOptions::new("glasscast", host, port);
+        let (mut client, mut connection) = Client::new(options, 64);
+        client
+            .subscribe(format!("{}/light/+/+", prefix), QoS::AtLeastOnce)
+            .map_err(|e| AppError::Mqtt(e.to_string()))?;
+        info!(
+            "Mirroring lights from MQTT broker {} under {}/",
+            broker, prefix
+        );
+
+        let (sender, messages) = channel();
+        let light_topic = format!("{}/light/", prefix);
+        thread::spawn(move || {
+            for event in connection.iter() {
+                let publish = match event {
+                    Ok(Event::Incoming(Packet::Publish(publish))) => publish,
+                    Ok(_) => continue,
+                    Err(error) => {
+                        warn!("MQTT connection error: {}", error);
+                        thread::sleep(RECONNECT_DELAY);
+                        continue;
+                    }
+                };
+                let payload = String::from_utf8_lossy(&publish.payload);
+                let message = publish
+                    .topic
+                    .strip_prefix(&light_topic)
+                    .and_then(|topic| parse_message(topic, payload.trim()));
+                match message {
+                    Some(message) => {
+                        if sender.send(message).is_err() {
+                            break;
+                        }
+                    }
+                    None => warn!("Ignoring MQTT message on {}: {}", publish.topic, payload),
+                }
+            }
+        });
+
+        Ok(Self {
+            client,
+            prefix: prefix.to_string(),
+            messages,
+        })
+    }
+
+    /// Apply every light change received since the last frame
+    pub fn update(&self, world: &mut World) {
+        for (index, message) in self.messages.try_iter() {
+            debug!("MQTT light {}: {:?}", index, message);
+            let light = match world.lights.get_mut(index) {
+                Some(light) => light,
+                None => {
+                    warn!("MQTT message for light {}, which does not exist", index);
+                    continue;
+                }
+            };
+            match message {
+                LightMessage::Color(color) => light.set_color(color),
+                LightMessage::Intensity(intensity) => light.intensity = intensity.max(0.0),
+                LightMessage::Position(position) => light.position = position,
+                LightMessage::Enabled(enabled) => light.enabled = enabled,
+            }
+        }
+    }
+
+    /// Publish the sensor readings of a light's trace, each as JSON on its sensor's topic
+    pub fn publish(&mut self, light: usize, readings: &[Reading]) {
+        for reading in readings {
+            let payload = serde_json::json!({
+                "light": light,
+                "red": reading.energy[0],
+                "green": reading.energy[1],
+                "blue": reading.energy[2],
+                "luminance": reading.luminance(),
+                "samples": reading.samples,
+            });
+            let topic = format!("{}/sensor/{}", self.prefix, reading.sensor);
+            if let Err(error) =
+                self.client
+                    .try_publish(topic, QoS::AtMostOnce, false, payload.to_string())
+            {
+                warn!("Failed to publish sensor reading: {}", error);
+            }
+        }
+    }
+}
+
+/// Parse a message from the part of its topic after `<prefix>/light/`
+fn parse_message(topic: &str, payload: &str) -> Option<(usize, LightMessage)> {
+    let (index, property) = topic.split_once('/')?;
+    let index = index.parse().ok()?;
+    let message = match property {
+        // Colors can be sent bare, like `#ff8000` or `red`, as well as quoted JSON
+        "color" => LightMessage::Color(
+            serde_json::from_str(payload)
+                .or_else(|_| serde_json::from_value(payload.into()))
+                .ok()?,
+        ),
+        "intensity" => LightMessage::Intensity(payload.parse().ok()?),
+        "position" => LightMessage::Position(serde_json::from_str(payload).ok()?),
+        "enabled" => LightMessage::Enabled(payload.parse().ok()?),
+        _ => return None,
+    };
+    Some((index, message))
+}
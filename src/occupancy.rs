@@ -0,0 +1,128 @@
+use crate::batch::WallBatch;
+use crate::math::Vector2;
+use crate::trace::find_intersect;
+use crate::world::Wall;
+
+/// Marks a texel that no wall covers
+const EMPTY: u32 = u32::MAX;
+
+/// Every wall rasterized into a grid of texels, so finding the wall under a ray sample is a
+/// single lookup rather than a distance test against every wall.
+///
+/// Texels hold the index of the first wall covering their center, matching the order a linear
+/// search would find them in. Pixels outside the map are tested against the walls eight at a
+/// time instead.
+#[derive(Debug, Clone)]
+pub struct Occupancy {
+    /// Where the map's first texel is, in pixels
+    origin: Vector2,
+
+    width: usize,
+    height: usize,
+    walls: Vec<u32>,
+
+    /// The same walls, for pixels the map doesn't cover
+    batch: WallBatch,
+}
+
+impl Occupancy {
+    /// Rasterize walls into a map covering a window of the given size, in pixels
+    pub fn new(walls: &[Wall], window_vec: Vector2) -> Self {
+        Self::covering(walls, Vector2::zero(), window_vec)
+    }
+
+    /// Rasterize walls into a map covering only the part of a window they lie in, such as one
+    /// chunk's walls
+    pub fn around(walls: &[Wall], window_vec: Vector2) -> Self {
+        let mut low = window_vec;
+        let mut high = Vector2::zero();
+        for wall in walls.iter() {
+            low = Vector2::new(
+                low.x.min(wall.start.x).min(wall.end.x),
+                low.y.min(wall.start.y).min(wall.end.y),
+            );
+            high = Vector2::new(
+                high.x.max(wall.start.x).max(wall.end.x),
+                high.y.max(wall.start.y).max(wall.end.y),
+            );
+        }
+
+        // Walls reach a pixel past their ends
+        let origin = Vector2::new(
+            (low.x - 2.0).max(0.0).floor(),
+            (low.y - 2.0).max(0.0).floor(),
+        );
+        let end = Vector2::new(
+            (high.x + 2.0).min(window_vec.x),
+            (high.y + 2.0).min(window_vec.y),
+        );
+        Self::covering(walls, origin, end - origin)
+    }
+
+    fn covering(walls: &[Wall], origin: Vector2, size: Vector2) -> Self {
+        let width = size.x.max(0.0).ceil() as usize + 1;
+        let height = size.y.max(0.0).ceil() as usize + 1;
+        let mut map = Self {
+            origin,
+            width,
+            height,
+            walls: vec![EMPTY; width * height],
+            batch: WallBatch::new(walls),
+        };
+
+        // Later walls are drawn first, so earlier walls win where they overlap
+        for (index, wall) in walls.iter().enumerate().rev() {
+            map.rasterize(index as u32, wall);
+        }
+        map
+    }
+
+    /// Mark every texel whose center is within reach of a wall
+    fn rasterize(&mut self, index: u32, wall: &Wall) {
+        // Walk the wall in half-pixel steps, testing the texels around each step
+        let length = (wall.end - wall.start).length();
+        let steps = (length * 2.0).ceil().max(1.0) as usize;
+        for step in 0..=steps {
+            let point =
+                wall.start + (wall.end - wall.start) * (step as f32 / steps as f32) - self.origin;
+            for y in (point.y.floor() as i64 - 1)..=(point.y.floor() as i64 + 1) {
+                for x in (point.x.floor() as i64 - 1)..=(point.x.floor() as i64 + 1) {
+                    if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+                        continue;
+                    }
+                    let center = self.origin + Vector2::new(x as f32 + 0.5, y as f32 + 0.5);
+                    if find_intersect(wall, center) {
+                        self.walls[y as usize * self.width + x as usize] = index;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether a pixel is inside the map. Pixels outside it need testing against the walls
+    pub fn contains(&self, pixel: Vector2) -> bool {
+        let pixel = pixel - self.origin;
+        pixel.x >= 0.0
+            && pixel.y >= 0.0
+            && (pixel.x as usize) < self.width
+            && (pixel.y as usize) < self.height
+    }
+
+    /// The walls laid out for testing eight at a time, for pixels outside the map
+    pub fn batch(&self) -> &WallBatch {
+        &self.batch
+    }
+
+    /// The index of the wall covering a pixel, if any
+    pub fn wall_at(&self, pixel: Vector2) -> Option<usize> {
+        if !self.contains(pixel) {
+            return None;
+        }
+
+        let pixel = pixel - self.origin;
+        match self.walls[pixel.y as usize * self.width + pixel.x as usize] {
+            EMPTY => None,
+            index => Some(index as usize),
+        }
+    }
+}
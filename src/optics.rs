@@ -0,0 +1,329 @@
+//! Curved optical elements that redirect rays, rather than filtering them like walls.
+
+use serde::{Deserialize, Serialize};
+
+use crate::math::Vector2;
+
+/// How far along a step a surface must be hit to count, so a redirected ray doesn't immediately
+/// hit the surface it just left
+const EPSILON: f32 = 1e-3;
+
+/// A parabolic reflector. Rays from the focus leave parallel to the axis, and parallel rays
+/// arriving along the axis converge on the focus.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParabolicMirror {
+    /// The mirror's focus, in pixels
+    pub focus: Vector2,
+
+    /// Distance from the mirror's vertex to its focus, in pixels
+    pub focal_length: f32,
+
+    /// Width of the mirror's opening, in pixels
+    pub width: f32,
+
+    /// Direction the mirror opens toward, in degrees. Measured like line lights' angles
+    #[serde(default)]
+    pub angle: f32,
+}
+
+impl ParabolicMirror {
+    /// Unit vectors along the mirror's axis, and across it, in pixel coordinates
+    fn axes(&self) -> (Vector2, Vector2) {
+        let angle = self.angle.to_radians();
+        let axis = Vector2::new(angle.cos(), angle.sin());
+        (axis, Vector2::new(-axis.y, axis.x))
+    }
+
+    fn vertex(&self) -> Vector2 {
+        self.focus - self.axes().0 * self.focal_length
+    }
+
+    /// Convert a point to the mirror's frame, where the surface is `u = v² / 4f`
+    fn to_local(&self, point: Vector2) -> (f32, f32) {
+        let (axis, across) = self.axes();
+        let offset = point - self.vertex();
+        (offset.dot(axis), offset.dot(across))
+    }
+
+    /// Points along the mirror's surface, for drawing it
+    pub fn outline(&self, segments: usize) -> Vec<Vector2> {
+        let (axis, across) = self.axes();
+        let vertex = self.vertex();
+        (0..=segments)
+            .map(|i| {
+                let v = (i as f32 / segments as f32 - 0.5) * self.width;
+                let u = v * v / (4.0 * self.focal_length);
+                vertex + axis * u + across * v
+            })
+            .collect()
+    }
+
+    /// Find where a ray step from `start` to `end` first hits the mirror.
+    ///
+    /// Returns how far along the step the hit is (0 to 1), and the reflected direction.
+    pub fn redirect(
+        &self,
+        start: Vector2,
+        end: Vector2,
+        direction: Vector2,
+    ) -> Option<(f32, Vector2)> {
+        if self.focal_length <= 0.0 {
+            return None;
+        }
+        let f = self.focal_length;
+        let (u0, v0) = self.to_local(start);
+        let (u1, v1) = self.to_local(end);
+        let (du, dv) = (u1 - u0, v1 - v0);
+
+        // Solve (v0 + t dv)² = 4f (u0 + t du) for the first t along the step
+        let a = dv * dv;
+        let b = 2.0 * v0 * dv - 4.0 * f * du;
+        let c = v0 * v0 - 4.0 * f * u0;
+        let t = smallest_root(a, b, c, |t| (v0 + t * dv).abs() <= self.width / 2.0)?;
+
+        // The surface normal is the gradient of v² - 4fu
+        let v = v0 + t * dv;
+        let (axis, across) = self.axes();
+        let normal = (axis * (-4.0 * f) + across * (2.0 * v)).normalized();
+        Some((t, direction.reflect(normal).normalized()))
+    }
+}
+
+/// The smallest root of `at² + bt + c` within a step that also passes a check
+pub(crate) fn smallest_root(a: f32, b: f32, c: f32, valid: impl Fn(f32) -> bool) -> Option<f32> {
+    let mut roots = if a.abs() < f32::EPSILON {
+        // The quadratic term vanished, leaving a straight line
+        if b.abs() < f32::EPSILON {
+            return None;
+        }
+        vec![-c / b]
+    } else {
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let root = discriminant.sqrt();
+        vec![(-b - root) / (2.0 * a), (-b + root) / (2.0 * a)]
+    };
+    roots.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    roots
+        .into_iter()
+        .find(|t| *t > EPSILON && *t <= 1.0 && valid(*t))
+}
+
+/// One curved face of a lens, in the lens's frame
+#[derive(Debug, Clone, Copy)]
+struct Surface {
+    /// Where the surface crosses the axis
+    vertex: f32,
+
+    /// Radius of curvature, positive when the center is further along the axis than the
+    /// vertex. Zero is a flat surface
+    radius: f32,
+}
+
+impl Surface {
+    fn center(&self) -> Vector2 {
+        Vector2::new(self.vertex + self.radius, 0.0)
+    }
+
+    /// Position along the axis of the surface at a distance across it
+    fn u_at(&self, v: f32) -> f32 {
+        if self.radius == 0.0 {
+            return self.vertex;
+        }
+        let root = (self.radius * self.radius - v * v).max(0.0).sqrt();
+        self.vertex + self.radius - self.radius.signum() * root
+    }
+
+    /// The first hit along a step, as a fraction of the step, within an aperture
+    fn intersect(&self, start: Vector2, end: Vector2, aperture: f32) -> Option<f32> {
+        let step = end - start;
+        let within = |t: f32| (start.y + step.y * t).abs() <= aperture / 2.0;
+        if self.radius == 0.0 {
+            return smallest_root(0.0, step.x, start.x - self.vertex, within);
+        }
+
+        // Only the cap of the circle nearest the vertex is part of the lens
+        let center = self.center();
+        let offset = start - center;
+        let cap = |t: f32| {
+            let u = start.x + step.x * t;
+            within(t) && (u - center.x) * self.radius <= 0.0
+        };
+        smallest_root(
+            step.dot(step),
+            2.0 * step.dot(offset),
+            offset.dot(offset) - self.radius * self.radius,
+            cap,
+        )
+    }
+
+    /// The surface normal at a point on it
+    fn normal(&self, point: Vector2) -> Vector2 {
+        if self.radius == 0.0 {
+            return Vector2::new(1.0, 0.0);
+        }
+        (point - self.center()).normalized()
+    }
+}
+
+/// A glass lens with two curved faces, which refracts rays as they enter and leave it.
+///
+/// Lenses are described either by a focal length, giving a symmetric lens that is convex when
+/// positive and concave when negative, or by the radii of their two faces.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lens {
+    /// Center of the lens, in pixels
+    pub center: Vector2,
+
+    /// Height of the lens across its axis, in pixels
+    pub aperture: f32,
+
+    /// Direction of the lens's axis, in degrees. Measured like line lights' angles
+    #[serde(default)]
+    pub angle: f32,
+
+    /// Focal length in pixels, used when `radii` isn't set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub focal_length: Option<f32>,
+
+    /// Radii of curvature of the first and second faces, in pixels. Positive radii curve away
+    /// from the direction of the axis, and zero is a flat face
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub radii: Option<[f32; 2]>,
+
+    /// Index of refraction of the glass
+    #[serde(default = "default_refractive_index")]
+    pub refractive_index: f32,
+}
+
+fn default_refractive_index() -> f32 {
+    1.5
+}
+
+/// Thickness of a lens's thinnest point, in pixels
+const MIN_THICKNESS: f32 = 2.0;
+
+impl Lens {
+    /// Unit vectors along the lens's axis, and across it, in pixel coordinates
+    fn axes(&self) -> (Vector2, Vector2) {
+        let angle = self.angle.to_radians();
+        let axis = Vector2::new(angle.cos(), angle.sin());
+        (axis, Vector2::new(-axis.y, axis.x))
+    }
+
+    fn to_local(&self, point: Vector2) -> Vector2 {
+        let (axis, across) = self.axes();
+        let offset = point - self.center;
+        Vector2::new(offset.dot(axis), offset.dot(across))
+    }
+
+    fn to_world(&self, point: Vector2) -> Vector2 {
+        let (axis, across) = self.axes();
+        self.center + axis * point.x + across * point.y
+    }
+
+    /// The lens's faces, positioned so the lens is just thick enough to hold its curves
+    fn surfaces(&self) -> (Surface, Surface) {
+        let [first, second] = self.radii.unwrap_or_else(|| {
+            // A symmetric thin lens has 1/f = (n - 1) * 2/R
+            let radius = match self.focal_length {
+                Some(f) if f != 0.0 => 2.0 * f * (self.refractive_index - 1.0),
+                _ => 0.0,
+            };
+            [radius, -radius]
+        });
+
+        // Each convex face bulges out by its sag at the edge of the aperture
+        let half = self.aperture / 2.0;
+        let sag = |radius: f32| {
+            let radius = radius.abs().max(half);
+            radius - (radius * radius - half * half).sqrt()
+        };
+        let mut thickness = MIN_THICKNESS;
+        if first > 0.0 {
+            thickness += sag(first);
+        }
+        if second < 0.0 {
+            thickness += sag(second);
+        }
+
+        (
+            Surface {
+                vertex: -thickness / 2.0,
+                radius: first,
+            },
+            Surface {
+                vertex: thickness / 2.0,
+                radius: second,
+            },
+        )
+    }
+
+    /// Whether a point, in the lens's frame, is inside the glass
+    fn contains(&self, point: Vector2) -> bool {
+        let (first, second) = self.surfaces();
+        point.y.abs() <= self.aperture / 2.0
+            && point.x > first.u_at(point.y)
+            && point.x < second.u_at(point.y)
+    }
+
+    /// Points around the lens's edge, for drawing it
+    pub fn outline(&self, segments: usize) -> Vec<Vector2> {
+        let (first, second) = self.surfaces();
+        let across = |i: usize| (i as f32 / segments as f32 - 0.5) * self.aperture;
+        let front = (0..=segments).map(|i| Vector2::new(first.u_at(across(i)), across(i)));
+        let back = (0..=segments)
+            .rev()
+            .map(|i| Vector2::new(second.u_at(across(i)), across(i)));
+        let mut points: Vec<_> = front.chain(back).map(|p| self.to_world(p)).collect();
+        points.push(points[0]);
+        points
+    }
+
+    /// Find where a ray step from `start` to `end` first crosses a face of the lens.
+    ///
+    /// Returns how far along the step the hit is (0 to 1), and the refracted direction. Rays
+    /// that can't leave the glass are reflected back inside it.
+    pub fn redirect(
+        &self,
+        start: Vector2,
+        end: Vector2,
+        direction: Vector2,
+    ) -> Option<(f32, Vector2)> {
+        let (local_start, local_end) = (self.to_local(start), self.to_local(end));
+        let (first, second) = self.surfaces();
+        let (t, surface) = [first, second]
+            .iter()
+            .filter_map(|surface| {
+                surface
+                    .intersect(local_start, local_end, self.aperture)
+                    .map(|t| (t, surface))
+            })
+            .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap())?;
+
+        // Refract with Snell's law, with the normal facing against the ray
+        let (axis, across) = self.axes();
+        let hit = local_start + (local_end - local_start) * t;
+        let incoming = Vector2::new(direction.dot(axis), direction.dot(across)).normalized();
+        let mut normal = surface.normal(hit);
+        if normal.dot(incoming) > 0.0 {
+            normal = normal * -1.0;
+        }
+        let ratio = if self.contains(local_start) {
+            self.refractive_index
+        } else {
+            1.0 / self.refractive_index
+        };
+        let cos_incoming = -normal.dot(incoming);
+        let k = 1.0 - ratio * ratio * (1.0 - cos_incoming * cos_incoming);
+        let outgoing = if k < 0.0 {
+            incoming.reflect(normal)
+        } else {
+            incoming * ratio + normal * (ratio * cos_incoming - k.sqrt())
+        };
+
+        Some((t, (axis * outgoing.x + across * outgoing.y).normalized()))
+    }
+}
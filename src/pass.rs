@@ -0,0 +1,48 @@
+use std::ops::{Deref, DerefMut};
+
+use log::trace;
+use raylib::prelude::*;
+
+/// Drawing into an off-screen render texture, which ends when the pass is dropped.
+///
+/// The pass derefs to the draw handle, so anything that draws to the window can draw into the
+/// texture instead. Passes are named for the log, to follow the order stages are drawn in.
+pub struct RenderPass<'a, 'b> {
+    d: &'a mut RaylibDrawHandle<'b>,
+    name: &'a str,
+}
+
+impl<'a, 'b> RenderPass<'a, 'b> {
+    /// Start drawing into a render texture. Passes can't be nested
+    pub fn begin(d: &'a mut RaylibDrawHandle<'b>, target: &RenderTexture2D, name: &'a str) -> Self {
+        trace!("Beginning render pass {}", name);
+        // Safe because the pass borrows the draw handle, so drawing only happens inside a frame
+        unsafe {
+            raylib::ffi::BeginTextureMode(**target);
+        }
+        Self { d, name }
+    }
+}
+
+impl<'a, 'b> Deref for RenderPass<'a, 'b> {
+    type Target = RaylibDrawHandle<'b>;
+
+    fn deref(&self) -> &Self::Target {
+        self.d
+    }
+}
+
+impl<'a, 'b> DerefMut for RenderPass<'a, 'b> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.d
+    }
+}
+
+impl<'a, 'b> Drop for RenderPass<'a, 'b> {
+    fn drop(&mut self) {
+        unsafe {
+            raylib::ffi::EndTextureMode();
+        }
+        trace!("Ended render pass {}", self.name);
+    }
+}
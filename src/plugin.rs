@@ -0,0 +1,170 @@
+//! Custom wall and light behaviors, added by programs using the simulation as a library.
+//!
+//! A behavior is registered under a name before any world using it is loaded. World files then
+//! pick it with a `behavior` object, whose `type` is the name and whose other fields are passed
+//! to the behavior's factory:
+//!
+//! ```json5
+//! { start: { x: 100, y: 100 }, end: { x: 100, y: 300 }, color: "#000000", behavior: { type: "grating", spacing: 4 } }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+
+use crate::color::Color;
+use crate::math::Vector2;
+use crate::world::{Light, Wall};
+
+/// Where a ray sample landed on a wall
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hit {
+    pub point: Vector2,
+
+    /// Direction the ray is travelling in
+    pub direction: Vector2,
+
+    /// How far along the wall the ray is, from 0 at its start to 1 at its end
+    pub along: f32,
+
+    /// Whether this is the ray's first sample inside the wall
+    pub entering: bool,
+}
+
+/// A custom way for a wall to affect the rays that reach it
+pub trait WallBehavior: Send + Sync {
+    /// The color of a ray sample inside the wall. Defaults to the wall's `kind`
+    fn modify(&self, wall: &Wall, ray: Color, _hit: &Hit) -> Color {
+        wall.modify(ray)
+    }
+
+    /// A new direction for a ray arriving at the wall, to bend or scatter it. Only asked once
+    /// per arrival, and counted as a bounce like mirrors and lenses
+    fn redirect(&self, _wall: &Wall, _hit: &Hit) -> Option<Vector2> {
+        None
+    }
+}
+
+/// A custom way for a light to change over time
+pub trait LightBehavior: Send + Sync {
+    /// Update a light for a point in time in seconds, after its own animation
+    fn animate(&self, light: &mut Light, time: f32);
+}
+
+type Factory<B> = Box<dyn Fn(&Value) -> Result<Arc<B>, String> + Send + Sync>;
+
+static WALL_BEHAVIORS: RwLock<Option<HashMap<String, Factory<dyn WallBehavior>>>> =
+    RwLock::new(None);
+static LIGHT_BEHAVIORS: RwLock<Option<HashMap<String, Factory<dyn LightBehavior>>>> =
+    RwLock::new(None);
+
+/// Make a wall behavior available to world files under a name. The factory is given the
+/// behavior's object from the world file, and may reject it with a message.
+///
+/// Registering a name again replaces the earlier behavior for worlds loaded afterwards.
+pub fn register_wall_behavior(
+    name: &str,
+    factory: impl Fn(&Value) -> Result<Arc<dyn WallBehavior>, String> + Send + Sync + 'static,
+) {
+    let mut behaviors = WALL_BEHAVIORS.write().unwrap();
+    behaviors
+        .get_or_insert_with(HashMap::new)
+        .insert(name.to_string(), Box::new(factory));
+}
+
+/// Make a light behavior available to world files under a name, like [`register_wall_behavior`]
+pub fn register_light_behavior(
+    name: &str,
+    factory: impl Fn(&Value) -> Result<Arc<dyn LightBehavior>, String> + Send + Sync + 'static,
+) {
+    let mut behaviors = LIGHT_BEHAVIORS.write().unwrap();
+    behaviors
+        .get_or_insert_with(HashMap::new)
+        .insert(name.to_string(), Box::new(factory));
+}
+
+/// Kinds of behavior that can be looked up by name
+pub trait Registered {
+    fn build(name: &str, params: &Value) -> Result<Arc<Self>, String>;
+}
+
+fn build<B: ?Sized>(
+    registry: &RwLock<Option<HashMap<String, Factory<B>>>>,
+    name: &str,
+    params: &Value,
+) -> Result<Arc<B>, String> {
+    let behaviors = registry.read().unwrap();
+    match behaviors.as_ref().and_then(|behaviors| behaviors.get(name)) {
+        Some(factory) => factory(params),
+        None => Err(format!(
+            "unknown behavior {} (has its plugin been registered?)",
+            name
+        )),
+    }
+}
+
+impl Registered for dyn WallBehavior {
+    fn build(name: &str, params: &Value) -> Result<Arc<Self>, String> {
+        build(&WALL_BEHAVIORS, name, params)
+    }
+}
+
+impl Registered for dyn LightBehavior {
+    fn build(name: &str, params: &Value) -> Result<Arc<Self>, String> {
+        build(&LIGHT_BEHAVIORS, name, params)
+    }
+}
+
+/// A registered behavior attached to a wall or light, along with the settings it was built
+/// from so the world can be saved again
+pub struct Plugin<B: ?Sized> {
+    pub name: String,
+    pub params: Value,
+    pub behavior: Arc<B>,
+}
+
+impl<B: ?Sized> Clone for Plugin<B> {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            params: self.params.clone(),
+            behavior: self.behavior.clone(),
+        }
+    }
+}
+
+impl<B: ?Sized> fmt::Debug for Plugin<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Plugin")
+            .field("name", &self.name)
+            .field("params", &self.params)
+            .finish()
+    }
+}
+
+impl<B: ?Sized> Serialize for Plugin<B> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.params.serialize(serializer)
+    }
+}
+
+impl<'de, B: Registered + ?Sized> Deserialize<'de> for Plugin<B> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let params = Value::deserialize(deserializer)?;
+        let name = params
+            .get("type")
+            .and_then(Value::as_str)
+            .ok_or_else(|| D::Error::custom("behaviors need a type"))?
+            .to_string();
+        let behavior = B::build(&name, &params).map_err(D::Error::custom)?;
+        Ok(Self {
+            name,
+            params,
+            behavior,
+        })
+    }
+}
@@ -0,0 +1,114 @@
+//! Picking walls and lights by name or tag, so they can be changed without knowing their indices.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::world::{Light, Wall};
+
+/// Something that can carry a name and tags
+pub trait Tagged {
+    fn name(&self) -> Option<&str>;
+    fn tags(&self) -> &[String];
+}
+
+impl Tagged for Wall {
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    fn tags(&self) -> &[String] {
+        &self.tags
+    }
+}
+
+impl Tagged for Light {
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    fn tags(&self) -> &[String] {
+        &self.tags
+    }
+}
+
+/// Which walls or lights a query picks, written `*`, `name:NAME`, or `tag:TAG`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Selector {
+    All,
+    Name(String),
+    Tag(String),
+}
+
+impl Selector {
+    pub fn matches(&self, object: &impl Tagged) -> bool {
+        match self {
+            Selector::All => true,
+            Selector::Name(name) => object.name() == Some(name.as_str()),
+            Selector::Tag(tag) => object.tags().iter().any(|t| t == tag),
+        }
+    }
+}
+
+impl FromStr for Selector {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        if text == "*" {
+            return Ok(Selector::All);
+        }
+        match text.split_once(':') {
+            Some(("name", name)) if !name.is_empty() => Ok(Selector::Name(name.to_string())),
+            Some(("tag", tag)) if !tag.is_empty() => Ok(Selector::Tag(tag.to_string())),
+            _ => Err(format!(
+                "Invalid selector {} (expected *, name:NAME, or tag:TAG)",
+                text
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Selector::All => write!(f, "*"),
+            Selector::Name(name) => write!(f, "name:{}", name),
+            Selector::Tag(tag) => write!(f, "tag:{}", tag),
+        }
+    }
+}
+
+/// A copy of an object with some of its fields replaced, as they would be written in a world
+/// file. Runtime state that isn't saved comes back at its default.
+///
+/// Fields the object doesn't have are rejected, so a misspelled field isn't silently dropped.
+pub fn with_fields<T: Serialize + DeserializeOwned>(
+    object: &T,
+    fields: &Map<String, Value>,
+) -> Result<T, String> {
+    let mut value = serde_json::to_value(object).map_err(|e| e.to_string())?;
+    let map = value
+        .as_object_mut()
+        .ok_or_else(|| "only objects have fields".to_string())?;
+    for (field, new) in fields.iter() {
+        map.insert(field.clone(), new.clone());
+    }
+
+    // Only the fields being set are checked, as saved objects may carry fields that loading skips
+    let mut unknown = None;
+    let updated = serde_ignored::deserialize(value, |path| {
+        let path = path.to_string();
+        let field = path.split('.').next().unwrap_or_default();
+        if unknown.is_none() && fields.contains_key(field) {
+            unknown = Some(path);
+        }
+    })
+    .map_err(|e| e.to_string())?;
+    match unknown {
+        Some(path) => Err(format!("unknown field {}", path)),
+        None => Ok(updated),
+    }
+}
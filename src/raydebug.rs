@@ -0,0 +1,214 @@
+use glasscast::raypath::{RayEvent, RayPath};
+use glasscast::{Vector2, World};
+use log::{debug, info};
+use raylib::prelude::*;
+
+use crate::editor::{draw_color, draw_vector};
+
+/// Casts single rays toward the cursor and draws their paths, toggled with R.
+///
+/// Each event along the path is labelled with the ray's color after it, which shows exactly
+/// where a ray is reflected, refracted, or tinted. The arrow keys step through the ray one
+/// sample at a time (or one event at a time with Shift), printing each step to stdout.
+#[derive(Default)]
+pub struct RayDebugger {
+    pub active: bool,
+
+    /// The most recently cast ray
+    path: Option<RayPath>,
+
+    /// The step of the path being inspected
+    step: usize,
+}
+
+impl RayDebugger {
+    /// Handle debugger input, casting a ray from a light toward the cursor on click
+    pub fn update(
+        &mut self,
+        d: &RaylibDrawHandle,
+        world: &World,
+        light: usize,
+        window_vec: &Vector2,
+    ) {
+        if d.is_key_pressed(KeyboardKey::KEY_R) {
+            self.active = !self.active;
+            self.path = None;
+            debug!("Ray debugger: {}", self.active);
+        }
+        if !self.active {
+            return;
+        }
+        self.handle_stepping(d);
+        if !d.is_mouse_button_pressed(MouseButton::MOUSE_LEFT_BUTTON) {
+            return;
+        }
+
+        let light = match world.lights.get(light) {
+            Some(light) => light,
+            None => return,
+        };
+        let center = light.position * *window_vec;
+        let mouse = d.get_mouse_position();
+        let direction = (Vector2::new(mouse.x, mouse.y) - center).normalized();
+        if direction.length() == 0.0 {
+            return;
+        }
+
+        let color = glasscast::Color {
+            a: 255,
+            ..light.emitted_color()
+        };
+        let path = RayPath::trace(
+            world,
+            center + direction * light.radius,
+            direction,
+            window_vec,
+            color,
+        );
+        debug!(
+            "Cast a ray toward ({}, {}): {} steps, {} events",
+            mouse.x,
+            mouse.y,
+            path.steps.len(),
+            path.events().count()
+        );
+        self.path = Some(path);
+        self.step = 0;
+        self.print_step();
+    }
+
+    /// Move through the path with the arrow keys, Home, and End
+    fn handle_stepping(&mut self, d: &RaylibDrawHandle) {
+        let path = match &self.path {
+            Some(path) if !path.steps.is_empty() => path,
+            _ => return,
+        };
+        let last = path.steps.len() - 1;
+        let by_event = d.is_key_down(KeyboardKey::KEY_LEFT_SHIFT)
+            || d.is_key_down(KeyboardKey::KEY_RIGHT_SHIFT);
+        let is_event = |index: &usize| path.steps[*index].event != RayEvent::March;
+
+        let step = if d.is_key_pressed(KeyboardKey::KEY_RIGHT) && by_event {
+            (self.step + 1..=last).find(is_event).unwrap_or(last)
+        } else if d.is_key_pressed(KeyboardKey::KEY_RIGHT) {
+            (self.step + 1).min(last)
+        } else if d.is_key_pressed(KeyboardKey::KEY_LEFT) && by_event {
+            (0..self.step).rev().find(is_event).unwrap_or(0)
+        } else if d.is_key_pressed(KeyboardKey::KEY_LEFT) {
+            self.step.saturating_sub(1)
+        } else if d.is_key_pressed(KeyboardKey::KEY_HOME) {
+            0
+        } else if d.is_key_pressed(KeyboardKey::KEY_END) {
+            last
+        } else {
+            return;
+        };
+
+        if step != self.step {
+            self.step = step;
+            self.print_step();
+        }
+    }
+
+    /// Describe the step being inspected
+    fn describe_step(&self) -> Option<String> {
+        let path = self.path.as_ref()?;
+        let step = path.steps.get(self.step)?;
+        Some(format!(
+            "Step {} of {}: {:?} at ({:.1}, {:.1}), {:.1} px along, color ({}, {}, {})",
+            self.step + 1,
+            path.steps.len(),
+            step.event,
+            step.position.x,
+            step.position.y,
+            step.distance,
+            step.color.r,
+            step.color.g,
+            step.color.b
+        ))
+    }
+
+    fn print_step(&self) {
+        if let Some(description) = self.describe_step() {
+            info!("{}", description);
+        }
+    }
+
+    /// Draw the last ray's path and its events, and the debugger's status
+    pub fn draw(&self, d: &mut RaylibDrawHandle) {
+        if !self.active {
+            return;
+        }
+
+        if let Some(path) = &self.path {
+            // The path up to the inspected step is drawn brighter than the rest
+            for (index, pair) in path.steps.windows(2).enumerate() {
+                let alpha = if index < self.step { 220 } else { 80 };
+                d.draw_line_v(
+                    draw_vector(pair[0].position),
+                    draw_vector(pair[1].position),
+                    Color::new(255, 255, 255, alpha),
+                );
+            }
+            if let Some(step) = path.steps.get(self.step) {
+                d.draw_circle_lines(
+                    step.position.x as i32,
+                    step.position.y as i32,
+                    9.0,
+                    Color::SKYBLUE,
+                );
+            }
+
+            // Label every event, and where the ray left the window
+            let last = path
+                .steps
+                .last()
+                .filter(|step| step.event == RayEvent::March);
+            for step in path.events().chain(last) {
+                let label = match step.event {
+                    RayEvent::Start => "start".to_string(),
+                    RayEvent::Wall(index) => format!("wall {}", index),
+                    RayEvent::Redirect => "redirect".to_string(),
+                    RayEvent::March => "exit".to_string(),
+                };
+                let color = step.color;
+                d.draw_circle_v(draw_vector(step.position), 5.0, draw_color(color));
+                d.draw_circle_lines(
+                    step.position.x as i32,
+                    step.position.y as i32,
+                    5.0,
+                    Color::YELLOW,
+                );
+                d.draw_text(
+                    &format!(
+                        "{}: ({}, {}, {}) at {:.0} px",
+                        label, color.r, color.g, color.b, step.distance
+                    ),
+                    step.position.x as i32 + 8,
+                    step.position.y as i32 + 8,
+                    10,
+                    Color::YELLOW,
+                );
+            }
+        }
+    }
+
+    /// Draw the debugger's status and the inspected step
+    pub fn draw_status(&self, d: &mut RaylibDrawHandle) {
+        if !self.active {
+            return;
+        }
+
+        let y = d.get_screen_height() - 50;
+        if let Some(description) = self.describe_step() {
+            d.draw_text(&description, 5, y - 25, 20, Color::SKYBLUE);
+        }
+        d.draw_text(
+            "RAY DEBUG: click to cast a ray, arrows to step, Shift to skip to events (R: exit)",
+            5,
+            y,
+            20,
+            Color::WHITE,
+        );
+    }
+}
@@ -0,0 +1,100 @@
+//! Recording a single ray's path, for debugging the tracer.
+
+use crate::color::Color;
+use crate::math::Vector2;
+use crate::trace::{trace_and_plot, RaySink};
+use crate::world::World;
+
+/// What happened to a ray at a step
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RayEvent {
+    /// The first sample of the ray
+    Start,
+
+    /// An ordinary step along the ray
+    March,
+
+    /// The ray arrived at the wall with this index
+    Wall(usize),
+
+    /// A mirror or lens turned the ray
+    Redirect,
+}
+
+/// One sample along a ray
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayStep {
+    pub position: Vector2,
+
+    /// Distance travelled along the ray to reach this sample, in pixels
+    pub distance: f32,
+
+    /// The ray's color after this sample
+    pub color: Color,
+
+    pub event: RayEvent,
+}
+
+/// Every sample of a single traced ray, in order
+#[derive(Debug, Clone, Default)]
+pub struct RayPath {
+    pub steps: Vec<RayStep>,
+
+    /// The event to attach to the next sample
+    pending: Option<RayEvent>,
+}
+
+impl RayPath {
+    /// Trace one ray through a world, recording every step it takes
+    pub fn trace(
+        world: &World,
+        origin: Vector2,
+        direction: Vector2,
+        window_vec: &Vector2,
+        color: Color,
+    ) -> Self {
+        let mut path = Self {
+            steps: Vec::new(),
+            pending: Some(RayEvent::Start),
+        };
+        trace_and_plot(
+            &origin,
+            direction.normalized(),
+            window_vec,
+            color,
+            world,
+            &mut path,
+        );
+        path
+    }
+
+    /// Only the steps where something happened to the ray
+    pub fn events(&self) -> impl Iterator<Item = &RayStep> {
+        self.steps
+            .iter()
+            .filter(|step| step.event != RayEvent::March)
+    }
+}
+
+impl RaySink for RayPath {
+    fn deposit(&mut self, pixel: Vector2, color: Color) {
+        let distance = match self.steps.last() {
+            Some(last) => last.distance + (pixel - last.position).length(),
+            None => 0.0,
+        };
+        self.steps.push(RayStep {
+            position: pixel,
+            distance,
+            color,
+            event: self.pending.take().unwrap_or(RayEvent::March),
+        });
+    }
+
+    fn hit_wall(&mut self, wall: usize, _along: f32, _color: Color, _cosine: f32) {
+        self.pending = Some(RayEvent::Wall(wall));
+    }
+
+    fn redirect(&mut self, _point: Vector2, _direction: Vector2) {
+        self.pending = Some(RayEvent::Redirect);
+    }
+}
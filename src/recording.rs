@@ -0,0 +1,70 @@
+use std::fs::{read_to_string, write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::world::{Keyframe, World};
+
+/// Light positions captured over time, so an interactive session can be replayed exactly.
+///
+/// Recordings replay through the lights' keyframes, so they play back like any other animation,
+/// including when exporting frames.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Recording {
+    /// The path of each light, by index
+    pub lights: Vec<Vec<Keyframe>>,
+}
+
+impl Recording {
+    /// Capture the position of every light at a point in time.
+    ///
+    /// A time at or before earlier samples, such as after scrubbing back through the timeline,
+    /// discards those samples, so the recording is always in order of time.
+    pub fn capture(&mut self, time: f32, world: &World) {
+        self.lights.resize_with(world.lights.len(), Vec::new);
+        for (light, path) in world.lights.iter().zip(self.lights.iter_mut()) {
+            let kept = path.partition_point(|sample| sample.time < time);
+            path.truncate(kept);
+            path.push(Keyframe {
+                time,
+                position: light.position,
+            });
+        }
+    }
+
+    /// Replace the animation of each recorded light with its recorded path
+    pub fn apply(&self, world: &mut World) {
+        for (light, path) in world.lights.iter_mut().zip(self.lights.iter()) {
+            if !path.is_empty() {
+                light.keyframes = path.clone();
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.lights.clear();
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let text = read_to_string(path).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        serde_json::from_str(&text).map_err(|e| Error::Parse {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        let io_error = |source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        };
+        let text = serde_json::to_string(self).map_err(|e| io_error(e.into()))?;
+        write(path, text).map_err(io_error)
+    }
+}
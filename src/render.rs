@@ -0,0 +1,508 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use glasscast::accumulate::Accumulator;
+use glasscast::framebuffer::Framebuffer;
+use glasscast::layer::{blend_layer, fill_background};
+use glasscast::sensor::{Reading, SensorProbe};
+use glasscast::shading::WallShading;
+use glasscast::trace::{
+    trace_light, trace_light_jittered, trace_light_stochastic, Tee, TraceStats,
+};
+use glasscast::world::{LayerSettings, Light};
+use glasscast::World;
+use log::debug;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use raylib::prelude::*;
+
+use crate::pass::RenderPass;
+use crate::shaders::blit;
+
+/// Seed for jittered rendering, so the same light position always renders identically
+const JITTER_SEED: u64 = 0x6c61_7373_6361_7374;
+
+/// How the light is traced into the offscreen texture
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RenderMode {
+    /// Rays are drawn directly, later rays overwriting earlier ones
+    Direct,
+
+    /// Rays add their energy into an HDR buffer
+    Accumulate,
+
+    /// Random rays add their energy into an HDR buffer every frame until the light changes
+    Progressive,
+
+    /// Jittered traces are averaged in an HDR buffer, one pass per frame
+    Jittered { passes: usize },
+}
+
+/// CPU-side buffer that rays are traced into
+enum TraceBuffer {
+    Direct(Framebuffer),
+    Additive(Accumulator),
+}
+
+/// CPU-side tracing state, owned by the tracing thread
+struct Tracer {
+    buffer: TraceBuffer,
+    mode: RenderMode,
+    rng: StdRng,
+
+    /// Number of traces accumulated since the last full render
+    passes: usize,
+
+    /// Work done by the most recent pass
+    stats: TraceStats,
+
+    /// Light that reached the world's sensors in the most recent pass
+    readings: Vec<Reading>,
+
+    /// Light that fell on the world's walls, over every pass since the last full render
+    shading: WallShading,
+}
+
+impl Tracer {
+    fn new(width: usize, height: usize, mode: RenderMode) -> Self {
+        let buffer = match mode {
+            RenderMode::Direct => TraceBuffer::Direct(Framebuffer::new(width, height)),
+            _ => TraceBuffer::Additive(Accumulator::new(width, height)),
+        };
+
+        Self {
+            buffer,
+            mode,
+            rng: StdRng::from_entropy(),
+            passes: 0,
+            stats: TraceStats::default(),
+            readings: Vec::new(),
+            shading: WallShading::default(),
+        }
+    }
+
+    /// Trace every ray from a light into a fresh buffer
+    fn render(&mut self, world: &World, light: &Light, window_vec: &glasscast::Vector2) -> Vec<u8> {
+        // Jittered renders always start from the same seed
+        if let RenderMode::Jittered { .. } = self.mode {
+            self.rng = StdRng::seed_from_u64(JITTER_SEED);
+        }
+
+        match &mut self.buffer {
+            TraceBuffer::Direct(buffer) => buffer.clear(glasscast::Color::TRANSPARENT),
+            TraceBuffer::Additive(buffer) => buffer.clear(),
+        }
+        self.passes = 0;
+        self.shading = WallShading::new(world);
+
+        self.trace(world, light, window_vec);
+        self.pixels()
+    }
+
+    /// Add more samples to the previous trace, if the render mode refines over multiple frames
+    fn refine(
+        &mut self,
+        world: &World,
+        light: &Light,
+        window_vec: &glasscast::Vector2,
+    ) -> Option<Vec<u8>> {
+        match self.mode {
+            RenderMode::Progressive => {}
+            RenderMode::Jittered { passes } if self.passes < passes => {}
+            _ => return None,
+        }
+
+        self.trace(world, light, window_vec);
+        Some(self.pixels())
+    }
+
+    /// Trace one pass into the buffer
+    fn trace(&mut self, world: &World, light: &Light, window_vec: &glasscast::Vector2) {
+        let (stats, readings) = match &mut self.buffer {
+            TraceBuffer::Direct(buffer) => {
+                let mut tee = Tee(buffer, &mut self.shading);
+                let mut probe = SensorProbe::new(&mut tee, &world.sensors);
+                let stats = trace_light(world, light, window_vec, &mut probe);
+                (stats, probe.into_readings())
+            }
+            TraceBuffer::Additive(buffer) => {
+                let mut tee = Tee(buffer, &mut self.shading);
+                let mut probe = SensorProbe::new(&mut tee, &world.sensors);
+                let stats = match self.mode {
+                    RenderMode::Progressive => {
+                        trace_light_stochastic(world, light, window_vec, &mut self.rng, &mut probe)
+                    }
+                    RenderMode::Jittered { .. } => {
+                        trace_light_jittered(world, light, window_vec, &mut self.rng, &mut probe)
+                    }
+                    _ => trace_light(world, light, window_vec, &mut probe),
+                };
+                (stats, probe.into_readings())
+            }
+        };
+        self.stats = stats;
+        self.readings = readings;
+        self.passes += 1;
+    }
+
+    /// The buffer as RGBA8 pixels
+    fn pixels(&self) -> Vec<u8> {
+        match &self.buffer {
+            TraceBuffer::Direct(buffer) => buffer.to_rgba(),
+            TraceBuffer::Additive(buffer) => buffer.tone_map(),
+        }
+    }
+}
+
+/// Work sent to the tracing thread, for the light at an index
+enum Job {
+    Render(usize, Arc<World>, glasscast::Vector2),
+    Refine(usize, Arc<World>, glasscast::Vector2),
+}
+
+/// A finished job, sent back from the tracing thread
+struct TraceResult {
+    light: usize,
+
+    /// The traced pixels, or `None` if refining has converged
+    pixels: Option<Vec<u8>>,
+
+    stats: TraceStats,
+    readings: Vec<Reading>,
+
+    /// The light on the walls, sent along with new pixels
+    shading: Option<WallShading>,
+
+    duration: Duration,
+}
+
+/// Statistics of the most recent trace and composite, for profiling
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RenderStats {
+    pub trace: TraceStats,
+    pub trace_time: Duration,
+
+    /// Time spent blending layers and uploading them to the GPU
+    pub composite_time: Duration,
+}
+
+/// The traced pixels of a single light
+#[derive(Default)]
+struct Layer {
+    /// The most recently completed trace
+    pixels: Option<Vec<u8>>,
+
+    /// The light on the walls in the most recently completed trace
+    shading: Option<WallShading>,
+
+    /// The latest world waiting to be traced, shared with the other lights it changed for
+    pending: Option<(Arc<World>, glasscast::Vector2)>,
+
+    /// The world of the last trace, kept for refining
+    current: Option<(Arc<World>, glasscast::Vector2)>,
+
+    /// Set once refining stops producing new passes
+    converged: bool,
+}
+
+/// Traces each of the world's lights into its own layer on a background thread, and composites
+/// the layers to the screen.
+///
+/// Layers are blended on the CPU, so toggling or fading a light never needs a re-trace.
+/// The composite lands in a back buffer, which is swapped with the displayed front buffer once
+/// complete, so slow traces never stall presenting.
+pub struct LightRenderer {
+    /// The most recently composited frame
+    pub surface: RenderTexture2D,
+
+    /// Composited frames are drawn here before being swapped to the front
+    back: RenderTexture2D,
+
+    /// Composited pixels are uploaded here before being drawn into the back buffer
+    texture: Texture2D,
+
+    jobs: Sender<Job>,
+    results: Receiver<TraceResult>,
+
+    pub stats: RenderStats,
+
+    /// Sensor readings of each finished trace pass, by light, waiting to be collected
+    pub readings: Vec<(usize, Vec<Reading>)>,
+
+    /// Whether a job is in flight
+    busy: bool,
+
+    layers: Vec<Layer>,
+
+    /// Whether each light was enabled, and its compositing settings, at the last composite
+    settings: Vec<(bool, LayerSettings)>,
+
+    /// Set when a layer or its settings changed since the last composite
+    dirty: bool,
+
+    /// The next layer to refine, so refining is shared between lights
+    next_refine: usize,
+
+    mode: RenderMode,
+
+    width: usize,
+    height: usize,
+}
+
+impl LightRenderer {
+    pub fn new(
+        rl: &mut RaylibHandle,
+        thread: &RaylibThread,
+        width: i32,
+        height: i32,
+        mode: RenderMode,
+    ) -> Result<Self, String> {
+        let surface = rl.load_render_texture(thread, width as u32, height as u32)?;
+        let back = rl.load_render_texture(thread, width as u32, height as u32)?;
+        let image = Image::gen_image_color(width, height, Color::BLACK);
+        let texture = rl.load_texture_from_image(thread, &image)?;
+
+        // Start the tracing thread, with one tracer per light. It exits when the renderer is dropped
+        let (jobs, job_receiver) = channel();
+        let (result_sender, results) = channel();
+        thread::spawn(move || {
+            let mut tracers = Vec::new();
+            for job in job_receiver {
+                let index = match &job {
+                    Job::Render(index, ..) | Job::Refine(index, ..) => *index,
+                };
+                while tracers.len() <= index {
+                    tracers.push(Tracer::new(width as usize, height as usize, mode));
+                }
+
+                let tracer = &mut tracers[index];
+                let start = Instant::now();
+                let pixels = match job {
+                    Job::Render(index, world, window_vec) => {
+                        Some(tracer.render(&world, &world.lights[index], &window_vec))
+                    }
+                    Job::Refine(index, world, window_vec) => {
+                        tracer.refine(&world, &world.lights[index], &window_vec)
+                    }
+                };
+                let shading = pixels.as_ref().map(|_| tracer.shading.clone());
+                let result = TraceResult {
+                    light: index,
+                    pixels,
+                    stats: tracer.stats,
+                    readings: tracer.readings.clone(),
+                    shading,
+                    duration: start.elapsed(),
+                };
+                if result.pixels.is_some() {
+                    debug!(
+                        "Traced light {} in {:.2?} ({} rays, {} steps)",
+                        index, result.duration, result.stats.rays, result.stats.steps
+                    );
+                }
+                if result_sender.send(result).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Ok(Self {
+            surface,
+            back,
+            texture,
+            jobs,
+            results,
+            stats: RenderStats::default(),
+            readings: Vec::new(),
+            busy: false,
+            layers: Vec::new(),
+            settings: Vec::new(),
+            dirty: false,
+            next_refine: 0,
+            mode,
+            width: width as usize,
+            height: height as usize,
+        })
+    }
+
+    /// Request a new trace of one light. Only the latest request is traced if several arrive while busy.
+    ///
+    /// The world's walls should already be rasterized, as they are shared by every trace of it
+    pub fn request(&mut self, light: usize, world: &Arc<World>, window_vec: &glasscast::Vector2) {
+        self.layers.resize_with(world.lights.len(), Layer::default);
+        self.layers[light].pending = Some((world.clone(), *window_vec));
+    }
+
+    /// Pick up each light's enabled state and compositing settings, re-compositing if they changed
+    pub fn set_layers(&mut self, world: &World) {
+        let settings: Vec<_> = world
+            .lights
+            .iter()
+            .map(|light| (light.enabled, light.layer))
+            .collect();
+        if settings != self.settings {
+            self.settings = settings;
+            self.dirty = true;
+        }
+    }
+
+    /// Present finished traces and start new ones. Called once per frame.
+    ///
+    /// When `refine` is set and nothing else is waiting, progressive modes keep adding samples.
+    pub fn update(&mut self, d: &mut RaylibDrawHandle, refine: bool) {
+        // Collect a finished trace
+        if self.busy {
+            if let Ok(result) = self.results.try_recv() {
+                self.stats.trace = result.stats;
+                self.stats.trace_time = result.duration;
+                if let Some(layer) = self.layers.get_mut(result.light) {
+                    match result.pixels {
+                        Some(pixels) => {
+                            layer.pixels = Some(pixels);
+                            layer.shading = result.shading;
+                            self.dirty = true;
+                            if !result.readings.is_empty() {
+                                self.readings.push((result.light, result.readings));
+                            }
+                        }
+                        None => layer.converged = true,
+                    }
+                }
+                self.busy = false;
+            }
+        }
+        if self.dirty {
+            self.present(d);
+        }
+        if self.busy {
+            return;
+        }
+
+        // Start the next trace, preferring lights that changed over refining
+        if let Some(index) = self.layers.iter().position(|layer| layer.pending.is_some()) {
+            let layer = &mut self.layers[index];
+            let (world, window_vec) = layer.pending.take().unwrap();
+            layer.current = Some((world.clone(), window_vec));
+            layer.converged = false;
+            self.busy = self
+                .jobs
+                .send(Job::Render(index, world, window_vec))
+                .is_ok();
+        } else if refine {
+            let count = self.layers.len();
+            for offset in 0..count {
+                let index = (self.next_refine + offset) % count;
+                let layer = &self.layers[index];
+                if let (false, Some((world, window_vec))) = (layer.converged, &layer.current) {
+                    self.busy = self
+                        .jobs
+                        .send(Job::Refine(index, world.clone(), *window_vec))
+                        .is_ok();
+                    self.next_refine = index + 1;
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Trace every light and wait for the results, bypassing the queue
+    pub fn render_now(
+        &mut self,
+        world: &World,
+        window_vec: &glasscast::Vector2,
+        d: &mut RaylibDrawHandle,
+    ) {
+        // Let any in-flight job finish first
+        if self.busy {
+            let _ = self.results.recv();
+            self.busy = false;
+        }
+
+        let mut world = world.clone();
+        if world.occupancy.is_none() {
+            world.rasterize_walls(*window_vec);
+        }
+        let world = Arc::new(world);
+        self.layers.resize_with(world.lights.len(), Layer::default);
+        self.stats.trace = TraceStats::default();
+        self.stats.trace_time = Duration::default();
+        for (index, layer) in self.layers.iter_mut().enumerate() {
+            layer.pending = None;
+            layer.current = Some((world.clone(), *window_vec));
+            layer.converged = false;
+            if self
+                .jobs
+                .send(Job::Render(index, world.clone(), *window_vec))
+                .is_ok()
+            {
+                if let Ok(result) = self.results.recv() {
+                    self.stats.trace += result.stats;
+                    self.stats.trace_time += result.duration;
+                    layer.pixels = result.pixels;
+                    layer.shading = result.shading;
+                }
+            }
+        }
+
+        self.set_layers(&world);
+        self.present(d);
+    }
+
+    /// Draw every wall lit by the light falling on it, from the enabled lights' latest traces
+    pub fn draw_walls(&self, d: &mut RaylibDrawHandle, world: &World) {
+        let mut shading = WallShading::new(world);
+        for (layer, (enabled, _)) in self.layers.iter().zip(self.settings.iter()) {
+            if let (true, Some(layer)) = (enabled, &layer.shading) {
+                shading.add(layer);
+            }
+        }
+
+        for (wall, colors) in world.walls.iter().zip(shading.tone_map()) {
+            let step = (wall.end - wall.start) / colors.len() as f32;
+            for (index, color) in colors.iter().enumerate() {
+                let start = wall.start + step * index as f32;
+                let end = start + step;
+                d.draw_line_ex(
+                    Vector2::new(start.x, start.y),
+                    Vector2::new(end.x, end.y),
+                    3.0,
+                    Color::new(color.r, color.g, color.b, 255),
+                );
+            }
+        }
+    }
+
+    /// Blend the enabled layers together, upload them into the back buffer, and swap it to the front
+    fn present(&mut self, d: &mut RaylibDrawHandle) {
+        let start = Instant::now();
+        let mut pixels = vec![0; self.width * self.height * 4];
+        for (layer, (enabled, settings)) in self.layers.iter().zip(self.settings.iter()) {
+            if let (true, Some(layer)) = (enabled, &layer.pixels) {
+                blend_layer(&mut pixels, layer, settings);
+            }
+        }
+
+        // Direct layers only cover the pixels their rays reached, over a shared white background
+        if self.mode == RenderMode::Direct {
+            fill_background(&mut pixels, glasscast::Color::WHITE);
+        }
+        self.texture.update_texture(&pixels);
+        self.dirty = false;
+
+        {
+            let mut target = RenderPass::begin(d, &self.back, "composite");
+            target.clear_background(Color::BLACK);
+            target.draw_texture(&self.texture, 0, 0, Color::WHITE);
+        }
+
+        std::mem::swap(&mut self.surface, &mut self.back);
+        self.stats.composite_time = start.elapsed();
+    }
+
+    /// Draw the composited light texture to the current render target through the bloom shader
+    pub fn composite(&self, d: &mut RaylibDrawHandle, shader: &Shader) {
+        blit(d, shader, &self.surface);
+    }
+}
@@ -0,0 +1,151 @@
+use serde::{Deserialize, Serialize};
+
+use crate::color::Color;
+use crate::math::Vector2;
+use crate::trace::{RaySink, STEP_SIZE};
+
+/// Column names for sensor readings written as CSV
+pub const CSV_HEADER: &str = "sensor,red,green,blue,luminance,samples";
+
+/// A point or segment in the world that measures the light reaching it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sensor {
+    pub name: String,
+
+    /// Position of the sensor, in pixels
+    pub position: Vector2,
+
+    /// Other end of the sensor when it is a segment, in pixels
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<Vector2>,
+
+    /// How close a ray sample must land to be counted, in pixels
+    #[serde(default = "default_radius")]
+    pub radius: f32,
+}
+
+fn default_radius() -> f32 {
+    // Rays are sampled every step, so anything smaller can miss rays passing straight through
+    STEP_SIZE
+}
+
+impl Sensor {
+    /// Distance from a point to the sensor
+    pub fn distance_to(&self, point: Vector2) -> f32 {
+        let end = match self.end {
+            Some(end) => end,
+            None => return (point - self.position).length(),
+        };
+
+        // Project onto the segment, clamped to its ends
+        let segment = end - self.position;
+        let length_squared = segment.x * segment.x + segment.y * segment.y;
+        if length_squared == 0.0 {
+            return (point - self.position).length();
+        }
+        let offset = point - self.position;
+        let t = ((offset.x * segment.x + offset.y * segment.y) / length_squared).clamp(0.0, 1.0);
+        (point - (self.position + segment * t)).length()
+    }
+}
+
+/// The light that reached a sensor
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reading {
+    pub sensor: String,
+
+    /// Total energy per channel, where a single full-strength sample is 1.0
+    pub energy: [f32; 3],
+
+    /// Number of ray samples that reached the sensor
+    pub samples: u64,
+}
+
+impl Reading {
+    /// Perceived brightness of the total energy
+    pub fn luminance(&self) -> f32 {
+        0.2126 * self.energy[0] + 0.7152 * self.energy[1] + 0.0722 * self.energy[2]
+    }
+
+    /// The reading as a row matching `CSV_HEADER`
+    pub fn to_csv(&self) -> String {
+        format!(
+            "{},{},{},{},{},{}",
+            self.sensor,
+            self.energy[0],
+            self.energy[1],
+            self.energy[2],
+            self.luminance(),
+            self.samples
+        )
+    }
+}
+
+/// A sink that records samples landing on sensors, and passes every sample on to another sink
+pub struct SensorProbe<'a, S: RaySink> {
+    sink: &'a mut S,
+    sensors: &'a [Sensor],
+    readings: Vec<Reading>,
+}
+
+impl<'a, S: RaySink> SensorProbe<'a, S> {
+    pub fn new(sink: &'a mut S, sensors: &'a [Sensor]) -> Self {
+        Self {
+            sink,
+            sensors,
+            readings: sensors
+                .iter()
+                .map(|sensor| Reading {
+                    sensor: sensor.name.clone(),
+                    energy: [0.0; 3],
+                    samples: 0,
+                })
+                .collect(),
+        }
+    }
+
+    /// The readings of every sensor, in the order the sensors were given
+    pub fn into_readings(self) -> Vec<Reading> {
+        self.readings
+    }
+}
+
+impl<'a, S: RaySink> RaySink for SensorProbe<'a, S> {
+    fn deposit(&mut self, pixel: Vector2, color: Color) {
+        for (sensor, reading) in self.sensors.iter().zip(self.readings.iter_mut()) {
+            if sensor.distance_to(pixel) <= sensor.radius {
+                // Weighted like the accumulator, so multi-emitter lights share their energy
+                let weight = color.a as f32 / (255.0 * 255.0);
+                reading.energy[0] += color.r as f32 * weight;
+                reading.energy[1] += color.g as f32 * weight;
+                reading.energy[2] += color.b as f32 * weight;
+                reading.samples += 1;
+            }
+        }
+
+        self.sink.deposit(pixel, color);
+    }
+
+    fn hit_wall(&mut self, wall: usize, along: f32, color: Color, cosine: f32) {
+        self.sink.hit_wall(wall, along, color, cosine);
+    }
+
+    fn redirect(&mut self, point: Vector2, direction: Vector2) {
+        self.sink.redirect(point, direction);
+    }
+
+    fn ignores_black(&self) -> bool {
+        self.sink.ignores_black()
+    }
+}
+
+/// A sink that discards every sample, for when only the sensors matter
+pub struct Discard;
+
+impl RaySink for Discard {
+    fn deposit(&mut self, _pixel: Vector2, _color: Color) {}
+
+    fn ignores_black(&self) -> bool {
+        true
+    }
+}
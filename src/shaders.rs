@@ -0,0 +1,614 @@
+use glasscast::world::{Bloom, ShaderPass, UniformValue};
+use glasscast::World;
+use log::{debug, info, warn};
+use raylib::prelude::*;
+
+use crate::pass::RenderPass;
+use crate::render::LightRenderer;
+use crate::watch::WatchedFile;
+
+/// GLSL dialects that the built-in shaders are written for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GlslVersion {
+    /// Desktop OpenGL 3.3
+    Gl330,
+
+    /// OpenGL ES 3
+    Gles300,
+
+    /// OpenGL ES 2, as on the Raspberry Pi
+    Gles100,
+}
+
+impl GlslVersion {
+    /// Every version, in the order they are tried when none is configured
+    const ALL: [GlslVersion; 3] = [
+        GlslVersion::Gl330,
+        GlslVersion::Gles300,
+        GlslVersion::Gles100,
+    ];
+
+    /// Parse a version as given on the command line
+    pub fn parse(text: &str) -> Result<Self, String> {
+        match text {
+            "330" => Ok(GlslVersion::Gl330),
+            "300es" => Ok(GlslVersion::Gles300),
+            "100" => Ok(GlslVersion::Gles100),
+            _ => Err(format!(
+                "Unknown GLSL version {} (expected 330, 300es, or 100)",
+                text
+            )),
+        }
+    }
+
+    /// The built-in bloom shader's vertex and fragment sources for this version.
+    ///
+    /// Versions without a vertex shader use raylib's default.
+    fn bloom_source(self) -> (Option<&'static str>, &'static str) {
+        match self {
+            GlslVersion::Gl330 => (None, include_str!("../shaders/bloom.fs")),
+            GlslVersion::Gles300 => (
+                Some(include_str!("../shaders/bloom_gles3.vs")),
+                include_str!("../shaders/bloom_gles3.fs"),
+            ),
+            GlslVersion::Gles100 => (None, include_str!("../shaders/bloom_gles2.fs")),
+        }
+    }
+
+    /// The built-in color vision shader's vertex and fragment sources for this version
+    fn vision_source(self) -> (Option<&'static str>, &'static str) {
+        match self {
+            GlslVersion::Gl330 => (None, include_str!("../shaders/vision.fs")),
+            GlslVersion::Gles300 => (
+                Some(include_str!("../shaders/bloom_gles3.vs")),
+                include_str!("../shaders/vision_gles3.fs"),
+            ),
+            GlslVersion::Gles100 => (None, include_str!("../shaders/vision_gles2.fs")),
+        }
+    }
+}
+
+/// raylib's default shader, which draws textures unchanged
+fn passthrough() -> Shader {
+    unsafe { Shader::from_raw(raylib::ffi::GetShaderDefault()) }
+}
+
+/// Fail if raylib fell back to its default shader because compiling failed
+fn check_compiled(shader: Shader, name: &str) -> Result<Shader, String> {
+    if shader.id == unsafe { raylib::ffi::GetShaderDefault() }.id {
+        return Err(format!(
+            "Failed to compile {} (see the log for details)",
+            name
+        ));
+    }
+    Ok(shader)
+}
+
+/// Compile a fragment shader from a file
+fn compile(rl: &mut RaylibHandle, thread: &RaylibThread, path: &str) -> Result<Shader, String> {
+    debug!("Compiling {}", path);
+    check_compiled(rl.load_shader(thread, None, Some(path))?, path)
+}
+
+/// Compile a built-in shader for a GLSL version, or the first version that compiles
+fn compile_builtin(
+    rl: &mut RaylibHandle,
+    thread: &RaylibThread,
+    glsl: Option<GlslVersion>,
+    name: &str,
+    source: fn(GlslVersion) -> (Option<&'static str>, &'static str),
+) -> Result<Shader, String> {
+    let versions = match glsl {
+        Some(version) => vec![version],
+        None => GlslVersion::ALL.to_vec(),
+    };
+
+    let mut result = Err("No GLSL versions to try".to_string());
+    for version in versions {
+        let (vertex, fragment) = source(version);
+        result = check_compiled(
+            rl.load_shader_code(thread, vertex, Some(fragment)),
+            &format!("the built-in {} shader for GLSL {:?}", name, version),
+        );
+        if result.is_ok() {
+            info!("Using the built-in {} shader for GLSL {:?}", name, version);
+            break;
+        }
+    }
+    result
+}
+
+/// The bloom post-processing shader, and the locations of its uniforms
+pub struct BloomShader {
+    pub shader: Shader,
+
+    /// The file the shader was loaded from, if it isn't the built-in one
+    pub file: Option<WatchedFile>,
+
+    resolution: i32,
+    threshold: i32,
+    radius: i32,
+    intensity: i32,
+}
+
+impl BloomShader {
+    /// Load the bloom shader from a file, or the built-in one.
+    ///
+    /// The built-in shader is compiled for the given GLSL version, or else for the first version
+    /// the driver accepts. If it fails to compile, a warning is printed and the light is drawn
+    /// without post-processing.
+    pub fn load(
+        rl: &mut RaylibHandle,
+        thread: &RaylibThread,
+        path: Option<&str>,
+        glsl: Option<GlslVersion>,
+    ) -> Self {
+        let (shader, file) = match path {
+            Some(path) => (compile(rl, thread, path), Some(WatchedFile::new(path))),
+            None => (
+                compile_builtin(rl, thread, glsl, "bloom", GlslVersion::bloom_source),
+                None,
+            ),
+        };
+        let shader = shader.unwrap_or_else(|error| {
+            warn!("{}. Post-processing is disabled", error);
+            passthrough()
+        });
+
+        let mut bloom = Self {
+            shader,
+            file,
+            resolution: -1,
+            threshold: -1,
+            radius: -1,
+            intensity: -1,
+        };
+        bloom.find_uniforms();
+        bloom
+    }
+
+    fn find_uniforms(&mut self) {
+        self.resolution = self.shader.get_shader_location("resolution");
+        self.threshold = self.shader.get_shader_location("threshold");
+        self.radius = self.shader.get_shader_location("radius");
+        self.intensity = self.shader.get_shader_location("intensity");
+    }
+
+    /// Recompile the shader if its file changed. A shader that fails to compile is reported,
+    /// and the previous one is kept.
+    ///
+    /// Returns `None` if nothing changed.
+    pub fn reload_if_changed(
+        &mut self,
+        rl: &mut RaylibHandle,
+        thread: &RaylibThread,
+    ) -> Option<Result<(), String>> {
+        let file = self.file.as_mut()?;
+        if !file.changed() {
+            return None;
+        }
+
+        let path = file.path.to_string_lossy().to_string();
+        info!("Reloading {}", path);
+        Some(compile(rl, thread, &path).map(|shader| {
+            self.shader = shader;
+            self.find_uniforms();
+        }))
+    }
+
+    /// Upload the bloom settings and render size to the shader
+    pub fn apply(&mut self, bloom: &Bloom, width: f32, height: f32) {
+        self.shader
+            .set_shader_value(self.resolution, Vector2::new(width, height));
+        self.shader
+            .set_shader_value(self.threshold, bloom.threshold);
+        self.shader.set_shader_value(self.radius, bloom.radius);
+        self.shader
+            .set_shader_value(self.intensity, bloom.intensity);
+    }
+}
+
+/// Color vision deficiencies the final image can be filtered to simulate, cycled with V
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Vision {
+    Normal,
+
+    /// No red cones
+    Protanopia,
+
+    /// No green cones
+    Deuteranopia,
+
+    /// No blue cones
+    Tritanopia,
+}
+
+impl Vision {
+    /// The next deficiency to simulate, returning to normal vision after the last
+    pub fn next(self) -> Self {
+        match self {
+            Vision::Normal => Vision::Protanopia,
+            Vision::Protanopia => Vision::Deuteranopia,
+            Vision::Deuteranopia => Vision::Tritanopia,
+            Vision::Tritanopia => Vision::Normal,
+        }
+    }
+
+    /// Rows of the matrix mapping a color to how it is seen, from Machado, Oliveira, and
+    /// Fernandes (2009) at full severity
+    fn matrix(self) -> [Vector3; 3] {
+        let rows = match self {
+            Vision::Normal => [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            Vision::Protanopia => [
+                [0.152286, 1.052583, -0.204868],
+                [0.114503, 0.786281, 0.099216],
+                [-0.003882, -0.048116, 1.051998],
+            ],
+            Vision::Deuteranopia => [
+                [0.367322, 0.860646, -0.227968],
+                [0.280085, 0.672501, 0.047413],
+                [-0.011820, 0.042940, 0.968881],
+            ],
+            Vision::Tritanopia => [
+                [1.255528, -0.076749, -0.178779],
+                [-0.078411, 0.930809, 0.147602],
+                [0.004733, 0.691367, 0.303900],
+            ],
+        };
+        let row = |row: [f32; 3]| Vector3::new(row[0], row[1], row[2]);
+        [row(rows[0]), row(rows[1]), row(rows[2])]
+    }
+}
+
+/// The shader that simulates color vision deficiencies, and the locations of its uniforms
+struct VisionShader {
+    shader: Shader,
+    rows: [i32; 3],
+}
+
+impl VisionShader {
+    /// Compile the built-in shader. If it fails to compile, a warning is printed and the
+    /// filters leave the image unchanged
+    fn load(rl: &mut RaylibHandle, thread: &RaylibThread, glsl: Option<GlslVersion>) -> Self {
+        let shader = compile_builtin(rl, thread, glsl, "color vision", GlslVersion::vision_source)
+            .unwrap_or_else(|error| {
+                warn!("{}. Color vision filters are disabled", error);
+                passthrough()
+            });
+        let rows = [
+            shader.get_shader_location("red"),
+            shader.get_shader_location("green"),
+            shader.get_shader_location("blue"),
+        ];
+        Self { shader, rows }
+    }
+
+    /// Upload the matrix for a deficiency
+    fn apply(&mut self, vision: Vision) {
+        for (location, row) in self.rows.iter().zip(vision.matrix().iter()) {
+            self.shader.set_shader_value(*location, *row);
+        }
+    }
+}
+
+/// A uniform value, ready to be bound
+enum Uniform {
+    Float(f32),
+    Vec2(Vector2),
+    Vec3(Vector3),
+    Vec4(Vector4),
+    Texture(Texture2D),
+}
+
+/// A post-processing pass declared in the world file.
+///
+/// Besides the world's uniforms, every pass receives `time` (seconds) and `resolution` (pixels).
+struct CustomPass {
+    shader: Shader,
+    file: WatchedFile,
+
+    /// Uniform names, their locations, and their values
+    uniforms: Vec<(String, i32, Uniform)>,
+
+    time: i32,
+    resolution: i32,
+}
+
+impl CustomPass {
+    fn load(
+        rl: &mut RaylibHandle,
+        thread: &RaylibThread,
+        pass: &ShaderPass,
+    ) -> Result<Self, String> {
+        let path = pass.resolve(&pass.path).to_string_lossy().to_string();
+
+        let mut uniforms = Vec::new();
+        for (name, value) in pass.uniforms.iter() {
+            let uniform = match value {
+                UniformValue::Float(value) => Uniform::Float(*value),
+                UniformValue::Vector(vector) => match vector.as_slice() {
+                    [x, y] => Uniform::Vec2(Vector2::new(*x, *y)),
+                    [x, y, z] => Uniform::Vec3(Vector3::new(*x, *y, *z)),
+                    [x, y, z, w] => Uniform::Vec4(Vector4::new(*x, *y, *z, *w)),
+                    _ => {
+                        return Err(format!(
+                            "Uniform {} of {} must have 2 to 4 components",
+                            name, path
+                        ))
+                    }
+                },
+                UniformValue::Texture { texture } => Uniform::Texture(
+                    rl.load_texture(thread, &pass.resolve(texture).to_string_lossy())?,
+                ),
+            };
+            uniforms.push((name.clone(), -1, uniform));
+        }
+
+        let mut custom = Self {
+            shader: compile(rl, thread, &path)?,
+            file: WatchedFile::new(&path),
+            uniforms,
+            time: -1,
+            resolution: -1,
+        };
+        custom.find_uniforms();
+        Ok(custom)
+    }
+
+    fn find_uniforms(&mut self) {
+        self.time = self.shader.get_shader_location("time");
+        self.resolution = self.shader.get_shader_location("resolution");
+        for (name, location, _) in self.uniforms.iter_mut() {
+            *location = self.shader.get_shader_location(name);
+        }
+    }
+
+    /// Recompile the shader if its file changed, keeping the previous one if compiling fails
+    fn reload_if_changed(
+        &mut self,
+        rl: &mut RaylibHandle,
+        thread: &RaylibThread,
+    ) -> Option<Result<(), String>> {
+        if !self.file.changed() {
+            return None;
+        }
+
+        let path = self.file.path.to_string_lossy().to_string();
+        info!("Reloading {}", path);
+        Some(compile(rl, thread, &path).map(|shader| {
+            self.shader = shader;
+            self.find_uniforms();
+        }))
+    }
+
+    /// Bind the built-in and world uniforms
+    fn apply(&mut self, time: f32, width: f32, height: f32) {
+        self.shader.set_shader_value(self.time, time);
+        self.shader
+            .set_shader_value(self.resolution, Vector2::new(width, height));
+        for (_, location, uniform) in self.uniforms.iter() {
+            match uniform {
+                Uniform::Float(value) => self.shader.set_shader_value(*location, *value),
+                Uniform::Vec2(value) => self.shader.set_shader_value(*location, *value),
+                Uniform::Vec3(value) => self.shader.set_shader_value(*location, *value),
+                Uniform::Vec4(value) => self.shader.set_shader_value(*location, *value),
+                Uniform::Texture(texture) => {
+                    self.shader.set_shader_value_texture(*location, texture)
+                }
+            }
+        }
+    }
+}
+
+/// Draw a render texture over the current render target through a shader
+pub fn blit(d: &mut RaylibDrawHandle, shader: &Shader, texture: &RenderTexture2D) {
+    let mut shader_context = d.begin_shader_mode(shader);
+    shader_context.draw_texture_rec(
+        texture,
+        Rectangle {
+            x: 0.0,
+            y: 0.0,
+            width: texture.width() as f32,
+            height: (texture.height() as f32) * -1.0,
+        },
+        Vector2::zero(),
+        Color::WHITE,
+    );
+}
+
+/// Bloom, followed by any passes declared in the world file, and then optionally a simulated
+/// color vision deficiency
+pub struct PostProcess {
+    pub bloom: BloomShader,
+    passes: Vec<CustomPass>,
+
+    /// Intermediate stages are drawn into these in turn
+    targets: Vec<RenderTexture2D>,
+
+    vision: Vision,
+    vision_shader: VisionShader,
+
+    /// The finished image, before the color vision filter
+    vision_target: Option<RenderTexture2D>,
+
+    width: i32,
+    height: i32,
+}
+
+impl PostProcess {
+    pub fn load(
+        rl: &mut RaylibHandle,
+        thread: &RaylibThread,
+        bloom_path: Option<&str>,
+        glsl: Option<GlslVersion>,
+        world: &World,
+        width: i32,
+        height: i32,
+    ) -> Result<Self, String> {
+        let mut post = Self {
+            bloom: BloomShader::load(rl, thread, bloom_path, glsl),
+            passes: Vec::new(),
+            targets: Vec::new(),
+            vision: Vision::Normal,
+            vision_shader: VisionShader::load(rl, thread, glsl),
+            vision_target: Some(rl.load_render_texture(thread, width as u32, height as u32)?),
+            width,
+            height,
+        };
+        post.load_world(rl, thread, world)?;
+        Ok(post)
+    }
+
+    /// Switch to a world's bloom settings and shader passes, keeping the bloom shader itself
+    pub fn load_world(
+        &mut self,
+        rl: &mut RaylibHandle,
+        thread: &RaylibThread,
+        world: &World,
+    ) -> Result<(), String> {
+        self.bloom
+            .apply(&world.bloom, self.width as f32, self.height as f32);
+
+        // Passes that fail to load are skipped, rather than losing the whole image
+        self.passes.clear();
+        for pass in world.passes.iter() {
+            match CustomPass::load(rl, thread, pass) {
+                Ok(pass) => self.passes.push(pass),
+                Err(error) => warn!("{}. Skipping the pass", error),
+            }
+        }
+        if !self.passes.is_empty() && self.targets.is_empty() {
+            for _ in 0..2 {
+                self.targets.push(rl.load_render_texture(
+                    thread,
+                    self.width as u32,
+                    self.height as u32,
+                )?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Recompile any shader whose file changed.
+    ///
+    /// Returns `None` if nothing changed, or the first error if any shader failed to compile.
+    pub fn reload_if_changed(
+        &mut self,
+        rl: &mut RaylibHandle,
+        thread: &RaylibThread,
+    ) -> Option<Result<(), String>> {
+        let mut result = self.bloom.reload_if_changed(rl, thread);
+        for pass in self.passes.iter_mut() {
+            if let Some(pass_result) = pass.reload_if_changed(rl, thread) {
+                result = Some(result.unwrap_or(Ok(())).and(pass_result));
+            }
+        }
+        result
+    }
+
+    /// The color vision deficiency being simulated
+    pub fn vision(&self) -> Vision {
+        self.vision
+    }
+
+    /// Simulate a color vision deficiency on the final image
+    pub fn set_vision(&mut self, vision: Vision) {
+        self.vision = vision;
+        self.vision_shader.apply(vision);
+    }
+
+    /// Run every stage but the last into offscreen textures, and the last too when a color
+    /// vision deficiency is simulated.
+    ///
+    /// Must be called outside of any texture mode, before [`PostProcess::draw`].
+    pub fn prepare(&mut self, d: &mut RaylibDrawHandle, renderer: &LightRenderer, time: f32) {
+        self.prepare_stages(d, renderer, time);
+
+        if self.vision != Vision::Normal {
+            if let Some(texture) = self.vision_target.take() {
+                {
+                    let mut target = RenderPass::begin(d, &texture, "vision");
+                    target.clear_background(Color::BLACK);
+                    self.draw_stages(&mut target, renderer, time);
+                }
+                self.vision_target = Some(texture);
+            }
+        }
+    }
+
+    /// Draw the final image into the current render target
+    pub fn draw(&mut self, d: &mut RaylibDrawHandle, renderer: &LightRenderer, time: f32) {
+        match (self.vision, &self.vision_target) {
+            (Vision::Normal, _) | (_, None) => self.draw_stages(d, renderer, time),
+            (_, Some(texture)) => blit(d, &self.vision_shader.shader, texture),
+        }
+    }
+
+    /// Run every stage but the last into offscreen textures
+    fn prepare_stages(&mut self, d: &mut RaylibDrawHandle, renderer: &LightRenderer, time: f32) {
+        if self.passes.is_empty() {
+            return;
+        }
+        let (width, height) = (self.width as f32, self.height as f32);
+
+        // Bloom the traced light into the first target
+        {
+            let mut target = RenderPass::begin(d, &self.targets[0], "bloom");
+            target.clear_background(Color::BLACK);
+            renderer.composite(&mut target, &self.bloom.shader);
+        }
+
+        // Then feed each pass into the next, swapping between targets
+        for index in 0..self.passes.len() - 1 {
+            let pass = &mut self.passes[index];
+            pass.apply(time, width, height);
+            let name = pass.file.path.display().to_string();
+            let mut target = RenderPass::begin(d, &self.targets[(index + 1) % 2], &name);
+            target.clear_background(Color::BLACK);
+            blit(&mut target, &pass.shader, &self.targets[index % 2]);
+        }
+    }
+
+    /// Draw the final stage into the current render target
+    fn draw_stages(&mut self, d: &mut RaylibDrawHandle, renderer: &LightRenderer, time: f32) {
+        let (width, height) = (self.width as f32, self.height as f32);
+        let count = self.passes.len();
+        match self.passes.last_mut() {
+            None => renderer.composite(d, &self.bloom.shader),
+            Some(pass) => {
+                pass.apply(time, width, height);
+                blit(d, &pass.shader, &self.targets[(count - 1) % 2]);
+            }
+        }
+    }
+}
+
+/// Adjust bloom settings with the number keys: 1/2 threshold, 3/4 radius, 5/6 intensity.
+/// The number keys toggle light groups instead while Shift is held.
+///
+/// Returns true if anything changed.
+pub fn handle_bloom_keys(d: &RaylibDrawHandle, bloom: &mut Bloom) -> bool {
+    if d.is_key_down(KeyboardKey::KEY_LEFT_SHIFT) || d.is_key_down(KeyboardKey::KEY_RIGHT_SHIFT) {
+        return false;
+    }
+    let before = *bloom;
+    if d.is_key_pressed(KeyboardKey::KEY_ONE) {
+        bloom.threshold = (bloom.threshold - 0.05).max(0.0);
+    }
+    if d.is_key_pressed(KeyboardKey::KEY_TWO) {
+        bloom.threshold = (bloom.threshold + 0.05).min(1.0);
+    }
+    if d.is_key_pressed(KeyboardKey::KEY_THREE) {
+        bloom.radius = (bloom.radius - 0.5).max(0.0);
+    }
+    if d.is_key_pressed(KeyboardKey::KEY_FOUR) {
+        bloom.radius += 0.5;
+    }
+    if d.is_key_pressed(KeyboardKey::KEY_FIVE) {
+        bloom.intensity = (bloom.intensity - 0.1).max(0.0);
+    }
+    if d.is_key_pressed(KeyboardKey::KEY_SIX) {
+        bloom.intensity += 0.1;
+    }
+    *bloom != before
+}
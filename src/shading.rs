@@ -0,0 +1,94 @@
+use crate::color::Color;
+use crate::math::Vector2;
+use crate::trace::RaySink;
+use crate::world::World;
+
+/// Length of wall, in pixels, that each shading segment covers
+pub const SEGMENT_LENGTH: f32 = 4.0;
+
+/// The light falling on each wall, in short segments along it.
+///
+/// Light is weighted by the cosine of its angle of incidence, so walls facing a light are lit
+/// more than walls it grazes.
+#[derive(Debug, Clone, Default)]
+pub struct WallShading {
+    /// Linear RGB energy of each segment of each wall, by wall index
+    pub walls: Vec<Vec<[f32; 3]>>,
+}
+
+impl WallShading {
+    /// Unlit shading for every wall in a world
+    pub fn new(world: &World) -> Self {
+        Self {
+            walls: world
+                .walls
+                .iter()
+                .map(|wall| {
+                    let segments = ((wall.end - wall.start).length() / SEGMENT_LENGTH).ceil();
+                    vec![[0.0; 3]; (segments as usize).max(1)]
+                })
+                .collect(),
+        }
+    }
+
+    /// Add another shading's energy into this one
+    pub fn add(&mut self, other: &WallShading) {
+        for (wall, other) in self.walls.iter_mut().zip(other.walls.iter()) {
+            for (segment, other) in wall.iter_mut().zip(other.iter()) {
+                for channel in 0..3 {
+                    segment[channel] += other[channel];
+                }
+            }
+        }
+    }
+
+    /// Tone map every segment to a displayable color, like [`crate::accumulate::Accumulator`]
+    pub fn tone_map(&self) -> Vec<Vec<Color>> {
+        let (sum, count) = self
+            .walls
+            .iter()
+            .flatten()
+            .map(|[r, g, b]| (r + g + b) / 3.0)
+            .filter(|luminance| *luminance > 0.0)
+            .fold((0.0, 0), |(sum, count), luminance| {
+                (sum + luminance, count + 1)
+            });
+        let mean = if count > 0 { sum / count as f32 } else { 1.0 };
+
+        let map = |energy: f32| ((1.0 - (-energy / mean).exp()) * 255.0) as u8;
+        self.walls
+            .iter()
+            .map(|wall| {
+                wall.iter()
+                    .map(|[r, g, b]| Color {
+                        r: map(*r),
+                        g: map(*g),
+                        b: map(*b),
+                        a: 255,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+impl RaySink for WallShading {
+    fn deposit(&mut self, _pixel: Vector2, _color: Color) {}
+
+    fn hit_wall(&mut self, wall: usize, along: f32, color: Color, cosine: f32) {
+        if let Some(segments) = self.walls.get_mut(wall) {
+            let index = ((along * segments.len() as f32) as usize).min(segments.len() - 1);
+
+            // Weighted like the accumulator, so multi-emitter lights share their energy
+            let weight = cosine * color.a as f32 / (255.0 * 255.0);
+            let segment = &mut segments[index];
+            segment[0] += color.r as f32 * weight;
+            segment[1] += color.g as f32 * weight;
+            segment[2] += color.b as f32 * weight;
+        }
+    }
+
+    fn ignores_black(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,206 @@
+use std::io::{stdin, stdout, BufRead, Write};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+
+use glasscast::query::Selector;
+use glasscast::world::{ColorLoad, Wall};
+use glasscast::World;
+use image::RgbaImage;
+use log::{debug, info, warn};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+/// Walls added with `add_wall`, either one or a list
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum NewWalls {
+    One(Wall),
+    Many(Vec<Wall>),
+}
+
+/// A command read from stdin, named by its `command` field
+#[derive(Debug, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+enum Command {
+    /// Reply with the world as JSON
+    GetWorld,
+
+    /// Change fields of the lights at an index or picked by a selector, as they would be
+    /// written in the world file
+    SetLight {
+        index: Option<usize>,
+        select: Option<String>,
+        set: Map<String, Value>,
+    },
+
+    /// Add walls in the world file's format
+    AddWall { wall: NewWalls },
+
+    /// Change fields of every wall a selector picks
+    SetWalls {
+        select: String,
+        set: Map<String, Value>,
+    },
+
+    /// Save the current frame as an image
+    RenderFrame { path: String },
+
+    /// Close the window
+    Quit,
+}
+
+/// What the simulation needs to do after a batch of commands
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct StdioEffects {
+    /// Every light needs re-tracing
+    pub walls_changed: bool,
+
+    pub quit: bool,
+}
+
+/// Drives the simulation with newline-delimited JSON commands on stdin, so it can be run as a
+/// child process from any language.
+///
+/// Each command is answered with one line on stdout: `{"ok":true,...}` with any results, or
+/// `{"ok":false,"error":"..."}`. An `id` field on a command is copied to its reply.
+///
+/// - `{"command":"get_world"}` replies with the world
+/// - `{"command":"set_light","index":0,"set":{"color":"red"}}` changes a light. `select`
+///   picks lights by name or tag instead
+/// - `{"command":"add_wall","wall":{...}}` adds a wall, or a list of walls
+/// - `{"command":"set_walls","select":"tag:glass","set":{...}}` changes walls
+/// - `{"command":"render_frame","path":"frame.png"}` saves the current frame
+/// - `{"command":"quit"}` closes the window
+pub struct StdioServer {
+    lines: Receiver<String>,
+}
+
+impl StdioServer {
+    /// Start reading commands from stdin
+    pub fn start() -> Self {
+        let (sender, lines) = channel();
+        thread::spawn(move || {
+            for line in stdin().lock().lines() {
+                match line {
+                    Ok(line) => {
+                        if sender.send(line).is_err() {
+                            break;
+                        }
+                    }
+                    Err(error) => {
+                        warn!("Failed to read from stdin: {}", error);
+                        break;
+                    }
+                }
+            }
+            debug!("Stdin closed");
+        });
+        info!("Reading commands from stdin");
+        Self { lines }
+    }
+
+    /// Run every command that arrived since the last frame. `frame` captures the current frame,
+    /// and is only called when one is asked for
+    pub fn handle(
+        &self,
+        world: &mut World,
+        frame: impl Fn() -> Result<RgbaImage, String>,
+    ) -> StdioEffects {
+        let mut effects = StdioEffects::default();
+        for line in self.lines.try_iter() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            debug!("Stdio command {}", line);
+
+            let message: Value = match serde_json::from_str(&line) {
+                Ok(message) => message,
+                Err(error) => {
+                    reply(None, Err(error.to_string()));
+                    continue;
+                }
+            };
+            let id = message.get("id").cloned();
+            let result = serde_json::from_value(message)
+                .map_err(|e| e.to_string())
+                .and_then(|command| run(command, world, &frame, &mut effects));
+            reply(id, result);
+        }
+        effects
+    }
+}
+
+fn run(
+    command: Command,
+    world: &mut World,
+    frame: &impl Fn() -> Result<RgbaImage, String>,
+    effects: &mut StdioEffects,
+) -> Result<Map<String, Value>, String> {
+    let mut result = Map::new();
+    match command {
+        Command::GetWorld => {
+            result.insert(
+                "world".to_string(),
+                serde_json::to_value(&*world).map_err(|e| e.to_string())?,
+            );
+        }
+        Command::SetLight { index, select, set } => {
+            let lights = match (index, select) {
+                (_, Some(select)) => world.find_lights(&select.parse::<Selector>()?),
+                (Some(index), None) => vec![index],
+                (None, None) => return Err("set_light needs an index or select".to_string()),
+            };
+            world.update_lights(&lights, &set)?;
+            result.insert("changed".to_string(), json!(lights.len()));
+        }
+        Command::AddWall { wall } => {
+            let mut walls = match wall {
+                NewWalls::One(wall) => vec![wall],
+                NewWalls::Many(walls) => walls,
+            };
+            for wall in walls.iter_mut() {
+                wall.load_colors();
+                wall.load_line();
+            }
+            let added = world.add_walls(walls);
+            result.insert("added".to_string(), json!(added.len()));
+            effects.walls_changed = true;
+        }
+        Command::SetWalls { select, set } => {
+            let walls = world.find_walls(&select.parse::<Selector>()?);
+            world.update_walls(&walls, &set)?;
+            effects.walls_changed |= !walls.is_empty();
+            result.insert("changed".to_string(), json!(walls.len()));
+        }
+        Command::RenderFrame { path } => {
+            frame()?.save(&path).map_err(|e| e.to_string())?;
+            result.insert("path".to_string(), json!(path));
+        }
+        Command::Quit => effects.quit = true,
+    }
+    Ok(result)
+}
+
+/// Write a command's reply as one line of JSON
+fn reply(id: Option<Value>, result: Result<Map<String, Value>, String>) {
+    let mut message = match result {
+        Ok(mut fields) => {
+            fields.insert("ok".to_string(), json!(true));
+            fields
+        }
+        Err(error) => {
+            let mut fields = Map::new();
+            fields.insert("ok".to_string(), json!(false));
+            fields.insert("error".to_string(), json!(error));
+            fields
+        }
+    };
+    if let Some(id) = id {
+        message.insert("id".to_string(), id);
+    }
+
+    let mut out = stdout();
+    if let Err(error) = writeln!(out, "{}", Value::Object(message)).and_then(|_| out.flush()) {
+        warn!("Failed to write to stdout: {}", error);
+    }
+}
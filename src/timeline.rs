@@ -0,0 +1,188 @@
+use glasscast::World;
+use log::debug;
+use raylib::prelude::*;
+
+/// Height of the timeline bar, in window pixels
+const BAR_HEIGHT: f32 = 16.0;
+
+/// Gap between the bar and the bottom of the window
+const BAR_MARGIN: f32 = 6.0;
+
+/// Width of the play and pause button
+const BUTTON_WIDTH: f32 = 20.0;
+
+/// Width kept clear to the right of the track for the time readout
+const READOUT_WIDTH: f32 = 120.0;
+
+fn contains(area: Rectangle, point: Vector2) -> bool {
+    point.x >= area.x
+        && point.y >= area.y
+        && point.x <= area.x + area.width
+        && point.y <= area.y + area.height
+}
+
+/// A bar along the bottom of the window for authoring animations, with a play and pause button
+/// and a scrubber that jumps to any time in the world's animation.
+///
+/// Only shown for worlds with keyframed lights or a day cycle. It works in window pixels, so it
+/// reads the mouse before the view maps it into the world.
+#[derive(Default)]
+pub struct Timeline {
+    /// Length of the animation being shown, in seconds
+    length: f32,
+
+    /// Times of every light's keyframes, marked on the track
+    keyframes: Vec<f32>,
+
+    /// Whether the scrubber is being dragged
+    dragging: bool,
+
+    /// Whether the mouse is over the bar, where clicks belong to the timeline
+    pub over: bool,
+}
+
+impl Timeline {
+    /// Handle clicks on the bar, moving `time` while the scrubber is dragged. Called once per
+    /// frame, before the view maps the mouse.
+    ///
+    /// Returns true if the play and pause button was clicked.
+    pub fn update(&mut self, rl: &mut RaylibHandle, world: &World, time: &mut f32) -> bool {
+        self.length = world
+            .animation_length()
+            .max(world.day.map_or(0.0, |day| day.length));
+        self.keyframes = world
+            .lights
+            .iter()
+            .flat_map(|light| light.keyframes.iter().map(|keyframe| keyframe.time))
+            .collect();
+        if self.length <= 0.0 {
+            self.over = false;
+            self.dragging = false;
+            return false;
+        }
+
+        // Work in window pixels, undoing the view's mapping from the last frame
+        rl.set_mouse_offset(Vector2::zero());
+        rl.set_mouse_scale(1.0, 1.0);
+        let width = rl.get_screen_width() as f32;
+        let height = rl.get_screen_height() as f32;
+        let mouse = rl.get_mouse_position();
+        let bar = Self::bar(width, height);
+        let button = Self::button(bar);
+        let track = Self::track(bar);
+        self.over = contains(bar, mouse);
+
+        let pressed = rl.is_mouse_button_pressed(MouseButton::MOUSE_LEFT_BUTTON);
+        let toggled = pressed && contains(button, mouse);
+        if pressed && contains(track, mouse) {
+            self.dragging = true;
+        }
+        if !rl.is_mouse_button_down(MouseButton::MOUSE_LEFT_BUTTON) {
+            self.dragging = false;
+        }
+
+        if self.dragging {
+            let along = ((mouse.x - track.x) / track.width).clamp(0.0, 1.0);
+            *time = along * self.length;
+            debug!("Scrubbed to {:.2} s", time);
+        }
+        toggled
+    }
+
+    /// Whether the scrubber is being dragged, so the mouse shouldn't move anything else
+    pub fn dragging(&self) -> bool {
+        self.dragging
+    }
+
+    /// The whole bar
+    fn bar(width: f32, height: f32) -> Rectangle {
+        Rectangle::new(
+            5.0,
+            height - BAR_HEIGHT - BAR_MARGIN,
+            width - 10.0,
+            BAR_HEIGHT,
+        )
+    }
+
+    fn button(bar: Rectangle) -> Rectangle {
+        Rectangle::new(bar.x, bar.y, BUTTON_WIDTH, bar.height)
+    }
+
+    /// The part of the bar that maps to time
+    fn track(bar: Rectangle) -> Rectangle {
+        let x = bar.x + BUTTON_WIDTH + 10.0;
+        Rectangle::new(
+            x,
+            bar.y,
+            (bar.width - (x - bar.x) - READOUT_WIDTH).max(1.0),
+            bar.height,
+        )
+    }
+
+    /// Draw the bar, with the scrubber at `time`
+    pub fn draw(&self, d: &mut RaylibDrawHandle, time: f32, paused: bool) {
+        if self.length <= 0.0 {
+            return;
+        }
+
+        let bar = Self::bar(d.get_screen_width() as f32, d.get_screen_height() as f32);
+        let button = Self::button(bar);
+        let track = Self::track(bar);
+        d.draw_rectangle_rec(bar, Color::new(0, 0, 0, 160));
+
+        // A triangle to play while paused, or two bars to pause while playing
+        if paused {
+            d.draw_triangle(
+                Vector2::new(button.x + 5.0, button.y + 2.0),
+                Vector2::new(button.x + 5.0, button.y + button.height - 2.0),
+                Vector2::new(
+                    button.x + button.width - 4.0,
+                    button.y + button.height / 2.0,
+                ),
+                Color::WHITE,
+            );
+        } else {
+            for x in [button.x + 5.0, button.x + 12.0].iter() {
+                d.draw_rectangle_rec(
+                    Rectangle::new(*x, button.y + 2.0, 4.0, button.height - 4.0),
+                    Color::WHITE,
+                );
+            }
+        }
+
+        // The track, with a tick at every keyframe
+        let middle = track.y + track.height / 2.0;
+        d.draw_line_ex(
+            Vector2::new(track.x, middle),
+            Vector2::new(track.x + track.width, middle),
+            2.0,
+            Color::GRAY,
+        );
+        let to_x = |time: f32| track.x + track.width * (time / self.length).clamp(0.0, 1.0);
+        for keyframe in self.keyframes.iter() {
+            let x = to_x(*keyframe);
+            d.draw_line_v(
+                Vector2::new(x, track.y + 3.0),
+                Vector2::new(x, track.y + track.height - 3.0),
+                Color::SKYBLUE,
+            );
+        }
+
+        // Animations loop, so the scrubber shows where in the loop the world is
+        let looped = time % self.length;
+        let scrubber = if self.dragging {
+            Color::YELLOW
+        } else {
+            Color::WHITE
+        };
+        d.draw_circle_v(Vector2::new(to_x(looped), middle), 5.0, scrubber);
+
+        d.draw_text(
+            &format!("{:.2} / {:.2} s", looped, self.length),
+            (track.x + track.width + 10.0) as i32,
+            bar.y as i32,
+            16,
+            Color::WHITE,
+        );
+    }
+}
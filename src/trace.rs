@@ -0,0 +1,488 @@
+use std::ops::AddAssign;
+
+use rand::{Rng, RngCore};
+
+use crate::color::Color;
+use crate::math::Vector2;
+use crate::plugin::Hit;
+use crate::world::{Light, LightShape, Wall, World};
+
+/// Distance in pixels between samples along a ray
+pub const STEP_SIZE: f32 = 2.0;
+
+/// Most times a single ray may be redirected by mirrors and lenses, so rays can't bounce forever
+pub const MAX_BOUNCES: usize = 32;
+
+/// Whether every channel of a color is at or below a brightness (0 to 1)
+fn is_dark(color: Color, cutoff: f32) -> bool {
+    color.r.max(color.g).max(color.b) as f32 <= cutoff * 255.0
+}
+
+/// Attenuate a ray color that travelled some distance through an absorbing medium
+fn absorb(color: Color, modifier: Color, density: f32, distance: f32) -> Color {
+    let channel = |channel: u8, modifier: u8| {
+        let extinction = density * (modifier as f32 / 255.0);
+        (channel as f32 * (-extinction * distance).exp()) as u8
+    };
+    Color {
+        r: channel(color.r, modifier.r),
+        g: channel(color.g, modifier.g),
+        b: channel(color.b, modifier.b),
+        a: color.a,
+    }
+}
+
+pub fn find_intersect(wall: &Wall, point: Vector2) -> bool {
+    wall.distance_to(point) < 1.0
+}
+
+pub fn get_color_modifier_of_pixel(pixel: Vector2, world: &World) -> Color {
+    match wall_at_counted(pixel, world).0 {
+        Some((_, wall)) => wall.color,
+        None => Color::BLACK,
+    }
+}
+
+/// Find the wall at a pixel and its index, and how many walls were tested to find it
+fn wall_at_counted(pixel: Vector2, world: &World) -> (Option<(usize, &Wall)>, u64) {
+    // Look the wall up in the rasterized walls, when they have been built
+    if let Some(map) = &world.occupancy {
+        let (mut found, tests) = if map.contains(pixel) {
+            (map.wall_at(pixel), 0)
+        } else {
+            // Outside the map, test the walls eight at a time
+            map.batch().first_within(pixel, 1.0)
+        };
+
+        // Then each loaded chunk's own map, as chunks' walls come after the rest. A chunk's map
+        // covers all of its walls, so pixels outside it miss every one
+        for (first, chunk) in world.chunk_occupancy.iter() {
+            if found.is_some() {
+                break;
+            }
+            found = chunk.wall_at(pixel).map(|index| first + index);
+        }
+
+        let wall = found.and_then(|index| world.walls.get(index).map(|wall| (index, wall)));
+        return (wall, tests);
+    }
+
+    // Search all walls
+    for (index, wall) in world.walls.iter().enumerate() {
+        // Check for collision
+        if find_intersect(&wall, pixel) {
+            return (Some((index, wall)), index as u64 + 1);
+        }
+    }
+
+    // No wall
+    (None, world.walls.len() as u64)
+}
+
+/// Work done by a trace, for profiling
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TraceStats {
+    pub rays: u64,
+
+    /// Ray-march steps taken across all rays
+    pub steps: u64,
+
+    /// Ray-wall intersection tests performed
+    pub intersection_tests: u64,
+}
+
+impl AddAssign for TraceStats {
+    fn add_assign(&mut self, other: Self) {
+        self.rays += other.rays;
+        self.steps += other.steps;
+        self.intersection_tests += other.intersection_tests;
+    }
+}
+
+/// Somewhere that traced ray samples are written to
+pub trait RaySink {
+    fn deposit(&mut self, pixel: Vector2, color: Color);
+
+    /// Called when a ray arrives at a wall, with how far along the wall it hit (0 to 1), the
+    /// ray's color as it arrived, and the cosine of its angle to the wall's normal
+    fn hit_wall(&mut self, _wall: usize, _along: f32, _color: Color, _cosine: f32) {}
+
+    /// Called when a mirror or lens turns a ray, with where it turned and its new direction
+    fn redirect(&mut self, _point: Vector2, _direction: Vector2) {}
+
+    /// Whether black samples leave the sink unchanged, so rays can stop once they fade out
+    fn ignores_black(&self) -> bool {
+        false
+    }
+}
+
+/// A sink that passes everything on to two others
+pub struct Tee<'a, A: RaySink, B: RaySink>(pub &'a mut A, pub &'a mut B);
+
+impl<'a, A: RaySink, B: RaySink> RaySink for Tee<'a, A, B> {
+    fn deposit(&mut self, pixel: Vector2, color: Color) {
+        self.0.deposit(pixel, color);
+        self.1.deposit(pixel, color);
+    }
+
+    fn hit_wall(&mut self, wall: usize, along: f32, color: Color, cosine: f32) {
+        self.0.hit_wall(wall, along, color, cosine);
+        self.1.hit_wall(wall, along, color, cosine);
+    }
+
+    fn redirect(&mut self, point: Vector2, direction: Vector2) {
+        self.0.redirect(point, direction);
+        self.1.redirect(point, direction);
+    }
+
+    fn ignores_black(&self) -> bool {
+        self.0.ignores_black() && self.1.ignores_black()
+    }
+}
+
+pub fn plot(
+    origin: &Vector2,
+    normal: Vector2,
+    magnitude: f32,
+    window_vec: &Vector2,
+    ray_color: &Color,
+    world: &World,
+    sink: &mut impl RaySink,
+) -> Option<Color> {
+    plot_counted(
+        origin, normal, magnitude, window_vec, ray_color, world, sink,
+    )
+    .0
+}
+
+/// Plot a single step of a ray, also returning how many walls were tested
+fn plot_counted(
+    origin: &Vector2,
+    normal: Vector2,
+    magnitude: f32,
+    window_vec: &Vector2,
+    ray_color: &Color,
+    world: &World,
+    sink: &mut impl RaySink,
+) -> (Option<Color>, u64) {
+    let (color, _, tests) = plot_pixel(
+        (normal * magnitude) + *origin,
+        normal,
+        &mut None,
+        &mut None,
+        window_vec,
+        ray_color,
+        world,
+        sink,
+    );
+    (color, tests)
+}
+
+/// Plot a single ray sample at a pixel, also returning how many walls were tested, and a new
+/// direction if a wall's custom behavior turned the ray.
+///
+/// `inside` tracks the wall the ray was in at its previous sample, so arriving at a wall is
+/// only reported once. `polarization` is the angle the ray is polarized along in radians, if
+/// it has passed through a polarizer.
+fn plot_pixel(
+    pixel: Vector2,
+    direction: Vector2,
+    inside: &mut Option<usize>,
+    polarization: &mut Option<f32>,
+    window_vec: &Vector2,
+    ray_color: &Color,
+    world: &World,
+    sink: &mut impl RaySink,
+) -> (Option<Color>, Option<Vector2>, u64) {
+    // We cannot plot outside the window
+    if (pixel.x < 0.0 || pixel.x > window_vec.x) || (pixel.y < 0.0 || pixel.y > window_vec.y) {
+        return (None, None, 0);
+    }
+
+    // Modify the light ray color
+    let (wall, tests) = wall_at_counted(pixel, world);
+    let ray_color = world
+        .fog
+        .iter()
+        .filter(|fog| fog.region.contains(pixel))
+        .fold(*ray_color, |color, fog| {
+            absorb(color, fog.color, fog.density, STEP_SIZE)
+        });
+    let ray_color = match &world.density_map {
+        Some(map) => absorb(ray_color, map.color, map.density_at(pixel), STEP_SIZE),
+        None => ray_color,
+    };
+    let mut turned = None;
+    let ray_color = match wall {
+        Some((index, wall)) => {
+            let hit = Hit {
+                point: pixel,
+                direction,
+                along: wall.along(pixel),
+                entering: *inside != Some(index),
+            };
+            if hit.entering {
+                sink.hit_wall(index, hit.along, ray_color, wall.cosine(direction));
+            }
+            let modified = match &wall.behavior {
+                Some(plugin) => {
+                    if hit.entering {
+                        turned = plugin.behavior.redirect(wall, &hit);
+                    }
+                    plugin.behavior.modify(wall, ray_color, &hit)
+                }
+                None => wall.modify(ray_color),
+            };
+            wall.polarize(modified, polarization)
+        }
+        None => ray_color,
+    };
+    *inside = wall.map(|(index, _)| index);
+
+    // Plot the ray
+    sink.deposit(
+        Vector2 {
+            x: pixel.x,
+            y: pixel.y,
+        },
+        ray_color,
+    );
+
+    // Iterate a step down the ray
+    (Some(ray_color), turned, tests)
+}
+
+pub fn trace_and_plot(
+    origin: &Vector2,
+    normal: Vector2,
+    window_vec: &Vector2,
+    ray_color: Color,
+    world: &World,
+    sink: &mut impl RaySink,
+) -> TraceStats {
+    trace_ray(origin, normal, window_vec, ray_color, world, sink, None)
+}
+
+/// Trace a ray, bouncing it off matte walls in random directions when given a random source
+fn trace_ray(
+    origin: &Vector2,
+    normal: Vector2,
+    window_vec: &Vector2,
+    ray_color: Color,
+    world: &World,
+    sink: &mut impl RaySink,
+    mut rng: Option<&mut dyn RngCore>,
+) -> TraceStats {
+    let mut stats = TraceStats {
+        rays: 1,
+        ..TraceStats::default()
+    };
+    let mut position = *origin;
+    let mut direction = normal;
+    let mut bounces = 0;
+    let mut inside = None;
+    let mut polarization = None;
+    let mut color = ray_color;
+
+    // Blending into a framebuffer paints shadows with dark rays, so only sinks that add light
+    // up can drop them
+    let cutoff = if sink.ignores_black() {
+        Some(world.ray_cutoff)
+    } else {
+        None
+    };
+    loop {
+        let was_inside = inside;
+        let (new_color, turned, tests) = plot_pixel(
+            position,
+            direction,
+            &mut inside,
+            &mut polarization,
+            window_vec,
+            &color,
+            world,
+            sink,
+        );
+        stats.steps += 1;
+        stats.intersection_tests += tests;
+
+        // Handle edge of the screen
+        if new_color.is_none() {
+            return stats;
+        }
+
+        color = new_color.unwrap();
+
+        // Stop once the ray has been absorbed, as nothing further along it would show
+        if cutoff.map_or(false, |cutoff| is_dark(color, cutoff)) {
+            return stats;
+        }
+
+        // Turn where a custom wall sent the ray
+        if let Some(new_direction) = turned.filter(|_| bounces < MAX_BOUNCES) {
+            direction = new_direction.normalized();
+            sink.redirect(position, direction);
+            bounces += 1;
+        } else if let (Some(rng), Some(index)) = (&mut rng, inside.filter(|_| inside != was_inside))
+        {
+            // Or bounce a ray arriving at a matte wall, as often as the wall scatters light
+            let wall = &world.walls[index];
+            let scatter = wall.scatter.unwrap_or(0.0);
+            if bounces < MAX_BOUNCES && scatter > 0.0 && rng.gen::<f32>() < scatter {
+                direction = wall.diffuse_bounce(direction, &mut **rng);
+                sink.redirect(position, direction);
+                bounces += 1;
+            }
+        }
+
+        // Step along the ray, turning at any mirror or lens in the way
+        let next = position + direction * STEP_SIZE;
+        match world.redirect(position, next, direction) {
+            Some((hit, new_direction)) if bounces < MAX_BOUNCES => {
+                sink.redirect(hit, new_direction);
+                position = hit;
+                direction = new_direction;
+                bounces += 1;
+            }
+            _ => position = next,
+        }
+    }
+}
+
+/// Draw a light's emitter disc, fading out over a second radius as a glow
+fn paint_emitter(light: &Light, window_vec: &Vector2, sink: &mut impl RaySink) {
+    // Directional light has no source in the window to draw
+    if light.radius <= 0.0 || matches!(light.shape, LightShape::Directional { .. }) {
+        return;
+    }
+
+    let center = light.position * *window_vec;
+    let color = light.emitted_color();
+    let extent = (light.radius * 2.0).ceil() as i32;
+    for y in -extent..=extent {
+        for x in -extent..=extent {
+            let offset = Vector2::new(x as f32, y as f32);
+            let pixel = center + offset;
+            if pixel.x < 0.0 || pixel.x > window_vec.x || pixel.y < 0.0 || pixel.y > window_vec.y {
+                continue;
+            }
+
+            // Solid inside the disc, then a linear falloff
+            let falloff = 1.0 - ((offset.length() - light.radius) / light.radius).max(0.0);
+            if falloff > 0.0 {
+                sink.deposit(
+                    pixel,
+                    Color {
+                        a: (u8::MAX as f32 * falloff) as u8,
+                        ..color
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// Trace every ray from a light into a sink
+pub fn trace_light(
+    world: &World,
+    light: &Light,
+    window_vec: &Vector2,
+    sink: &mut impl RaySink,
+) -> TraceStats {
+    // Each emitter point contributes an equal share of the light
+    let emitters = light.emitters(window_vec);
+    let color = Color {
+        a: light.ray_alpha(emitters.len()),
+        ..light.emitted_color()
+    };
+
+    paint_emitter(light, window_vec, sink);
+
+    let mut stats = TraceStats::default();
+    for emitter in emitters.iter() {
+        // Render every ray extending from the emitter
+        for normal in emitter.normals() {
+            // Recursive render
+            stats += trace_and_plot(
+                &(emitter.origin + normal * light.radius),
+                normal,
+                window_vec,
+                color,
+                world,
+                sink,
+            );
+        }
+    }
+    stats
+}
+
+/// Trace the same number of rays as `trace_light`, but in random directions, and bouncing off
+/// matte walls in random directions.
+///
+/// Repeated calls into an accumulating sink converge on a noise-free image.
+pub fn trace_light_stochastic(
+    world: &World,
+    light: &Light,
+    window_vec: &Vector2,
+    rng: &mut impl Rng,
+    sink: &mut impl RaySink,
+) -> TraceStats {
+    let emitters = light.emitters(window_vec);
+    let color = Color {
+        a: light.ray_alpha(emitters.len()),
+        ..light.emitted_color()
+    };
+
+    paint_emitter(light, window_vec, sink);
+
+    let mut stats = TraceStats::default();
+    for emitter in emitters.iter() {
+        for _ in 0..emitter.ray_count() {
+            let normal = emitter.random_normal(rng);
+            stats += trace_ray(
+                &(emitter.origin + normal * light.radius),
+                normal,
+                window_vec,
+                color,
+                world,
+                sink,
+                Some(&mut *rng as &mut dyn RngCore),
+            );
+        }
+    }
+    stats
+}
+
+/// Trace every ray from a light, with each ray's angle jittered slightly.
+///
+/// Averaging a few jittered traces removes the radial banding of exact one-degree rays.
+pub fn trace_light_jittered(
+    world: &World,
+    light: &Light,
+    window_vec: &Vector2,
+    rng: &mut impl Rng,
+    sink: &mut impl RaySink,
+) -> TraceStats {
+    let emitters = light.emitters(window_vec);
+    let color = Color {
+        a: light.ray_alpha(emitters.len()),
+        ..light.emitted_color()
+    };
+
+    paint_emitter(light, window_vec, sink);
+
+    let mut stats = TraceStats::default();
+    for emitter in emitters.iter() {
+        for normal in emitter.jittered_normals(rng) {
+            stats += trace_and_plot(
+                &(emitter.origin + normal * light.radius),
+                normal,
+                window_vec,
+                color,
+                world,
+                sink,
+            );
+        }
+    }
+    stats
+}
@@ -0,0 +1,152 @@
+use std::io::{stdout, Write};
+use std::time::{Duration, Instant};
+
+use crossterm::event::{
+    self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, MouseEventKind,
+};
+use crossterm::style::{
+    Color as TermColor, Print, ResetColor, SetBackgroundColor, SetForegroundColor,
+};
+use crossterm::{cursor, execute, queue, terminal};
+use glasscast::headless::render_image;
+use glasscast::world::TraceKey;
+use glasscast::{Vector2, World};
+use image::RgbaImage;
+
+/// Average the pixels of an image within a rectangle
+fn average(buffer: &RgbaImage, x0: usize, y0: usize, x1: usize, y1: usize) -> TermColor {
+    let (mut r, mut g, mut b, mut count) = (0, 0, 0, 0);
+    for y in y0..y1.min(buffer.height() as usize) {
+        for x in x0..x1.min(buffer.width() as usize) {
+            let pixel = buffer.get_pixel(x as u32, y as u32);
+            r += pixel[0] as usize;
+            g += pixel[1] as usize;
+            b += pixel[2] as usize;
+            count += 1;
+        }
+    }
+    let count = count.max(1);
+    TermColor::Rgb {
+        r: (r / count) as u8,
+        g: (g / count) as u8,
+        b: (b / count) as u8,
+    }
+}
+
+/// The trace inputs of every light, used to detect changes
+fn light_state(world: &World) -> Vec<TraceKey> {
+    world.lights.iter().map(|light| light.trace_key()).collect()
+}
+
+/// Draw an image to the terminal, two pixels per character cell
+fn draw(
+    out: &mut impl Write,
+    buffer: &RgbaImage,
+    columns: u16,
+    rows: u16,
+) -> crossterm::Result<()> {
+    let cell_width = buffer.width() as f32 / columns as f32;
+    let cell_height = buffer.height() as f32 / (rows as f32 * 2.0);
+
+    for row in 0..rows {
+        queue!(out, cursor::MoveTo(0, row))?;
+        for column in 0..columns {
+            let x0 = (column as f32 * cell_width) as usize;
+            let x1 = ((column + 1) as f32 * cell_width).ceil() as usize;
+            let top = (row as f32 * 2.0 * cell_height) as usize;
+            let middle = ((row as f32 * 2.0 + 1.0) * cell_height) as usize;
+            let bottom = ((row as f32 * 2.0 + 2.0) * cell_height).ceil() as usize;
+
+            // The upper half block takes the foreground color, the lower half the background
+            queue!(
+                out,
+                SetForegroundColor(average(buffer, x0, top, x1, middle.max(top + 1))),
+                SetBackgroundColor(average(buffer, x0, middle, x1, bottom.max(middle + 1))),
+                Print('▀')
+            )?;
+        }
+    }
+    queue!(out, ResetColor)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Render the world to the terminal until Q or Escape is pressed
+pub fn run(world: &mut World, width: usize, height: usize) -> crossterm::Result<()> {
+    let mut out = stdout();
+    terminal::enable_raw_mode()?;
+    execute!(
+        out,
+        terminal::EnterAlternateScreen,
+        EnableMouseCapture,
+        cursor::Hide
+    )?;
+
+    let result = render_loop(&mut out, world, width, height);
+
+    // Always restore the terminal, even if rendering failed
+    execute!(
+        out,
+        cursor::Show,
+        DisableMouseCapture,
+        terminal::LeaveAlternateScreen
+    )?;
+    terminal::disable_raw_mode()?;
+    result
+}
+
+fn render_loop(
+    out: &mut impl Write,
+    world: &mut World,
+    width: usize,
+    height: usize,
+) -> crossterm::Result<()> {
+    let (mut columns, mut rows) = terminal::size()?;
+    let start = Instant::now();
+    let mut dirty = true;
+
+    loop {
+        // Handle input
+        while event::poll(Duration::from_millis(0))? {
+            match event::read()? {
+                Event::Key(key) => match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                    _ => {}
+                },
+                Event::Mouse(mouse) => {
+                    if let MouseEventKind::Moved | MouseEventKind::Drag(_) = mouse.kind {
+                        let position = Vector2::new(
+                            mouse.column as f32 / columns as f32,
+                            mouse.row as f32 / rows as f32,
+                        );
+                        for light in world.lights.iter_mut() {
+                            if !light.fixed && light.keyframes.is_empty() {
+                                light.position = position;
+                                dirty = true;
+                            }
+                        }
+                    }
+                }
+                Event::Resize(new_columns, new_rows) => {
+                    columns = new_columns;
+                    rows = new_rows;
+                    dirty = true;
+                }
+            }
+        }
+
+        // Animate the lights
+        let time = start.elapsed().as_secs_f32();
+        let last_state = light_state(world);
+        world.animate(time);
+        dirty |= light_state(world) != last_state;
+
+        if dirty {
+            let image = render_image(world, width as u32, height as u32, false);
+            draw(out, &image, columns, rows)?;
+            dirty = false;
+        }
+
+        std::thread::sleep(Duration::from_millis(16));
+    }
+}
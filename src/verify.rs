@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+
+use crate::headless::render_image;
+use crate::math::Vector2;
+use crate::world::World;
+
+/// Light positions, normalized to the frame, that verification renders the first light at
+pub const POSITIONS: [Vector2; 9] = [
+    Vector2::new(0.25, 0.25),
+    Vector2::new(0.5, 0.25),
+    Vector2::new(0.75, 0.25),
+    Vector2::new(0.25, 0.5),
+    Vector2::new(0.5, 0.5),
+    Vector2::new(0.75, 0.5),
+    Vector2::new(0.25, 0.75),
+    Vector2::new(0.5, 0.75),
+    Vector2::new(0.75, 0.75),
+];
+
+/// The hash of one verification frame
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FrameHash {
+    pub position: Vector2,
+
+    /// FNV-1a hash of the frame's RGBA pixels, in hex
+    pub hash: String,
+}
+
+/// Hashes of a world rendered at every verification position, to compare future renders against
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Baseline {
+    pub width: u32,
+    pub height: u32,
+    pub frames: Vec<FrameHash>,
+}
+
+impl Baseline {
+    /// Render a world at every verification position and hash the frames.
+    ///
+    /// Frames are rendered with direct tracing, which involves no randomness.
+    pub fn render(world: &World, width: u32, height: u32) -> Self {
+        let mut world = world.clone();
+        let frames = POSITIONS
+            .iter()
+            .map(|position| {
+                if let Some(light) = world.lights.first_mut() {
+                    light.position = *position;
+                }
+                let image = render_image(&world, width, height, false);
+                FrameHash {
+                    position: *position,
+                    hash: format!("{:016x}", fnv1a(image.as_raw())),
+                }
+            })
+            .collect();
+
+        Self {
+            width,
+            height,
+            frames,
+        }
+    }
+
+    /// Positions whose frames differ from another baseline's. Frames only one of the baselines
+    /// has count as differing
+    pub fn mismatches(&self, other: &Baseline) -> Vec<Vector2> {
+        let (longer, shorter) = if self.frames.len() >= other.frames.len() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        longer
+            .frames
+            .iter()
+            .enumerate()
+            .filter(|(index, frame)| shorter.frames.get(*index) != Some(frame))
+            .map(|(_, frame)| frame.position)
+            .collect()
+    }
+}
+
+/// 64-bit FNV-1a, which unlike std's hasher is guaranteed stable between Rust releases
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
@@ -0,0 +1,328 @@
+use glasscast::World;
+use log::debug;
+use raylib::prelude::*;
+
+use crate::editor::draw_color;
+use crate::render::LightRenderer;
+
+/// Furthest the view can zoom in
+const MAX_ZOOM: f32 = 8.0;
+
+/// How much each notch of the mouse wheel zooms
+const ZOOM_STEP: f32 = 1.25;
+
+/// Width of the minimap, in pixels
+const MINIMAP_WIDTH: f32 = 200.0;
+
+/// How the scene is fitted into the window, and magnified within it.
+///
+/// The scene keeps its aspect ratio however the window is shaped, with black bars filling the
+/// rest of the window. It can be zoomed about the cursor with the mouse wheel, panned by dragging
+/// with the middle mouse button, and reset with Z.
+///
+/// While zoomed, a minimap shows the whole scene and the part of it in view, and clicking the
+/// minimap jumps the view there. The mouse is mapped into world coordinates, so everything that
+/// reads it works unchanged at any zoom.
+///
+/// Worlds split into chunks can reach beyond the scene, and the view pans over all of them.
+/// Light is traced in a scene-sized frame, which moves to follow the view when it leaves.
+pub struct View {
+    zoom: f32,
+
+    /// World position of the window's top left corner
+    origin: glasscast::Vector2,
+
+    /// Where the current pan started, in window pixels
+    drag: Option<glasscast::Vector2>,
+
+    /// Whether the mouse is over the minimap, where clicks belong to the minimap
+    pub over_minimap: bool,
+
+    /// Size of the scene, in world pixels
+    width: f32,
+    height: f32,
+
+    /// Top left corner and size of the area the view can pan over, in world pixels
+    bounds: (glasscast::Vector2, glasscast::Vector2),
+
+    /// Top left corner of the part of the world light is traced in, in world pixels
+    frame: glasscast::Vector2,
+
+    /// Size of the window, in window pixels
+    window: glasscast::Vector2,
+
+    /// Render texture pixels per world pixel
+    resolution: f32,
+}
+
+impl View {
+    pub fn new(width: i32, height: i32, resolution: f32) -> Self {
+        Self {
+            zoom: 1.0,
+            origin: glasscast::Vector2::zero(),
+            drag: None,
+            over_minimap: false,
+            width: width as f32,
+            height: height as f32,
+            bounds: (
+                glasscast::Vector2::zero(),
+                glasscast::Vector2::new(width as f32, height as f32),
+            ),
+            frame: glasscast::Vector2::zero(),
+            window: glasscast::Vector2::new(width as f32, height as f32),
+            resolution,
+        }
+    }
+
+    /// Size of the scene, in world pixels
+    pub fn scene_size(&self) -> glasscast::Vector2 {
+        glasscast::Vector2::new(self.width, self.height)
+    }
+
+    /// Pan over a world's chunks as well as the scene, going back to the scene's frame
+    pub fn set_world(&mut self, world: &World) {
+        self.bounds = world.bounds(self.scene_size());
+        self.origin = glasscast::Vector2::zero();
+        self.frame = glasscast::Vector2::zero();
+    }
+
+    /// Top left corner of the scene-sized part of the world that light is traced in
+    pub fn frame(&self) -> glasscast::Vector2 {
+        self.frame
+    }
+
+    /// Window pixels per world pixel, including the zoom
+    fn scale(&self) -> f32 {
+        let fit = (self.window.x / self.width).min(self.window.y / self.height);
+        fit * self.zoom
+    }
+
+    /// Size of the black bars to the left of and above the scene
+    fn margin(&self) -> glasscast::Vector2 {
+        (self.window - self.scene_size() * (self.scale() / self.zoom)) / 2.0
+    }
+
+    /// Handle zooming, panning, and minimap clicks. Called once per frame, before drawing
+    pub fn update(&mut self, rl: &mut RaylibHandle) {
+        // Work in window pixels, undoing the mapping from the last frame
+        rl.set_mouse_offset(Vector2::zero());
+        rl.set_mouse_scale(1.0, 1.0);
+        let mouse = rl.get_mouse_position();
+        let mouse = glasscast::Vector2::new(mouse.x, mouse.y);
+        self.window =
+            glasscast::Vector2::new(rl.get_screen_width() as f32, rl.get_screen_height() as f32);
+
+        // Jump to wherever the minimap is clicked
+        let minimap = self.minimap_to_world(mouse);
+        self.over_minimap = minimap.is_some();
+        if let (Some(point), true) = (
+            minimap,
+            rl.is_mouse_button_pressed(MouseButton::MOUSE_LEFT_BUTTON),
+        ) {
+            self.origin = point - self.scene_size() / self.zoom / 2.0;
+        }
+
+        // Zoom about the cursor. The wheel with modifiers is left for adjusting lights
+        let modifier = [
+            KeyboardKey::KEY_LEFT_SHIFT,
+            KeyboardKey::KEY_RIGHT_SHIFT,
+            KeyboardKey::KEY_LEFT_CONTROL,
+            KeyboardKey::KEY_RIGHT_CONTROL,
+            KeyboardKey::KEY_LEFT_ALT,
+            KeyboardKey::KEY_RIGHT_ALT,
+        ]
+        .iter()
+        .any(|key| rl.is_key_down(*key));
+        let wheel = rl.get_mouse_wheel_move();
+        if wheel != 0.0 && !modifier {
+            let anchor = self.to_world(mouse);
+            self.zoom = (self.zoom * ZOOM_STEP.powf(wheel)).clamp(1.0, MAX_ZOOM);
+            self.origin = anchor - (mouse - self.margin()) / self.scale();
+            debug!("Zoom: {:.2}", self.zoom);
+        }
+
+        // Pan by dragging with the middle button
+        if rl.is_mouse_button_down(MouseButton::MOUSE_MIDDLE_BUTTON) {
+            if let Some(last) = self.drag {
+                self.origin = self.origin - (mouse - last) / self.scale();
+            }
+            self.drag = Some(mouse);
+        } else {
+            self.drag = None;
+        }
+
+        if rl.is_key_pressed(KeyboardKey::KEY_Z) {
+            self.zoom = 1.0;
+        }
+
+        // Keep the view inside the world
+        let size = self.scene_size() / self.zoom;
+        let (low, extent) = self.bounds;
+        let high = low + extent - size;
+        self.origin = glasscast::Vector2::new(
+            self.origin.x.clamp(low.x, high.x.max(low.x)),
+            self.origin.y.clamp(low.y, high.y.max(low.y)),
+        );
+
+        // Move the traced frame to center on the view once any of the view leaves it
+        let end = self.frame + self.scene_size();
+        let corner = self.origin + size;
+        if self.origin.x < self.frame.x
+            || self.origin.y < self.frame.y
+            || corner.x > end.x
+            || corner.y > end.y
+        {
+            let centered = self.origin + (size - self.scene_size()) / 2.0;
+            let high = low + extent - self.scene_size();
+            self.frame = glasscast::Vector2::new(
+                centered.x.clamp(low.x, high.x.max(low.x)),
+                centered.y.clamp(low.y, high.y.max(low.y)),
+            );
+            debug!(
+                "Traced frame moved to {:.0}, {:.0}",
+                self.frame.x, self.frame.y
+            );
+        }
+
+        // raylib adds the offset before scaling
+        let (scale, margin) = (self.scale(), self.margin());
+        rl.set_mouse_offset(Vector2::new(
+            self.origin.x * scale - margin.x,
+            self.origin.y * scale - margin.y,
+        ));
+        rl.set_mouse_scale(1.0 / scale, 1.0 / scale);
+    }
+
+    /// The top left corner and size of the part of the world in view
+    pub fn visible(&self) -> (glasscast::Vector2, glasscast::Vector2) {
+        (self.origin, self.scene_size() / self.zoom)
+    }
+
+    /// Convert a position in window pixels to the world
+    fn to_world(&self, point: glasscast::Vector2) -> glasscast::Vector2 {
+        self.origin + (point - self.margin()) / self.scale()
+    }
+
+    /// Convert a position in the world to window pixels
+    pub fn to_screen(&self, point: glasscast::Vector2) -> glasscast::Vector2 {
+        self.margin() + (point - self.origin) * self.scale()
+    }
+
+    /// Start drawing in world coordinates, clipped to the scene. Must be paired with [`View::end`]
+    pub fn begin(&self) {
+        self.begin_scaled(self.origin, 1.0);
+    }
+
+    /// Start drawing render textures, which may have more pixels than the world and are placed
+    /// at the traced frame. Must be paired with [`View::end`]
+    pub fn begin_render(&self) {
+        self.begin_scaled(self.origin - self.frame, self.resolution);
+    }
+
+    fn begin_scaled(&self, origin: glasscast::Vector2, pixels_per_unit: f32) {
+        let margin = self.margin();
+        let camera = raylib::ffi::Camera2D {
+            offset: Vector2::new(margin.x, margin.y).into(),
+            target: Vector2::new(origin.x * pixels_per_unit, origin.y * pixels_per_unit).into(),
+            rotation: 0.0,
+            zoom: self.scale() / pixels_per_unit,
+        };
+        unsafe {
+            raylib::ffi::BeginScissorMode(
+                margin.x as i32,
+                margin.y as i32,
+                (self.window.x - margin.x * 2.0) as i32,
+                (self.window.y - margin.y * 2.0) as i32,
+            );
+            raylib::ffi::BeginMode2D(camera);
+        }
+    }
+
+    /// Go back to drawing in window pixels
+    pub fn end(&self) {
+        unsafe {
+            raylib::ffi::EndMode2D();
+            raylib::ffi::EndScissorMode();
+        }
+    }
+
+    /// The minimap's area in the window, when zoomed or when the world is larger than the scene
+    fn minimap(&self) -> Option<Rectangle> {
+        let extent = self.bounds.1;
+        if self.zoom <= 1.0 && extent == self.scene_size() {
+            return None;
+        }
+        let height = MINIMAP_WIDTH * extent.y / extent.x;
+        Some(Rectangle::new(
+            self.window.x - MINIMAP_WIDTH - 10.0,
+            self.window.y - height - 40.0,
+            MINIMAP_WIDTH,
+            height,
+        ))
+    }
+
+    /// The world position under a point on the minimap, if the point is on it
+    fn minimap_to_world(&self, point: glasscast::Vector2) -> Option<glasscast::Vector2> {
+        let area = self.minimap()?;
+        let local = glasscast::Vector2::new(point.x - area.x, point.y - area.y);
+        if local.x < 0.0 || local.y < 0.0 || local.x > area.width || local.y > area.height {
+            return None;
+        }
+        Some(self.bounds.0 + local * (self.bounds.1.x / area.width))
+    }
+
+    /// Draw the minimap, with the scene, the part of it in view, and the lights
+    pub fn draw_minimap(&self, d: &mut RaylibDrawHandle, renderer: &LightRenderer, world: &World) {
+        let area = match self.minimap() {
+            Some(area) => area,
+            None => return,
+        };
+        let scale = area.width / self.bounds.1.x;
+        let to_map = |point: glasscast::Vector2| {
+            let point = (point - self.bounds.0) * scale;
+            Vector2::new(area.x + point.x, area.y + point.y)
+        };
+
+        // Render textures are stored upside down, and only cover the traced frame
+        let window_vec = self.scene_size();
+        let frame = to_map(self.frame);
+        d.draw_rectangle_rec(area, Color::BLACK);
+        d.draw_texture_pro(
+            &renderer.surface,
+            Rectangle::new(
+                0.0,
+                0.0,
+                renderer.surface.width() as f32,
+                -renderer.surface.height() as f32,
+            ),
+            Rectangle::new(frame.x, frame.y, window_vec.x * scale, window_vec.y * scale),
+            Vector2::zero(),
+            0.0,
+            Color::WHITE,
+        );
+        for wall in world.walls.iter() {
+            d.draw_line_v(
+                to_map(wall.start),
+                to_map(wall.end),
+                Color::new(255, 255, 255, 96),
+            );
+        }
+        for light in world.lights.iter().filter(|light| light.enabled) {
+            d.draw_circle_v(
+                to_map(light.position * window_vec),
+                3.0,
+                draw_color(light.emitted_color()),
+            );
+        }
+
+        // The part of the scene in view
+        let size = window_vec / self.zoom;
+        let corner = to_map(self.origin);
+        d.draw_rectangle_lines_ex(
+            Rectangle::new(corner.x, corner.y, size.x * scale, size.y * scale),
+            1,
+            Color::YELLOW,
+        );
+        d.draw_rectangle_lines_ex(area, 1, Color::WHITE);
+    }
+}
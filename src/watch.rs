@@ -0,0 +1,27 @@
+use std::fs::metadata;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// A file watched for changes by polling its modification time
+pub struct WatchedFile {
+    pub path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl WatchedFile {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let modified = metadata(&path).and_then(|m| m.modified()).ok();
+        Self { path, modified }
+    }
+
+    /// Check whether the file was modified since the last call
+    pub fn changed(&mut self) -> bool {
+        let modified = metadata(&self.path).and_then(|m| m.modified()).ok();
+        if modified != self.modified {
+            self.modified = modified;
+            return modified.is_some();
+        }
+        false
+    }
+}
@@ -0,0 +1,1770 @@
+use std::collections::BTreeMap;
+use std::fs::{read, read_to_string, write};
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use geo::algorithm::euclidean_distance::EuclideanDistance;
+use geo::{Line, Point};
+use image::GrayImage;
+use log::{debug, info, warn};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::color::{kelvin_to_color, lerp_color, rotate_hue, Color, ColorSpec};
+use crate::error::Error;
+use crate::math::{perlin, Vector2};
+use crate::migrate;
+use crate::occupancy::Occupancy;
+use crate::optics::{Lens, ParabolicMirror};
+use crate::plugin::{LightBehavior, Plugin, WallBehavior};
+use crate::query::{with_fields, Selector};
+use crate::sensor::Sensor;
+
+pub trait ColorLoad {
+    fn load_colors(&mut self);
+}
+
+/// How a wall changes the color of rays passing through it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WallKind {
+    /// The wall's color is subtracted from the ray
+    Absorb,
+
+    /// The ray is multiplied by the wall's color, like a colored gel. A red filter passes only red
+    Filter,
+
+    /// A filter that also only passes light polarized along its `angle`, by Malus's law
+    Polarizer,
+}
+
+impl Default for WallKind {
+    fn default() -> Self {
+        WallKind::Absorb
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Wall {
+    #[serde(rename = "color")]
+    raw_color: ColorSpec,
+
+    #[serde(default)]
+    pub kind: WallKind,
+
+    /// Transmission axis of a polarizer, in degrees
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub angle: Option<f32>,
+
+    /// Chance (0 to 1) that a ray arriving at the wall bounces off it in a random direction,
+    /// like a matte surface. Only progressive rendering samples bounces
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scatter: Option<f32>,
+
+    /// Stable handle for scripts and remote control
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// Labels for picking several walls at once, like `glass`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+
+    /// A registered custom behavior, which replaces `kind` for rays inside the wall
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub behavior: Option<Plugin<dyn WallBehavior>>,
+
+    #[serde(skip)]
+    pub color: Color,
+
+    pub start: Vector2,
+    pub end: Vector2,
+
+    #[serde(skip)]
+    pub line: Option<Line<f32>>,
+
+    /// Set for walls merged in from an include, which are left out when the world is saved
+    #[serde(skip)]
+    pub included: bool,
+
+    /// The chunk the wall was loaded from, if any
+    #[serde(skip)]
+    pub chunk: Option<usize>,
+}
+
+impl Wall {
+    /// Create a wall with its color and geometry already loaded
+    pub fn new(start: Vector2, end: Vector2, color: ColorSpec) -> Self {
+        let mut wall = Self {
+            raw_color: color,
+            kind: WallKind::default(),
+            angle: None,
+            scatter: None,
+            name: None,
+            tags: Vec::new(),
+            behavior: None,
+            color: Color::default(),
+            start,
+            end,
+            line: None,
+            included: false,
+            chunk: None,
+        };
+        wall.load_colors();
+        wall.load_line();
+        wall
+    }
+
+    pub fn load_line(&mut self) {
+        self.line = Some(Line::new(
+            Point::new(self.start.x, self.start.y),
+            Point::new(self.end.x, self.end.y),
+        ));
+    }
+
+    /// The color of a ray after passing through the wall once
+    pub fn modify(&self, ray: Color) -> Color {
+        match self.kind {
+            WallKind::Absorb => Color {
+                r: ray.r.saturating_sub(self.color.r),
+                g: ray.g.saturating_sub(self.color.g),
+                b: ray.b.saturating_sub(self.color.b),
+                a: ray.a,
+            },
+            WallKind::Filter | WallKind::Polarizer => {
+                let filter = |ray: u8, wall: u8| (ray as u16 * wall as u16 / 255) as u8;
+                Color {
+                    r: filter(ray.r, self.color.r),
+                    g: filter(ray.g, self.color.g),
+                    b: filter(ray.b, self.color.b),
+                    a: ray.a,
+                }
+            }
+        }
+    }
+
+    /// The color of a ray after passing through a polarizer, which leaves the ray polarized along
+    /// the polarizer's axis. Unpolarized rays lose half their intensity, and polarized rays are
+    /// scaled by the squared cosine of the angle between their polarization and the axis.
+    ///
+    /// Walls of other kinds leave the ray unchanged.
+    pub fn polarize(&self, ray: Color, polarization: &mut Option<f32>) -> Color {
+        if self.kind != WallKind::Polarizer {
+            return ray;
+        }
+
+        let axis = self.angle.unwrap_or(0.0).to_radians();
+        let transmitted = match *polarization {
+            Some(angle) => (angle - axis).cos().powi(2),
+            None => 0.5,
+        };
+        *polarization = Some(axis);
+
+        let scale = |channel: u8| (channel as f32 * transmitted) as u8;
+        Color {
+            r: scale(ray.r),
+            g: scale(ray.g),
+            b: scale(ray.b),
+            a: ray.a,
+        }
+    }
+
+    /// How far along the wall a point is, from 0 at its start to 1 at its end
+    pub fn along(&self, point: Vector2) -> f32 {
+        let wall = self.end - self.start;
+        let length_squared = wall.dot(wall);
+        if length_squared == 0.0 {
+            return 0.0;
+        }
+        ((point - self.start).dot(wall) / length_squared).clamp(0.0, 1.0)
+    }
+
+    /// A random direction for a ray bouncing off a matte wall, back towards the side it came from.
+    /// Directions near the wall's normal are likelier, by Lambert's cosine law
+    pub fn diffuse_bounce(&self, direction: Vector2, rng: &mut (impl Rng + ?Sized)) -> Vector2 {
+        let tangent = (self.end - self.start).normalized();
+        let mut normal = Vector2::new(-tangent.y, tangent.x);
+        if normal.dot(direction) > 0.0 {
+            normal = normal * -1.0;
+        }
+
+        // In 2D, a cosine-weighted angle from the normal has a uniformly distributed sine
+        let sine: f32 = rng.gen_range(-1.0..1.0);
+        let cosine = (1.0 - sine * sine).sqrt();
+        normal * cosine + tangent * sine
+    }
+
+    /// The cosine of the angle between a direction and the wall's normal, for Lambert shading
+    pub fn cosine(&self, direction: Vector2) -> f32 {
+        let wall = (self.end - self.start).normalized();
+        let normal = Vector2::new(-wall.y, wall.x);
+        normal.dot(direction.normalized()).abs()
+    }
+
+    /// Shortest distance from a point to the wall's segment
+    pub fn distance_to(&self, point: Vector2) -> f32 {
+        // Walls built by hand may not have their line loaded yet
+        let line = self.line.unwrap_or_else(|| {
+            Line::new(
+                Point::new(self.start.x, self.start.y),
+                Point::new(self.end.x, self.end.y),
+            )
+        });
+        line.euclidean_distance(&Point::new(point.x, point.y))
+    }
+}
+
+impl ColorLoad for Wall {
+    fn load_colors(&mut self) {
+        self.color = self.raw_color.into();
+    }
+}
+
+/// The geometry rays are emitted from
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LightShape {
+    /// All rays start at the light's position
+    Point,
+
+    /// Rays start from points sampled around a circle, producing soft shadows
+    Area {
+        /// Radius in pixels
+        radius: f32,
+
+        /// Number of points sampled on the perimeter. More samples give smoother penumbras
+        #[serde(default = "default_area_samples")]
+        samples: usize,
+    },
+
+    /// Rays start from points along a segment centered on the light, like a neon tube
+    Line {
+        /// Length of the segment in pixels
+        length: f32,
+
+        /// Orientation of the segment in degrees
+        #[serde(default)]
+        angle: f32,
+
+        /// Width of the fan of rays leaving each side of the segment, in degrees. Zero emits perpendicular rays only
+        #[serde(default = "default_line_arc")]
+        arc: f32,
+
+        /// Number of points sampled along the segment
+        #[serde(default = "default_line_samples")]
+        samples: usize,
+    },
+
+    /// Parallel rays crossing the whole window, like sunlight. The light's position is ignored
+    Directional {
+        /// Direction the rays travel in, in degrees
+        #[serde(default)]
+        angle: f32,
+
+        /// Distance between neighbouring rays in pixels
+        #[serde(default = "default_directional_spacing")]
+        spacing: f32,
+    },
+}
+
+impl Default for LightShape {
+    fn default() -> Self {
+        LightShape::Point
+    }
+}
+
+fn default_area_samples() -> usize {
+    8
+}
+
+fn default_line_arc() -> f32 {
+    180.0
+}
+
+fn default_line_samples() -> usize {
+    16
+}
+
+fn default_directional_spacing() -> f32 {
+    1.0
+}
+
+/// A point that rays are emitted from, and the fan of directions they leave in
+#[derive(Debug, Clone, Copy)]
+pub struct Emitter {
+    /// Origin in pixels
+    pub origin: Vector2,
+
+    /// Direction of the first ray, in degrees
+    pub start_angle: f32,
+
+    /// Width of the fan of rays, in degrees
+    pub sweep: f32,
+}
+
+impl Emitter {
+    /// An emitter casting rays in every direction
+    pub fn omnidirectional(origin: Vector2) -> Self {
+        Self {
+            origin,
+            start_angle: 0.0,
+            sweep: 360.0,
+        }
+    }
+
+    /// Direction of every ray cast by this emitter, one per degree of sweep
+    pub fn normals(&self) -> impl Iterator<Item = Vector2> {
+        let start_angle = self.start_angle;
+        let count = self.ray_count();
+        let step = if self.sweep > 0.0 {
+            self.sweep / count as f32
+        } else {
+            0.0
+        };
+        (0..count).map(move |i| {
+            let angle = (start_angle + i as f32 * step).to_radians();
+            Vector2 {
+                x: angle.cos(),
+                y: angle.sin(),
+            }
+        })
+    }
+
+    /// Number of rays cast by this emitter per trace
+    pub fn ray_count(&self) -> usize {
+        self.sweep.round().max(1.0) as usize
+    }
+
+    /// Direction of every ray cast by this emitter, each offset randomly within its share of the sweep
+    pub fn jittered_normals<'a>(
+        &self,
+        rng: &'a mut impl Rng,
+    ) -> impl Iterator<Item = Vector2> + 'a {
+        let start_angle = self.start_angle;
+        let count = self.ray_count();
+        let step = self.sweep / count as f32;
+        (0..count).map(move |i| {
+            let angle = (start_angle + (i as f32 + rng.gen_range(-0.5..0.5)) * step).to_radians();
+            Vector2 {
+                x: angle.cos(),
+                y: angle.sin(),
+            }
+        })
+    }
+
+    /// A uniformly random direction within this emitter's fan
+    pub fn random_normal(&self, rng: &mut impl Rng) -> Vector2 {
+        let angle = (self.start_angle + rng.gen::<f32>() * self.sweep).to_radians();
+        Vector2 {
+            x: angle.cos(),
+            y: angle.sin(),
+        }
+    }
+}
+
+/// A looping animation through a list of colors
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Gradient {
+    pub colors: Vec<ColorSpec>,
+
+    /// Time in seconds to pass through every color once
+    pub period: f32,
+}
+
+/// A light position at a point in time, for animated lights
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Keyframe {
+    /// Time in seconds since the start of the animation
+    pub time: f32,
+    pub position: Vector2,
+}
+
+/// A virtual day that sweeps a light across the world like the sun, for daylighting studies.
+///
+/// Between sunrise and sunset the light shines in parallel rays whose direction turns from
+/// `rise_angle` to `set_angle`, warm at the horizon and whitest at noon. At night it is dark.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DayCycle {
+    /// Index of the light that acts as the sun
+    #[serde(default)]
+    pub light: usize,
+
+    /// Real time in seconds for a whole virtual day
+    #[serde(default = "default_day_length")]
+    pub length: f32,
+
+    /// Hour the day starts at when the world opens, on a 24 hour clock
+    #[serde(default = "default_sunrise")]
+    pub start: f32,
+
+    #[serde(default = "default_sunrise")]
+    pub sunrise: f32,
+
+    #[serde(default = "default_sunset")]
+    pub sunset: f32,
+
+    /// Direction sunlight travels at sunrise, in degrees. By default it comes from the east,
+    /// with north at the top of the window
+    #[serde(default = "default_rise_angle")]
+    pub rise_angle: f32,
+
+    /// Direction sunlight travels at sunset, in degrees
+    #[serde(default = "default_set_angle")]
+    pub set_angle: f32,
+
+    /// Color temperature of the sun at sunrise and sunset
+    #[serde(default = "default_horizon_kelvin")]
+    pub horizon_kelvin: f32,
+
+    /// Color temperature of the sun at noon
+    #[serde(default = "default_noon_kelvin")]
+    pub noon_kelvin: f32,
+}
+
+fn default_day_length() -> f32 {
+    120.0
+}
+
+fn default_sunrise() -> f32 {
+    6.0
+}
+
+fn default_sunset() -> f32 {
+    18.0
+}
+
+fn default_rise_angle() -> f32 {
+    180.0
+}
+
+fn default_set_angle() -> f32 {
+    // Through 270, so the sun passes the south side at noon
+    360.0
+}
+
+fn default_horizon_kelvin() -> f32 {
+    2000.0
+}
+
+fn default_noon_kelvin() -> f32 {
+    5800.0
+}
+
+impl DayCycle {
+    /// The hour of the virtual day after some real time, on a 24 hour clock
+    pub fn hour_at(&self, time: f32) -> f32 {
+        if self.length <= 0.0 {
+            return self.start;
+        }
+        (self.start + time / self.length * 24.0).rem_euclid(24.0)
+    }
+
+    /// How far the sun is through the day at an hour, from 0 at sunrise to 1 at sunset, or
+    /// `None` at night
+    pub fn progress(&self, hour: f32) -> Option<f32> {
+        if self.sunset <= self.sunrise || hour < self.sunrise || hour > self.sunset {
+            return None;
+        }
+        Some((hour - self.sunrise) / (self.sunset - self.sunrise))
+    }
+
+    /// Point, color, and dim a light like the sun at an hour of the day
+    pub fn apply(&self, light: &mut Light, hour: f32) {
+        let progress = match self.progress(hour) {
+            Some(progress) => progress,
+            None => {
+                light.modulation = 0.0;
+                return;
+            }
+        };
+
+        let spacing = match light.shape {
+            LightShape::Directional { spacing, .. } => spacing,
+            _ => default_directional_spacing(),
+        };
+        light.shape = LightShape::Directional {
+            angle: self.rise_angle + (self.set_angle - self.rise_angle) * progress,
+            spacing,
+        };
+
+        // The sun is highest, brightest, and whitest at noon
+        let elevation = (progress * std::f32::consts::PI).sin();
+        light.color = kelvin_to_color(
+            self.horizon_kelvin + (self.noon_kelvin - self.horizon_kelvin) * elevation,
+        );
+        light.modulation = elevation;
+    }
+}
+
+/// Post-processing bloom controls, passed to the bloom shader as uniforms
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Bloom {
+    /// Brightness (0 to 1) above which pixels start to glow
+    #[serde(default = "default_bloom_threshold")]
+    pub threshold: f32,
+
+    /// Blur spread in pixels
+    #[serde(default = "default_bloom_radius")]
+    pub radius: f32,
+
+    /// How strongly the glow is added back over the image
+    #[serde(default = "default_bloom_intensity")]
+    pub intensity: f32,
+}
+
+fn default_bloom_threshold() -> f32 {
+    0.5
+}
+
+fn default_bloom_radius() -> f32 {
+    2.0
+}
+
+fn default_bloom_intensity() -> f32 {
+    1.0
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Self {
+            threshold: default_bloom_threshold(),
+            radius: default_bloom_radius(),
+            intensity: default_bloom_intensity(),
+        }
+    }
+}
+
+/// A value bound to a custom shader uniform
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum UniformValue {
+    Float(f32),
+
+    /// A `vec2`, `vec3`, or `vec4`
+    Vector(Vec<f32>),
+
+    /// An image file, relative to the world file
+    Texture {
+        texture: String,
+    },
+}
+
+/// An extra post-processing pass, applied after bloom
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShaderPass {
+    /// Path to the fragment shader, relative to the world file
+    pub path: String,
+
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub uniforms: BTreeMap<String, UniformValue>,
+
+    /// Directory of the world file that declared this pass
+    #[serde(skip)]
+    pub directory: PathBuf,
+}
+
+impl ShaderPass {
+    /// Resolve a path relative to the world file
+    pub fn resolve(&self, path: &str) -> PathBuf {
+        self.directory.join(path)
+    }
+}
+
+/// How a light's layer is combined with the layers beneath it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlendMode {
+    /// Covers the layers beneath, faded by opacity
+    Normal,
+    Additive,
+    Multiply,
+    Subtract,
+}
+
+impl Default for BlendMode {
+    fn default() -> Self {
+        BlendMode::Additive
+    }
+}
+
+/// Compositing controls for a light's render layer
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LayerSettings {
+    #[serde(default)]
+    pub blend: BlendMode,
+
+    #[serde(default = "default_opacity")]
+    pub opacity: f32,
+}
+
+fn default_opacity() -> f32 {
+    1.0
+}
+
+impl Default for LayerSettings {
+    fn default() -> Self {
+        Self {
+            blend: BlendMode::default(),
+            opacity: default_opacity(),
+        }
+    }
+}
+
+/// Random brightness changes driven by noise, for candles and fires
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Flicker {
+    /// How far the brightness strays from the light's intensity, as a fraction of it
+    #[serde(default = "default_flicker_amplitude")]
+    pub amplitude: f32,
+
+    /// How quickly the brightness changes, in noise cycles per second
+    #[serde(default = "default_flicker_speed")]
+    pub speed: f32,
+}
+
+fn default_flicker_amplitude() -> f32 {
+    0.3
+}
+
+fn default_flicker_speed() -> f32 {
+    4.0
+}
+
+impl Flicker {
+    /// The brightness multiplier at a point in time. Lights with different seeds flicker
+    /// independently
+    pub fn level_at(&self, time: f32, seed: f32) -> f32 {
+        (1.0 + self.amplitude * perlin(time * self.speed + seed)).max(0.0)
+    }
+}
+
+/// The inputs to a light's trace. When they change, the light needs tracing again
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TraceKey {
+    pub position: Vector2,
+
+    /// The light's color after intensity, modulation, and flicker
+    pub color: Color,
+
+    pub radius: f32,
+    pub shape: LightShape,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Light {
+    pub fixed: bool,
+
+    /// Disabled lights are traced, but hidden when compositing
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+
+    /// Stable handle for scripts and remote control
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// Labels for picking several lights at once
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+
+    /// Name of a group of lights that are switched on and off together, like a circuit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+
+    #[serde(default)]
+    pub layer: LayerSettings,
+
+    #[serde(rename = "color", default)]
+    raw_color: ColorSpec,
+
+    /// Optional color temperature. Takes priority over `color` when set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kelvin: Option<f32>,
+
+    #[serde(skip)]
+    pub color: Color,
+
+    pub position: Vector2,
+
+    #[serde(default)]
+    pub shape: LightShape,
+
+    /// Radius of the emitter's disc in pixels. Rays start at its edge, and the disc itself glows
+    #[serde(default)]
+    pub radius: f32,
+
+    /// Brightness multiplier applied to the light's color
+    #[serde(default = "default_intensity")]
+    pub intensity: f32,
+
+    /// Runtime brightness multiplier driven by effects such as audio input
+    #[serde(skip, default = "default_intensity")]
+    pub modulation: f32,
+
+    /// Random brightness changes, like a candle or fire
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flicker: Option<Flicker>,
+
+    /// Runtime brightness multiplier from the light's flicker
+    #[serde(skip, default = "default_intensity")]
+    pub flicker_level: f32,
+
+    /// Time in seconds for the light's hue to rotate through the full color wheel
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hue_cycle: Option<f32>,
+
+    /// Cycle the light's color through a gradient. Takes priority over `hue_cycle`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gradient: Option<Gradient>,
+
+    /// Animation path, in order of time. When set, the light ignores the mouse
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keyframes: Vec<Keyframe>,
+
+    /// A registered custom behavior, run after the light's own animation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub behavior: Option<Plugin<dyn LightBehavior>>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_intensity() -> f32 {
+    1.0
+}
+
+impl Light {
+    /// Replace the light's configured color
+    pub fn set_color(&mut self, color: ColorSpec) {
+        self.raw_color = color;
+        self.kelvin = None;
+        self.load_colors();
+    }
+
+    /// The configured color of the light, before any animation
+    fn base_color(&self) -> Color {
+        match self.kelvin {
+            Some(kelvin) => kelvin_to_color(kelvin),
+            None => self.raw_color.into(),
+        }
+    }
+
+    /// Update the light's color for a point in time, if its color is animated
+    pub fn update_color(&mut self, time: f32) {
+        if let Some(gradient) = &self.gradient {
+            if gradient.colors.is_empty() || gradient.period <= 0.0 {
+                return;
+            }
+
+            // Find the pair of colors we are between
+            let position = (time / gradient.period).rem_euclid(1.0) * gradient.colors.len() as f32;
+            let index = position as usize % gradient.colors.len();
+            let next = (index + 1) % gradient.colors.len();
+            self.color = lerp_color(
+                gradient.colors[index].into(),
+                gradient.colors[next].into(),
+                position.fract(),
+            );
+        } else if let Some(period) = self.hue_cycle {
+            if period > 0.0 {
+                self.color = rotate_hue(self.base_color(), 360.0 * time / period);
+            }
+        }
+    }
+
+    /// The color of the light's rays after intensity and modulation are applied
+    pub fn emitted_color(&self) -> Color {
+        let scale = self.intensity * self.modulation * self.flicker_level;
+        Color {
+            r: (self.color.r as f32 * scale).clamp(u8::MIN as f32, u8::MAX as f32) as u8,
+            g: (self.color.g as f32 * scale).clamp(u8::MIN as f32, u8::MAX as f32) as u8,
+            b: (self.color.b as f32 * scale).clamp(u8::MIN as f32, u8::MAX as f32) as u8,
+            a: 255,
+        }
+    }
+
+    /// Everything about the light that its traced rays depend on
+    pub fn trace_key(&self) -> TraceKey {
+        TraceKey {
+            position: self.position,
+            color: self.emitted_color(),
+            radius: self.radius,
+            shape: self.shape,
+        }
+    }
+
+    /// Get every point that rays are emitted from
+    pub fn emitters(&self, window_vec: &Vector2) -> Vec<Emitter> {
+        let center = self.position * *window_vec;
+        match self.shape {
+            LightShape::Point => vec![Emitter::omnidirectional(center)],
+            LightShape::Area { radius, samples } => {
+                let samples = samples.max(1);
+                (0..samples)
+                    .map(|i| {
+                        let angle = (i as f32 / samples as f32) * std::f32::consts::PI * 2.0;
+                        Emitter::omnidirectional(
+                            center
+                                + Vector2 {
+                                    x: angle.cos() * radius,
+                                    y: angle.sin() * radius,
+                                },
+                        )
+                    })
+                    .collect()
+            }
+            LightShape::Line {
+                length,
+                angle,
+                arc,
+                samples,
+            } => {
+                let samples = samples.max(1);
+                let direction = Vector2 {
+                    x: angle.to_radians().cos(),
+                    y: angle.to_radians().sin(),
+                };
+                let arc = arc.clamp(0.0, 180.0);
+
+                (0..samples)
+                    .flat_map(|i| {
+                        // Spread samples evenly along the segment
+                        let t = if samples > 1 {
+                            i as f32 / (samples - 1) as f32 - 0.5
+                        } else {
+                            0.0
+                        };
+                        let origin = center + direction * (t * length);
+
+                        // Emit a fan centered on each side's normal
+                        vec![
+                            Emitter {
+                                origin,
+                                start_angle: angle + 90.0 - arc / 2.0,
+                                sweep: arc,
+                            },
+                            Emitter {
+                                origin,
+                                start_angle: angle - 90.0 - arc / 2.0,
+                                sweep: arc,
+                            },
+                        ]
+                    })
+                    .collect()
+            }
+            LightShape::Directional { angle, spacing } => {
+                let direction = Vector2 {
+                    x: angle.to_radians().cos(),
+                    y: angle.to_radians().sin(),
+                };
+                let across = Vector2::new(-direction.y, direction.x);
+
+                // Lay rays out across the window's diagonal, each starting where it enters
+                let center = *window_vec / 2.0;
+                let reach = window_vec.length() / 2.0;
+                let count = (reach * 2.0 / spacing.max(0.1)).ceil() as usize + 1;
+                (0..count)
+                    .filter_map(|i| {
+                        let offset = -reach + i as f32 * spacing.max(0.1);
+                        let line = center + across * offset;
+                        let entry = window_entry(line, direction, window_vec)?;
+                        Some(Emitter {
+                            origin: line + direction * entry,
+                            start_angle: angle,
+                            sweep: 0.0,
+                        })
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// The alpha of each ray, so that every emitter point contributes an equal share of the
+    /// light. Directional rays never overlap, so each carries the light's full strength
+    pub fn ray_alpha(&self, emitters: usize) -> u8 {
+        match self.shape {
+            LightShape::Directional { .. } => u8::MAX,
+            _ => (u8::MAX as usize / emitters.max(1)).max(1) as u8,
+        }
+    }
+
+    /// Length of the light's animation in seconds
+    pub fn animation_length(&self) -> f32 {
+        self.keyframes.last().map(|k| k.time).unwrap_or(0.0)
+    }
+
+    /// Get the light's position at a point in its animation, if it is animated
+    pub fn position_at(&self, time: f32) -> Option<Vector2> {
+        let first = self.keyframes.first()?;
+        if time <= first.time {
+            return Some(first.position);
+        }
+
+        // Linearly interpolate between the surrounding keyframes
+        for pair in self.keyframes.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            if time <= b.time {
+                let t = if b.time > a.time {
+                    (time - a.time) / (b.time - a.time)
+                } else {
+                    1.0
+                };
+                return Some(a.position + (b.position - a.position) * t);
+            }
+        }
+
+        self.keyframes.last().map(|k| k.position)
+    }
+}
+
+/// How far along a line through a point the line enters the window, if it crosses it at all.
+///
+/// The entry is nudged a little inside, so the first sample along the line is in the window.
+fn window_entry(point: Vector2, direction: Vector2, window_vec: &Vector2) -> Option<f32> {
+    let (mut near, mut far) = (f32::NEG_INFINITY, f32::INFINITY);
+    for (start, step, size) in [
+        (point.x, direction.x, window_vec.x),
+        (point.y, direction.y, window_vec.y),
+    ]
+    .iter()
+    {
+        if step.abs() < f32::EPSILON {
+            // Parallel to this pair of edges, so it must already be between them
+            if *start < 0.0 || *start > *size {
+                return None;
+            }
+            continue;
+        }
+        let (a, b) = ((0.0 - start) / step, (size - start) / step);
+        near = near.max(a.min(b));
+        far = far.min(a.max(b));
+    }
+
+    if near + 1.0 < far {
+        Some(near + 0.5)
+    } else {
+        None
+    }
+}
+
+impl ColorLoad for Light {
+    fn load_colors(&mut self) {
+        self.color = self.base_color();
+        self.update_color(0.0);
+    }
+}
+
+/// A reference to another world file whose walls are merged into this one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Include {
+    Path(String),
+    Transformed {
+        path: String,
+
+        #[serde(default)]
+        translate: Vector2,
+
+        /// Rotation about the included file's origin, in degrees
+        #[serde(default)]
+        rotate: f32,
+    },
+}
+
+impl Include {
+    fn path(&self) -> &str {
+        match self {
+            Include::Path(path) => path,
+            Include::Transformed { path, .. } => path,
+        }
+    }
+
+    /// Map a point from the included file's space into the including file's space
+    fn transform(&self, point: Vector2) -> Vector2 {
+        match self {
+            Include::Path(_) => point,
+            Include::Transformed {
+                translate, rotate, ..
+            } => {
+                let (sin, cos) = rotate.to_radians().sin_cos();
+                Vector2 {
+                    x: point.x * cos - point.y * sin + translate.x,
+                    y: point.x * sin + point.y * cos + translate.y,
+                }
+            }
+        }
+    }
+}
+
+/// The parts of a world file that are pulled in by an include
+#[derive(Debug, Clone, Deserialize)]
+struct WorldFragment {
+    #[serde(default)]
+    walls: Vec<Wall>,
+
+    #[serde(default)]
+    include: Vec<Include>,
+}
+
+/// A file of walls covering part of a very large world, loaded only while it is near the view
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Chunk {
+    /// Path to the chunk's file, relative to the world file. It holds walls like an include
+    pub path: String,
+
+    /// Top left corner of the area the chunk's walls lie within, in pixels
+    pub position: Vector2,
+
+    /// Size of the area the chunk's walls lie within, in pixels
+    pub size: Vector2,
+
+    /// Directory of the world file that declared this chunk
+    #[serde(skip)]
+    pub directory: PathBuf,
+
+    #[serde(skip)]
+    pub loaded: bool,
+}
+
+impl Chunk {
+    /// Whether the chunk's area overlaps a rectangle
+    pub fn overlaps(&self, position: Vector2, size: Vector2) -> bool {
+        self.position.x < position.x + size.x
+            && position.x < self.position.x + self.size.x
+            && self.position.y < position.y + size.y
+            && position.y < self.position.y + self.size.y
+    }
+
+    /// Load the chunk's walls, and any they include
+    fn load_walls(&self) -> Result<Vec<Wall>, Error> {
+        let path = self.directory.join(&self.path);
+        let fragment: WorldFragment =
+            serde_json::from_value(read_world_value(&path)?).map_err(|e| parse_error(&path, e))?;
+        let mut walls = fragment.walls;
+        walls.extend(resolve_includes(&path, &fragment.include, 1)?);
+        Ok(walls)
+    }
+}
+
+/// An area of the world that fog occupies
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "shape", rename_all = "snake_case")]
+pub enum Region {
+    Rect { position: Vector2, size: Vector2 },
+    Circle { center: Vector2, radius: f32 },
+}
+
+impl Region {
+    pub fn contains(&self, point: Vector2) -> bool {
+        match self {
+            Region::Rect { position, size } => {
+                point.x >= position.x
+                    && point.x <= position.x + size.x
+                    && point.y >= position.y
+                    && point.y <= position.y + size.y
+            }
+            Region::Circle { center, radius } => {
+                let offset = point - *center;
+                offset.x * offset.x + offset.y * offset.y <= radius * radius
+            }
+        }
+    }
+}
+
+/// A participating medium that absorbs light as rays pass through it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fog {
+    #[serde(flatten)]
+    pub region: Region,
+
+    /// Fraction of light absorbed per pixel travelled
+    pub density: f32,
+
+    /// Like walls, the color that the fog removes from light
+    #[serde(rename = "color")]
+    raw_color: ColorSpec,
+
+    #[serde(skip)]
+    pub color: Color,
+}
+
+impl ColorLoad for Fog {
+    fn load_colors(&mut self) {
+        self.color = self.raw_color.into();
+    }
+}
+
+/// A grayscale image used as a spatially varying absorption field
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DensityMap {
+    /// Path to the image, relative to the world file
+    pub path: String,
+
+    /// World position of the image's top left corner
+    #[serde(default)]
+    pub position: Vector2,
+
+    /// World units per image pixel
+    #[serde(default = "default_density_scale")]
+    pub scale: f32,
+
+    /// Fraction of light absorbed per pixel travelled where the image is white
+    pub density: f32,
+
+    /// The color that the field removes from light
+    #[serde(rename = "color", default = "default_density_color")]
+    raw_color: ColorSpec,
+
+    #[serde(skip)]
+    pub color: Color,
+
+    #[serde(skip)]
+    pub image: Option<Arc<GrayImage>>,
+}
+
+fn default_density_scale() -> f32 {
+    1.0
+}
+
+fn default_density_color() -> ColorSpec {
+    ColorSpec(255, 255, 255, 255)
+}
+
+impl ColorLoad for DensityMap {
+    fn load_colors(&mut self) {
+        self.color = self.raw_color.into();
+    }
+}
+
+impl DensityMap {
+    /// Load the image, relative to the world file it was declared in
+    fn load_image(&mut self, world_path: &Path) -> Result<(), Error> {
+        let directory = world_path.parent().unwrap_or_else(|| Path::new(""));
+        let path = directory.join(&self.path);
+        let image = image::open(&path).map_err(|source| Error::Image { path, source })?;
+        self.image = Some(Arc::new(image.to_luma8()));
+        Ok(())
+    }
+
+    /// Sample the density at a world position
+    pub fn density_at(&self, point: Vector2) -> f32 {
+        let image = match &self.image {
+            Some(image) => image,
+            None => return 0.0,
+        };
+
+        let x = (point.x - self.position.x) / self.scale;
+        let y = (point.y - self.position.y) / self.scale;
+        if x < 0.0 || y < 0.0 || x >= image.width() as f32 || y >= image.height() as f32 {
+            return 0.0;
+        }
+
+        self.density * (image.get_pixel(x as u32, y as u32)[0] as f32 / 255.0)
+    }
+}
+
+/// Maximum include nesting, to catch include cycles
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct World {
+    #[serde(default = "current_version")]
+    pub version: u64,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<Include>,
+
+    pub walls: Vec<Wall>,
+
+    #[serde(default)]
+    pub lights: Vec<Light>,
+
+    #[serde(default)]
+    pub bloom: Bloom,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub passes: Vec<ShaderPass>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fog: Vec<Fog>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub density_map: Option<DensityMap>,
+
+    /// Curved reflectors that bounce rays
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<ParabolicMirror>,
+
+    /// Glass lenses that refract rays
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lenses: Vec<Lens>,
+
+    /// Points that measure the light reaching them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sensors: Vec<Sensor>,
+
+    /// Walls split out into files by area, for worlds too large to load at once
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunks: Vec<Chunk>,
+
+    /// Brightness (0 to 1) below which a fading ray stops being traced, where dark samples
+    /// would be invisible anyway
+    #[serde(default = "default_ray_cutoff")]
+    pub ray_cutoff: f32,
+
+    /// Sweep one of the lights across the world like the sun over a virtual day
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub day: Option<DayCycle>,
+
+    /// The walls rasterized for fast lookups while tracing, built by `rasterize_walls`
+    #[serde(skip)]
+    pub occupancy: Option<Arc<Occupancy>>,
+
+    /// Loaded chunks' walls rasterized on their own, with the index of each chunk's first wall,
+    /// so loading a chunk doesn't re-rasterize the rest. When these are set, `occupancy` only
+    /// holds the walls before the first chunk's
+    #[serde(skip)]
+    pub chunk_occupancy: Vec<(usize, Arc<Occupancy>)>,
+}
+
+fn default_ray_cutoff() -> f32 {
+    // Two steps of an 8-bit channel
+    2.0 / 255.0
+}
+
+impl ColorLoad for World {
+    fn load_colors(&mut self) {
+        for wall in self.walls.iter_mut() {
+            wall.load_colors();
+            wall.load_line();
+        }
+        for fog in self.fog.iter_mut() {
+            fog.load_colors();
+        }
+        if let Some(map) = &mut self.density_map {
+            map.load_colors();
+        }
+        for light in self.lights.iter_mut() {
+            light.load_colors();
+        }
+    }
+}
+
+fn current_version() -> u64 {
+    migrate::CURRENT_VERSION
+}
+
+/// Whether a path names a world in the compact binary format, which holds the same data as a
+/// JSON world encoded as MessagePack, and loads much faster for very large worlds
+pub fn is_binary(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map_or(false, |extension| extension.eq_ignore_ascii_case("bin"))
+}
+
+/// Where to save changes to a world loaded from a path. JSON5 worlds are written by hand, and
+/// saving would drop their comments and formatting, so they are saved to a `.json` file beside
+/// them instead
+pub fn save_path(source: &Path) -> PathBuf {
+    let json5 = source
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map_or(false, |extension| extension.eq_ignore_ascii_case("json5"));
+    if json5 {
+        warn!(
+            "Saving {} as JSON beside it, to keep its comments",
+            source.display()
+        );
+        source.with_extension("json")
+    } else {
+        source.to_path_buf()
+    }
+}
+
+/// Read a world file and bring it up to the current format version
+fn read_world_value(path: &Path) -> Result<serde_json::Value, Error> {
+    let io_error = |source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    let raw: serde_json::Value = if is_binary(path) {
+        rmp_serde::from_slice(&read(path).map_err(io_error)?).map_err(|e| parse_error(path, e))?
+    } else {
+        // Text worlds are parsed as JSON5 so they may contain comments and trailing commas
+        json5::from_str(&read_to_string(path).map_err(io_error)?)
+            .map_err(|e| parse_error(path, e))?
+    };
+    migrate::migrate(raw, &path.to_string_lossy())
+}
+
+fn parse_error(path: &Path, error: impl std::fmt::Display) -> Error {
+    Error::Parse {
+        path: path.to_path_buf(),
+        message: error.to_string(),
+    }
+}
+
+/// Load every wall referenced by a list of includes, relative to the including file
+fn resolve_includes(base: &Path, includes: &[Include], depth: usize) -> Result<Vec<Wall>, Error> {
+    if depth > MAX_INCLUDE_DEPTH {
+        return Err(Error::IncludeDepth {
+            path: base.to_path_buf(),
+        });
+    }
+
+    let directory = base.parent().unwrap_or_else(|| Path::new(""));
+    let mut walls = Vec::new();
+    for include in includes {
+        let path = directory.join(include.path());
+        debug!("Including {} from {}", path.display(), base.display());
+        let fragment: WorldFragment =
+            serde_json::from_value(read_world_value(&path)?).map_err(|e| parse_error(&path, e))?;
+
+        // Nested includes are expressed in the fragment's own space
+        let mut fragment_walls = fragment.walls;
+        fragment_walls.extend(resolve_includes(&path, &fragment.include, depth + 1)?);
+
+        for mut wall in fragment_walls {
+            wall.start = include.transform(wall.start);
+            wall.end = include.transform(wall.end);
+            walls.push(wall);
+        }
+    }
+
+    Ok(walls)
+}
+
+impl World {
+    /// Number of walls before those loaded from chunks. Chunks' walls always come after the
+    /// rest, so loading and unloading chunks never moves other walls
+    pub fn unchunked_walls(&self) -> usize {
+        self.walls
+            .iter()
+            .position(|wall| wall.chunk.is_some())
+            .unwrap_or_else(|| self.walls.len())
+    }
+
+    /// Add walls to the world ahead of any loaded from chunks, returning their indices
+    pub fn add_walls(&mut self, walls: impl IntoIterator<Item = Wall>) -> Range<usize> {
+        let first = self.unchunked_walls();
+        let count = self.walls.len();
+        self.walls.splice(first..first, walls);
+        self.occupancy = None;
+        self.chunk_occupancy.clear();
+        first..first + self.walls.len() - count
+    }
+
+    /// Indices of a loaded chunk's walls, which are kept together
+    pub fn chunk_walls(&self, chunk: usize) -> Range<usize> {
+        let first = self
+            .walls
+            .iter()
+            .position(|wall| wall.chunk == Some(chunk))
+            .unwrap_or_else(|| self.walls.len());
+        let count = self.walls[first..]
+            .iter()
+            .take_while(|wall| wall.chunk == Some(chunk))
+            .count();
+        first..first + count
+    }
+
+    /// Index of the wall closest to a point, if any is within `max_distance` pixels
+    pub fn nearest_wall(&self, point: Vector2, max_distance: f32) -> Option<usize> {
+        self.walls
+            .iter()
+            .enumerate()
+            .map(|(index, wall)| (index, wall.distance_to(point)))
+            .filter(|(_, distance)| *distance <= max_distance)
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+            .map(|(index, _)| index)
+    }
+
+    /// Names of the light groups, in the order they first appear
+    pub fn groups(&self) -> Vec<&str> {
+        let mut groups = Vec::new();
+        for group in self
+            .lights
+            .iter()
+            .filter_map(|light| light.group.as_deref())
+        {
+            if !groups.contains(&group) {
+                groups.push(group);
+            }
+        }
+        groups
+    }
+
+    /// Switch a group of lights off if any of them are on, or back on otherwise.
+    ///
+    /// Returns whether the group is now on.
+    pub fn toggle_group(&mut self, group: &str) -> bool {
+        let members = |light: &&mut Light| light.group.as_deref() == Some(group);
+        let enabled = !self
+            .lights
+            .iter_mut()
+            .filter(members)
+            .any(|light| light.enabled);
+        for light in self.lights.iter_mut().filter(members) {
+            light.enabled = enabled;
+        }
+        enabled
+    }
+
+    /// Indices of the walls a selector picks
+    pub fn find_walls(&self, selector: &Selector) -> Vec<usize> {
+        (0..self.walls.len())
+            .filter(|&index| selector.matches(&self.walls[index]))
+            .collect()
+    }
+
+    /// Indices of the lights a selector picks
+    pub fn find_lights(&self, selector: &Selector) -> Vec<usize> {
+        (0..self.lights.len())
+            .filter(|&index| selector.matches(&self.lights[index]))
+            .collect()
+    }
+
+    /// Replace fields of several walls, written as they would be in a world file. Every wall is
+    /// checked before any changes, so if one can't take the fields none of them do. Errors name
+    /// walls by the index they were given, counting from zero
+    pub fn update_walls(
+        &mut self,
+        indices: &[usize],
+        fields: &Map<String, Value>,
+    ) -> Result<(), String> {
+        let mut updates = Vec::with_capacity(indices.len());
+        for &index in indices.iter() {
+            let wall = self
+                .walls
+                .get(index)
+                .ok_or_else(|| format!("There is no wall {}", index))?;
+            if let Some(chunk) = wall.chunk {
+                return Err(format!(
+                    "Wall {} is from chunk {}, which can't be changed",
+                    index, self.chunks[chunk].path
+                ));
+            }
+            let mut updated: Wall = with_fields(wall, fields)
+                .map_err(|e| format!("Can't change wall {}: {}", index, e))?;
+            updated.load_colors();
+            updated.load_line();
+            updated.included = wall.included;
+            updated.chunk = wall.chunk;
+            updates.push((index, updated));
+        }
+        if updates.is_empty() {
+            return Ok(());
+        }
+        for (index, updated) in updates {
+            self.walls[index] = updated;
+        }
+
+        // The walls may have moved
+        self.occupancy = None;
+        self.chunk_occupancy.clear();
+        Ok(())
+    }
+
+    /// Replace fields of several lights, written as they would be in a world file. Runtime
+    /// effects such as audio modulation carry over, and like [`World::update_walls`] nothing
+    /// changes unless every light can take the fields
+    pub fn update_lights(
+        &mut self,
+        indices: &[usize],
+        fields: &Map<String, Value>,
+    ) -> Result<(), String> {
+        let mut updates = Vec::with_capacity(indices.len());
+        for &index in indices.iter() {
+            let light = self
+                .lights
+                .get(index)
+                .ok_or_else(|| format!("There is no light {}", index))?;
+            let mut updated: Light = with_fields(light, fields)
+                .map_err(|e| format!("Can't change light {}: {}", index, e))?;
+            updated.load_colors();
+            updated.modulation = light.modulation;
+            updated.flicker_level = light.flicker_level;
+            updates.push((index, updated));
+        }
+        if updates.is_empty() {
+            return Ok(());
+        }
+        for (index, updated) in updates {
+            self.lights[index] = updated;
+        }
+        Ok(())
+    }
+
+    /// Load the chunks that overlap an area and unload the rest, so only walls near the view
+    /// take up memory.
+    ///
+    /// Returns true if any walls were loaded or unloaded.
+    pub fn load_chunks_near(&mut self, position: Vector2, size: Vector2) -> Result<bool, Error> {
+        self.update_chunks(|chunk| chunk.overlaps(position, size))
+    }
+
+    /// Load every chunk, for when the whole world is needed at once
+    pub fn load_all_chunks(&mut self) -> Result<(), Error> {
+        self.update_chunks(|_| true).map(|_| ())
+    }
+
+    /// The top left corner and size of the area covering both a window of size `window_vec` and
+    /// every chunk, in pixels
+    pub fn bounds(&self, window_vec: Vector2) -> (Vector2, Vector2) {
+        let mut low = Vector2::zero();
+        let mut high = window_vec;
+        for chunk in self.chunks.iter() {
+            let end = chunk.position + chunk.size;
+            low = Vector2::new(low.x.min(chunk.position.x), low.y.min(chunk.position.y));
+            high = Vector2::new(high.x.max(end.x), high.y.max(end.y));
+        }
+        (low, high - low)
+    }
+
+    fn update_chunks(&mut self, wanted: impl Fn(&Chunk) -> bool) -> Result<bool, Error> {
+        let mut changed = false;
+        for index in 0..self.chunks.len() {
+            let chunk = &self.chunks[index];
+            match (chunk.loaded, wanted(chunk)) {
+                (false, true) => {
+                    debug!("Loading chunk {}", chunk.path);
+                    let mut walls = chunk.load_walls()?;
+                    for wall in walls.iter_mut() {
+                        wall.chunk = Some(index);
+                        wall.load_colors();
+                        wall.load_line();
+                    }
+                    self.walls.extend(walls);
+                    self.chunks[index].loaded = true;
+                    changed = true;
+                }
+                (true, false) => {
+                    debug!("Unloading chunk {}", chunk.path);
+                    self.walls.retain(|wall| wall.chunk != Some(index));
+                    self.chunks[index].loaded = false;
+                    changed = true;
+                }
+                _ => {}
+            }
+        }
+        if changed {
+            self.occupancy = None;
+            self.chunk_occupancy.clear();
+        }
+        Ok(changed)
+    }
+
+    /// Length of the longest light animation in seconds
+    pub fn animation_length(&self) -> f32 {
+        self.lights
+            .iter()
+            .map(|light| light.animation_length())
+            .fold(0.0, f32::max)
+    }
+
+    /// Find where a ray step from `start` to `end` first hits a mirror or lens.
+    ///
+    /// Returns the hit point and the ray's new direction.
+    pub fn redirect(
+        &self,
+        start: Vector2,
+        end: Vector2,
+        direction: Vector2,
+    ) -> Option<(Vector2, Vector2)> {
+        let mirrors = self
+            .mirrors
+            .iter()
+            .filter_map(|mirror| mirror.redirect(start, end, direction));
+        let lenses = self
+            .lenses
+            .iter()
+            .filter_map(|lens| lens.redirect(start, end, direction));
+        mirrors
+            .chain(lenses)
+            .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap())
+            .map(|(t, direction)| (start + (end - start) * t, direction))
+    }
+
+    /// Rasterize the walls into an occupancy map covering a window, so tracing finds the wall
+    /// under each sample with one lookup. The map is only valid until the walls next change
+    pub fn rasterize_walls(&mut self, window_vec: Vector2) {
+        self.occupancy = Some(Arc::new(Occupancy::new(&self.walls, window_vec)));
+        self.chunk_occupancy.clear();
+    }
+
+    /// A copy of the world with every size and position in pixels multiplied by `factor`, for
+    /// tracing at a higher resolution. Light positions are normalized, so they stay put
+    pub fn scaled(&self, factor: f32) -> World {
+        self.framed(Vector2::zero(), Vector2::new(1.0, 1.0), factor)
+    }
+
+    /// A copy of the world as seen from a window of size `window_vec` whose top left corner is at
+    /// `origin`, in pixels, and scaled by `factor` like [`World::scaled`]. Tracing is limited to
+    /// the window, so this moves the traced part of a world larger than it
+    pub fn framed(&self, origin: Vector2, window_vec: Vector2, factor: f32) -> World {
+        let to_frame = |point: Vector2| (point - origin) * factor;
+        let mut world = self.clone();
+        world.occupancy = None;
+        world.chunk_occupancy.clear();
+        for wall in world.walls.iter_mut() {
+            wall.start = to_frame(wall.start);
+            wall.end = to_frame(wall.end);
+            wall.load_line();
+        }
+        for light in world.lights.iter_mut() {
+            light.position = light.position - origin / window_vec;
+            light.radius *= factor;
+            match &mut light.shape {
+                LightShape::Point => {}
+                LightShape::Area { radius, .. } => *radius *= factor,
+                LightShape::Line { length, .. } => *length *= factor,
+                // Rays stay a render pixel apart, so they still cover every pixel
+                LightShape::Directional { .. } => {}
+            }
+        }
+
+        // Absorption is per pixel travelled, so it thins out as the pixels shrink
+        for fog in world.fog.iter_mut() {
+            fog.density /= factor;
+            match &mut fog.region {
+                Region::Rect { position, size } => {
+                    *position = to_frame(*position);
+                    *size = *size * factor;
+                }
+                Region::Circle { center, radius } => {
+                    *center = to_frame(*center);
+                    *radius *= factor;
+                }
+            }
+        }
+        if let Some(map) = &mut world.density_map {
+            map.position = to_frame(map.position);
+            map.scale *= factor;
+            map.density /= factor;
+        }
+
+        for mirror in world.mirrors.iter_mut() {
+            mirror.focus = to_frame(mirror.focus);
+            mirror.focal_length *= factor;
+            mirror.width *= factor;
+        }
+        for lens in world.lenses.iter_mut() {
+            lens.center = to_frame(lens.center);
+            lens.aperture *= factor;
+            lens.focal_length = lens.focal_length.map(|length| length * factor);
+            lens.radii = lens
+                .radii
+                .map(|radii| [radii[0] * factor, radii[1] * factor]);
+        }
+        for sensor in world.sensors.iter_mut() {
+            sensor.position = to_frame(sensor.position);
+            sensor.end = sensor.end.map(to_frame);
+            sensor.radius *= factor;
+        }
+        world
+    }
+
+    /// Move every animated light to its position at a point in time, and update animated colors
+    pub fn animate(&mut self, time: f32) {
+        for (index, light) in self.lights.iter_mut().enumerate() {
+            light.update_color(time);
+            if let Some(flicker) = light.flicker {
+                // Far apart in the noise, so lights don't flicker in step
+                light.flicker_level = flicker.level_at(time, index as f32 * 37.5);
+            }
+            let length = light.animation_length();
+            let keyframe_time = if length > 0.0 { time % length } else { 0.0 };
+            if let Some(position) = light.position_at(keyframe_time) {
+                light.position = position;
+            }
+            if let Some(plugin) = light.behavior.clone() {
+                plugin.behavior.animate(light, time);
+            }
+        }
+
+        if let Some(day) = self.day {
+            self.set_hour(day.hour_at(time));
+        }
+    }
+
+    /// Move the sun to an hour of its virtual day, if the world has a day cycle
+    pub fn set_hour(&mut self, hour: f32) {
+        if let Some(day) = self.day {
+            if let Some(light) = self.lights.get_mut(day.light) {
+                day.apply(light, hour);
+            }
+        }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        info!("Loading world {}", path.display());
+        let mut world: World =
+            serde_json::from_value(read_world_value(path)?).map_err(|e| parse_error(path, e))?;
+
+        // Merge in any included walls
+        let mut included = resolve_includes(path, &world.include, 0)?;
+        let included_count = included.len();
+        for wall in included.iter_mut() {
+            wall.included = true;
+        }
+        world.walls.extend(included);
+
+        // Load the density field
+        if let Some(map) = &mut world.density_map {
+            debug!("Loading density map {}", map.path);
+            map.load_image(path)?;
+        }
+
+        // Shader passes are loaded by the renderer, relative to this file
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        for pass in world.passes.iter_mut() {
+            pass.directory = directory.to_path_buf();
+        }
+
+        // Chunks are loaded later, as they come into view
+        for chunk in world.chunks.iter_mut() {
+            chunk.directory = directory.to_path_buf();
+        }
+
+        world.load_colors();
+        info!(
+            "Loaded {} walls ({} included), {} lights, and {} shader passes",
+            world.walls.len(),
+            included_count,
+            world.lights.len(),
+            world.passes.len()
+        );
+        Ok(world)
+    }
+
+    /// Write the world out as pretty-printed JSON, or in the binary format for `.bin` paths.
+    ///
+    /// Included and chunked walls stay in their own files, so only references to them are
+    /// written.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        let io_error = |source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        };
+
+        let mut world = self.clone();
+        world
+            .walls
+            .retain(|wall| !wall.included && wall.chunk.is_none());
+        let bytes = if is_binary(path) {
+            rmp_serde::to_vec_named(&world)
+                .map_err(|e| io_error(io::Error::new(io::ErrorKind::InvalidData, e)))?
+        } else {
+            serde_json::to_vec_pretty(&world).map_err(|e| io_error(e.into()))?
+        };
+        write(path, bytes).map_err(io_error)?;
+
+        info!("Saved world {}", path.display());
+        Ok(())
+    }
+}